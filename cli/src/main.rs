@@ -17,6 +17,7 @@ async fn main() {
             ServerCommands::Init(args) => server::init(args).await,
             ServerCommands::Start(args) => server::start(args).await,
             ServerCommands::Info(args) => server::info(args).await,
+            ServerCommands::Cert(args) => server::cert(args).await,
         },
         // Commands::Init(args) => cmd::client::init(args).await,
         // Commands::Status(args) => cmd::client::status(args).await,
@@ -96,6 +97,8 @@ enum ServerCommands {
     Start(server::StartArgs),
     /// Server info
     Info(server::InfoArgs),
+    /// Issues a client certificate for a user
+    Cert(server::CertArgs),
 }
 
 // /// Authentication subcommands
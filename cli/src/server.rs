@@ -1,12 +1,13 @@
 //! Server commands
 
-use std::{path::PathBuf, str::FromStr};
+use std::{fs, path::PathBuf, str::FromStr};
 
 use anyhow::anyhow;
 use clap::Parser;
 use colored::Colorize;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input};
-use server::{Config, Server};
+use server::{generate_certificates, write_private, Config, Server};
+use tracing_subscriber::EnvFilter;
 
 // ------------------------------------------------------------------
 // init
@@ -41,12 +42,40 @@ pub async fn init(_args: InitArgs) -> anyhow::Result<()> {
     let mut config = Config::default();
 
     // Ask for server port
-    let input_port: u16 = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Server port")
-        .default(6666)
+    config.port = if Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Listen on a TCP port?")
+        .default(true)
         .report(true)
-        .interact()?;
-    config.port = input_port;
+        .interact()?
+    {
+        let input_port: u16 = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Server port")
+            .default(6666)
+            .report(true)
+            .interact()?;
+        Some(input_port)
+    } else {
+        None
+    };
+
+    // Ask for the Unix socket path
+    config.socket = if Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Listen on a Unix socket?")
+        .default(config.port.is_none())
+        .report(true)
+        .interact()?
+    {
+        let input_socket: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Socket path")
+            .default(config.socket_file()?.to_str().unwrap().to_string())
+            .interact()?;
+        Some(PathBuf::from_str(&input_socket)?)
+    } else {
+        None
+    };
+    if config.port.is_none() && config.socket.is_none() {
+        return Err(anyhow!("The server must listen on a port or a socket"));
+    }
 
     // Ask for server database path
     let input_db: String = Input::with_theme(&ColorfulTheme::default())
@@ -55,6 +84,41 @@ pub async fn init(_args: InitArgs) -> anyhow::Result<()> {
         .interact()?;
     config.database = PathBuf::from_str(&input_db)?;
 
+    // Ask for TLS certificates
+    if config.port.is_some()
+        && Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Generate a self-signed TLS certificate?")
+            .default(true)
+            .report(true)
+            .interact()?
+    {
+        let input_hosts: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Server hostnames (comma separated)")
+            .default("localhost".to_string())
+            .interact()?;
+        let hosts = input_hosts
+            .split(',')
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect::<Vec<_>>();
+
+        let files = generate_certificates(&config.config_dir()?, &hosts)?;
+        eprintln!(
+            "{} {}: {}",
+            "✔".bright_green(),
+            "CA certificate".bold(),
+            files.ca_cert.display()
+        );
+        eprintln!(
+            "{} Pin this CA certificate on the clients",
+            "i".bright_cyan()
+        );
+        config.cert = Some(files.cert);
+        config.key = Some(files.key);
+        config.ca_cert = Some(files.ca_cert);
+        config.ca_key = Some(files.ca_key);
+    }
+
     // Write the config to disk
     config.save()?;
     eprintln!(
@@ -87,15 +151,82 @@ pub async fn start(_args: StartArgs) -> anyhow::Result<()> {
     // Load the configuration
     let config = Config::load()?.ok_or_else(|| anyhow!("Config not found"))?;
 
+    // Log the requests (`RUST_LOG` overrides the filter)
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("warn,rpc=info,server=info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
     // Starts the server
     let server = Server::new(config);
+    if let Some(addr) = server.addr() {
+        eprintln!(
+            "{} {}: {}{}",
+            "✔".bright_green(),
+            "Listening on".bold(),
+            addr,
+            if server.tls() { " (TLS)" } else { "" }
+        );
+    }
+    if let Some(socket) = &server.socket {
+        eprintln!(
+            "{} {}: {}",
+            "✔".bright_green(),
+            "Listening on".bold(),
+            socket.display()
+        );
+    }
+    server.start().await
+}
+
+// ------------------------------------------------------------------
+// cert
+// ------------------------------------------------------------------
+
+/// Client certificate CLI arguments
+#[derive(Debug, Parser)]
+pub struct CertArgs {
+    /// Email of the user the certificate authenticates
+    email: String,
+    /// Output directory
+    #[clap(long, default_value = ".")]
+    out: PathBuf,
+}
+
+/// Issues a client certificate
+pub async fn cert(args: CertArgs) -> anyhow::Result<()> {
+    // Load the configuration
+    let config = Config::load()?.ok_or_else(|| anyhow!("Config not found"))?;
+
+    // Issue the certificate
+    let server = Server::new(config);
+    let cert = server.issue_client_certificate(&args.email).await?;
+
+    let cert_file = args.out.join("client.pem");
+    let key_file = args.out.join("client-key.pem");
+    fs::write(&cert_file, &cert.cert)?;
+    write_private(&key_file, &cert.key)?;
     eprintln!(
         "{} {}: {}",
         "✔".bright_green(),
-        "Listening on".bold(),
-        server.addr()
+        "Client certificate".bold(),
+        cert_file.display()
     );
-    server.start().await
+    eprintln!(
+        "{} {}: {}",
+        "✔".bright_green(),
+        "Client private key".bold(),
+        key_file.display()
+    );
+    eprintln!(
+        "{} Subject {} authenticates as {}",
+        "i".bright_cyan(),
+        cert.subject,
+        args.email
+    );
+    Ok(())
 }
 
 // ------------------------------------------------------------------
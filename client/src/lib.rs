@@ -2,37 +2,126 @@
 
 #![deny(missing_docs)]
 
-use service::*;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::path::Path;
+
+#[cfg(unix)]
+use service::rpc::unix::UnixTransport;
+use service::{
+    gen,
+    rpc::{http::HttpTransport, local::LocalTransport, ws::WsTransport, Sender},
+    SecretsService, ServiceStatus, API_VERSION,
+};
+pub use service::{Error, ErrorKind};
 
 /// Marker struct for the client
 pub struct Secrets;
 
 /// Client
-pub struct Client {
-    /// RPC client
-    rpc_client: rpc::Client<rpc::json::JsonTransport>,
-    /// Token
-    token: Option<String>,
+///
+/// The service methods are available through [gen::Client].
+pub struct Client<S = HttpTransport>
+where
+    S: Sender,
+{
+    /// Service client
+    service: gen::Client<S>,
 }
 
 impl Client {
-    /// Instantiates a new [Client]
-    pub const fn new() -> Self {
-        let sender = rpc::json::JsonTransport::new();
-        let rpc_client = rpc::Client::new(sender);
+    /// Instantiates a new [Client] for the server at `url` (eg. `http://localhost:6666`)
+    pub fn new(url: impl AsRef<str>) -> Result<Self, Error> {
+        Ok(Self {
+            service: gen::Client::new(url)?,
+        })
+    }
+
+    /// Connects to the server at `url`, and checks its compatibility (see [Client::check_version])
+    pub async fn connect(url: impl AsRef<str>) -> Result<Self, Error> {
+        let client = Self::new(url)?;
+        client.check_version().await?;
+        Ok(client)
+    }
+}
+
+impl Client<WsTransport> {
+    /// Instantiates a new [Client] for the server at `url`, over a WebSocket connection
+    ///
+    /// The client can stream the secret events with [gen::Client::events].
+    pub fn ws(url: impl AsRef<str>) -> Result<Self, Error> {
+        Ok(Self {
+            service: gen::Client::ws(url)?,
+        })
+    }
+}
+
+impl Client<LocalTransport> {
+    /// Instantiates a new [Client] for a service in the same process (eg. in tests)
+    pub fn local<T>(service: T) -> Self
+    where
+        T: SecretsService + Clone + Send + Sync + 'static,
+    {
         Self {
-            rpc_client,
-            token: None,
+            service: gen::Client::local(service),
         }
     }
+}
 
-    /// Authenticates the client
-    pub fn authenticate(&mut self, token: impl AsRef<str>) {
-        self.token = Some(token.as_ref().to_string());
+#[cfg(unix)]
+impl Client<UnixTransport> {
+    /// Instantiates a new [Client] for the server listening on a Unix socket
+    pub fn unix(path: impl AsRef<Path>) -> Self {
+        Self {
+            service: gen::Client::unix(path),
+        }
     }
+}
+
+impl<S> Client<S>
+where
+    S: Sender,
+{
+    /// Instantiates a new [Client] from a configured transport
+    ///
+    /// Use [HttpTransport::builder] to set the timeouts.
+    pub const fn with_transport(transport: S) -> Self {
+        Self {
+            service: gen::Client::with_transport(transport),
+        }
+    }
+
+    /// Checks that the server supports the [API_VERSION] of the client
+    ///
+    /// Returns the server status, or an [ErrorKind::IncompatibleVersion] error.
+    pub async fn check_version(&self) -> Result<ServiceStatus, Error> {
+        let status = self.service.status().await?;
+        if !status.supports(API_VERSION) {
+            return Err(Error::incompatible_version(format!(
+                "API version {API_VERSION} is not supported by the server (supported: {} to {})",
+                status.min_api_version, status.api_version
+            )));
+        }
+        Ok(status)
+    }
+}
+
+impl<S> Deref for Client<S>
+where
+    S: Sender,
+{
+    type Target = gen::Client<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.service
+    }
+}
 
-    /// Deuthenticates the client
-    pub fn deauthenticate(&mut self) {
-        self.token = None;
+impl<S> DerefMut for Client<S>
+where
+    S: Sender,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.service
     }
 }
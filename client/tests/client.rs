@@ -1,30 +1,249 @@
 //! Client tests
 
-// use client::Client;
-// use server::{Config, Server};
-
-// /// Initializes a test
-// fn init() -> Client {
-//     /// Started tag
-//     static STARTED: AtomicBool = AtomicBool::new(false);
-
-//     let was_started = STARTED
-//         .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
-//         .unwrap();
-
-//     if !was_started {
-//         tokio::spawn(async {
-//             let srv_cfg = Config::default();
-//             let server = Server::new(srv_cfg);
-//             server.start().await.unwrap();
-//         });
-//     }
-
-//     Client::new("http://localhost:6666").unwrap()
-// }
-
-// #[tokio::test]
-// async fn status() {
-//     let client = init();
-//     // let _status = client.status().await.unwrap();
-// }
+use std::{path::PathBuf, time::Duration};
+
+use client::{Client, Error, ErrorKind};
+use server::{Config, Server};
+use service::{
+    rpc::{http::HttpTransport, Sender},
+    LoginInput, OrganizationInput, Secret, SecretEventKind, SecretInput, SignupInput, Topic,
+};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+
+/// Test server, on an ephemeral port with a temporary database
+struct TestServer {
+    /// URL of the TCP port
+    url: String,
+    /// Path to the Unix socket, if served
+    socket: Option<PathBuf>,
+    /// Temporary directory of the database, removed once dropped (even on panic)
+    _dir: TempDir,
+}
+
+impl TestServer {
+    /// Starts a server, also on a Unix socket if set
+    ///
+    /// The TCP port is bound before the server starts: the clients connect without
+    /// retries. The Unix socket is bound by the server, and waited for.
+    async fn start(unix: bool) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let socket = unix.then(|| dir.path().join("server.sock"));
+
+        let srv_cfg = Config {
+            port: Some(port),
+            socket: socket.clone(),
+            database: dir.path().join("data.db"),
+            ..Config::default()
+        };
+        let server = Server::new(srv_cfg).with_listener(listener).unwrap();
+        server.init().await.unwrap();
+        tokio::spawn(async { server.start().await.unwrap() });
+
+        if let Some(socket) = &socket {
+            for _ in 0..50 {
+                if socket.exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+        Self {
+            url: format!("http://127.0.0.1:{port}"),
+            socket,
+            _dir: dir,
+        }
+    }
+}
+
+#[tokio::test]
+async fn status() {
+    let server = TestServer::start(false).await;
+    let client = Client::new(&server.url).unwrap();
+    client.status().await.unwrap();
+
+    // The server supports the version of the client
+    Client::connect(&server.url).await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn status_unix() {
+    let server = TestServer::start(true).await;
+    let client = Client::unix(server.socket.as_ref().unwrap());
+    client.status().await.unwrap();
+}
+
+#[tokio::test]
+async fn secret_events() {
+    let server = TestServer::start(false).await;
+    let mut client = Client::ws(&server.url).unwrap();
+
+    let login = client
+        .signup(SignupInput {
+            email: "events@doe.com".to_string(),
+            name: "John".to_string(),
+            password: "password".to_string(),
+            label: None,
+        })
+        .await
+        .unwrap();
+    client.authenticate(&login.token);
+    let org = client
+        .add_organization(OrganizationInput {
+            name: "Acme".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let mut events = client
+        .events(&Topic::Organization(org.id.clone()))
+        .await
+        .unwrap();
+    let secret = client
+        .add_secret(SecretInput {
+            org_id: org.id,
+            project_id: None,
+            environment: None,
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        })
+        .await
+        .unwrap();
+    client
+        .update_secret(Secret {
+            value: "5678".to_string(),
+            ..secret.clone()
+        })
+        .await
+        .unwrap();
+
+    let added = events.next().await.unwrap();
+    assert_eq!(added.kind, SecretEventKind::Added);
+    assert_eq!(added.id, secret.id);
+    let updated = events.next().await.unwrap();
+    assert_eq!(updated.kind, SecretEventKind::Updated);
+}
+
+#[tokio::test]
+async fn revoked_events() {
+    let server = TestServer::start(false).await;
+    let mut client = Client::ws(&server.url).unwrap();
+
+    let login = client
+        .signup(SignupInput {
+            email: "revoked@doe.com".to_string(),
+            name: "John".to_string(),
+            password: "password".to_string(),
+            label: None,
+        })
+        .await
+        .unwrap();
+    client.authenticate(&login.token);
+    let org = client
+        .add_organization(OrganizationInput {
+            name: "Acme".to_string(),
+        })
+        .await
+        .unwrap();
+    let mut events = client
+        .events(&Topic::Organization(org.id.clone()))
+        .await
+        .unwrap();
+
+    // Another session adds a secret, once the session of the stream is revoked
+    let mut other = Client::new(&server.url).unwrap();
+    let login = other
+        .login(LoginInput {
+            email: "revoked@doe.com".to_string(),
+            password: "password".to_string(),
+            label: None,
+        })
+        .await
+        .unwrap();
+    other.authenticate(&login.token);
+    client.logout().await.unwrap();
+    other
+        .add_secret(SecretInput {
+            org_id: org.id,
+            project_id: None,
+            environment: None,
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // The stream ends without the event
+    let next = tokio::time::timeout(Duration::from_secs(5), events.next()).await;
+    assert!(next.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn invalid_request() {
+    let server = TestServer::start(false).await;
+    // A malformed request is a client error, which is not retried
+    let http = hyper::Client::new();
+    let requests = [
+        (None, ErrorKind::InvalidInput),
+        (Some("Basic abc"), ErrorKind::Unauthorized),
+    ];
+    for (auth, kind) in requests {
+        let mut req = hyper::Request::post(format!("{}/", server.url));
+        if let Some(auth) = auth {
+            req = req
+                .header("X-RPC-METHOD", "status")
+                .header("Authorization", auth);
+        }
+        let res = http.request(req.body(hyper::Body::empty()).unwrap()).await;
+        let res = HttpTransport::new()
+            .decode_response::<(), Error>(res.unwrap())
+            .await;
+        let err = res.unwrap_err();
+        assert_eq!(err.kind, kind, "{err}");
+        assert!(!err.is_retryable());
+    }
+}
+
+#[tokio::test]
+async fn local() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let srv_cfg = Config {
+        port: None,
+        database: dir.path().join("data.db"),
+        ..Config::default()
+    };
+    let server = Server::new(srv_cfg);
+    server.init().await.unwrap();
+
+    // No socket, the requests are still encoded
+    let mut client = Client::local(server.service().await.unwrap());
+    let status = client.check_version().await.unwrap();
+    assert_eq!(status.api_version, service::API_VERSION);
+    let login = client
+        .signup(SignupInput {
+            email: "local@doe.com".to_string(),
+            name: "John".to_string(),
+            password: "password".to_string(),
+            label: None,
+        })
+        .await
+        .unwrap();
+    let err = client.organization("1".to_string()).await.unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Unauthorized);
+
+    client.authenticate(&login.token);
+    let org = client
+        .add_organization(OrganizationInput {
+            name: "Acme".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.organization(org.id.clone()).await.unwrap(), org);
+    let err = client.organization("999".to_string()).await.unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NotFound);
+    assert!(!err.is_retryable());
+}
@@ -0,0 +1,349 @@
+//! RPC macros
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Attribute, Error, FnArg, GenericArgument, Ident, ItemTrait, Pat, PathArguments, ReturnType,
+    Signature, Token, TraitItem, Type,
+};
+
+/// Name of the argument carrying the credentials
+const AUTH_ARG: &str = "auth";
+
+/// Derives the RPC client and server from a service trait
+///
+/// ```ignore
+/// #[rpc::service(client = gen::Client, handler = gen::Handler)]
+/// #[async_trait]
+/// pub trait MyService {
+///     /// Returns the status
+///     async fn status(&self) -> Result<Status, Error>;
+///
+///     /// Reads an item
+///     async fn item(&self, auth: rpc::Credentials, id: String) -> Result<Item, Error>;
+/// }
+/// ```
+///
+/// Each method is exposed as a RPC method with the same name.
+///
+/// A method whose first argument is named `auth` requires authentication: the
+/// handler passes the credentials of the request (`rpc::Credentials`: token or
+/// client certificate), and rejects requests which do not carry any with an
+/// `unauthorized` error. The other arguments make the payload (`()` if none, the value
+/// itself if one, a tuple otherwise).
+///
+/// All methods must return a `Result<T, E>`, where `E: From<String>`.
+///
+/// # Client
+///
+/// `client = <path>` generates one method per service method on the client type,
+/// minus the `auth` argument. The client type must be generic over the sender `S`
+/// (`S: rpc::Sender`), and provide a method:
+///
+/// ```ignore
+/// async fn call<P, R, E>(&self, method: &str, data: P) -> rpc::Response<R, E>;
+/// ```
+///
+/// # Handler
+///
+/// `handler = <path>` generates a `router()` method on the handler type, which returns
+/// a `rpc::Router` of the service methods. The handler type must be generic over the
+/// service implementation `S` (`S: Clone`), and provide a method:
+///
+/// ```ignore
+/// fn service(&self) -> &S;
+/// ```
+///
+/// The router may be served as is, or mounted under a namespace with `rpc::Router::nest`.
+#[proc_macro_attribute]
+pub fn service(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as ServiceArgs);
+    let item = parse_macro_input!(input as ItemTrait);
+
+    match expand(args, item) {
+        Ok(ok) => ok.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Service macro arguments
+#[derive(Default)]
+struct ServiceArgs {
+    /// Client type
+    client: Option<syn::Path>,
+    /// Handler type
+    handler: Option<syn::Path>,
+}
+
+impl Parse for ServiceArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = ServiceArgs::default();
+        let pairs = Punctuated::<ServiceArg, Token![,]>::parse_terminated(input)?;
+        for ServiceArg { name, path } in pairs {
+            match name.to_string().as_str() {
+                "client" => args.client = Some(path),
+                "handler" => args.handler = Some(path),
+                other => {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("Unknown argument: {other}"),
+                    ));
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Service macro argument (`name = path`)
+struct ServiceArg {
+    /// Argument name
+    name: Ident,
+    /// Argument value
+    path: syn::Path,
+}
+
+impl Parse for ServiceArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let path = input.parse()?;
+        Ok(Self { name, path })
+    }
+}
+
+/// Service method
+struct Method {
+    /// Doc attributes
+    docs: Vec<Attribute>,
+    /// Name
+    name: Ident,
+    /// Authenticated method
+    auth: bool,
+    /// Payload arguments
+    args: Vec<(Ident, Type)>,
+    /// Ok type
+    ok: Type,
+    /// Error type
+    err: Type,
+}
+
+impl Method {
+    /// Parses a trait method
+    fn parse(attrs: &[Attribute], sig: &Signature) -> syn::Result<Self> {
+        if sig.asyncness.is_none() {
+            return Err(Error::new_spanned(sig, "Service methods must be async"));
+        }
+
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(r)) if r.reference.is_some() && r.mutability.is_none() => {}
+            _ => return Err(Error::new_spanned(sig, "Service methods must take `&self`")),
+        }
+
+        let mut auth = false;
+        let mut args = vec![];
+        for (i, input) in inputs.enumerate() {
+            let arg = match input {
+                FnArg::Typed(arg) => arg,
+                FnArg::Receiver(_) => unreachable!("receiver is the first argument"),
+            };
+            let ident = match &*arg.pat {
+                Pat::Ident(pat) => pat.ident.clone(),
+                pat => return Err(Error::new_spanned(pat, "Unsupported argument pattern")),
+            };
+            if i == 0 && ident == AUTH_ARG {
+                auth = true;
+            } else {
+                args.push((ident, (*arg.ty).clone()));
+            }
+        }
+
+        let (ok, err) = result_types(&sig.output)?;
+
+        Ok(Self {
+            docs: attrs
+                .iter()
+                .filter(|a| a.path.is_ident("doc"))
+                .cloned()
+                .collect(),
+            name: sig.ident.clone(),
+            auth,
+            args,
+            ok,
+            err,
+        })
+    }
+
+    /// Returns the RPC method name
+    fn rpc_name(&self) -> String {
+        self.name.to_string()
+    }
+
+    /// Returns the payload type
+    fn payload_type(&self) -> TokenStream2 {
+        let types = self.args.iter().map(|(_, ty)| ty);
+        match self.args.as_slice() {
+            [(_, ty)] => ty.to_token_stream(),
+            _ => quote! { (#(#types),*) },
+        }
+    }
+
+    /// Returns the payload value
+    fn payload_value(&self) -> TokenStream2 {
+        let idents = self.args.iter().map(|(ident, _)| ident);
+        match self.args.as_slice() {
+            [(ident, _)] => ident.to_token_stream(),
+            _ => quote! { (#(#idents),*) },
+        }
+    }
+}
+
+/// Extracts the `T` and `E` types from a `Result<T, E>` return type
+fn result_types(output: &ReturnType) -> syn::Result<(Type, Type)> {
+    let invalid = || Error::new_spanned(output, "Service methods must return a `Result<T, E>`");
+
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return Err(invalid()),
+    };
+    let segment = match &**ty {
+        Type::Path(p) => p.path.segments.last().ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    if segment.ident != "Result" {
+        return Err(invalid());
+    }
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => &args.args,
+        _ => return Err(invalid()),
+    };
+    let mut types = args.iter().filter_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty.clone()),
+        _ => None,
+    });
+    match (types.next(), types.next(), types.next()) {
+        (Some(ok), Some(err), None) => Ok((ok, err)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Expands the service macro
+fn expand(args: ServiceArgs, item: ItemTrait) -> syn::Result<TokenStream2> {
+    let methods = item
+        .items
+        .iter()
+        .filter_map(|i| match i {
+            TraitItem::Method(m) => Some(Method::parse(&m.attrs, &m.sig)),
+            _ => None,
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let client = match &args.client {
+        Some(client) => expand_client(client, &methods),
+        None => quote! {},
+    };
+    let handler = match &args.handler {
+        Some(handler) => expand_handler(handler, &item.ident, &methods)?,
+        None => quote! {},
+    };
+
+    Ok(quote! {
+        #item
+        #client
+        #handler
+    })
+}
+
+/// Expands the client methods
+fn expand_client(client: &syn::Path, methods: &[Method]) -> TokenStream2 {
+    let fns = methods.iter().map(|m| {
+        let docs = &m.docs;
+        let name = &m.name;
+        let rpc_name = m.rpc_name();
+        let args = m.args.iter().map(|(ident, ty)| quote! { #ident: #ty });
+        let payload_type = m.payload_type();
+        let payload = m.payload_value();
+        let ok = &m.ok;
+        let err = &m.err;
+
+        quote! {
+            #(#docs)*
+            pub async fn #name(&self, #(#args),*) -> ::rpc::Response<#ok, #err> {
+                self.call::<#payload_type, #ok, #err>(#rpc_name, #payload).await
+            }
+        }
+    });
+
+    quote! {
+        impl<S> #client<S>
+        where
+            S: ::rpc::Sender,
+        {
+            #(#fns)*
+        }
+    }
+}
+
+/// Expands the handler router
+fn expand_handler(
+    handler: &syn::Path,
+    service: &Ident,
+    methods: &[Method],
+) -> syn::Result<TokenStream2> {
+    if methods.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "A service requires at least one method",
+        ));
+    }
+
+    let routes = methods.iter().map(|m| {
+        let name = &m.name;
+        let rpc_name = m.rpc_name();
+        let args = m.args.iter().map(|(ident, _)| ident);
+        let payload_type = m.payload_type();
+        let payload = m.payload_value();
+
+        if m.auth {
+            quote! {
+                .authenticated(#rpc_name, {
+                    let service = self.service().clone();
+                    move |_ctx: ::rpc::Context, auth, #payload: #payload_type| {
+                        let service = service.clone();
+                        async move { service.#name(auth, #(#args),*).await }
+                    }
+                })
+            }
+        } else {
+            quote! {
+                .method(#rpc_name, {
+                    let service = self.service().clone();
+                    move |_ctx: ::rpc::Context, #payload: #payload_type| {
+                        let service = service.clone();
+                        async move { service.#name(#(#args),*).await }
+                    }
+                })
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl<S> #handler<S>
+        where
+            S: #service + Clone + Send + Sync + 'static,
+        {
+            /// Returns the router of the service methods
+            pub fn router(&self) -> ::rpc::Router {
+                ::rpc::Router::new()
+                    #(#routes)*
+            }
+        }
+    })
+}
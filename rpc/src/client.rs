@@ -1,18 +1,28 @@
 //! RPC client
 
+use std::time::Instant;
+
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::Instrument;
 
 use crate::{Request, Response};
 
 /// RPC sender
 #[async_trait]
-pub trait Sender {
+pub trait Sender: Send + Sync {
     /// Sender request
-    type Request;
+    type Request: Send;
 
     /// Sender response
-    type Response;
+    type Response: Send;
+
+    /// Generates a request ID
+    ///
+    /// The transports send it with the request, and the server echoes it back.
+    fn request_id(&self) -> String {
+        crate::new_request_id()
+    }
 
     /// Sends a request
     async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
@@ -30,6 +40,36 @@ pub trait Sender {
     where
         T: DeserializeOwned,
         E: DeserializeOwned + From<String>;
+
+    /// Encodes a batch of RPC requests, sent in one round trip
+    ///
+    /// By default, batches are not supported.
+    async fn encode_batch<T, E>(&self, reqs: Vec<Request<T>>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let _ = reqs;
+        Err(E::from(
+            "Batch requests are not supported by the transport".to_string(),
+        ))
+    }
+
+    /// Decodes the responses to a batch of `len` requests, in the order of the requests
+    async fn decode_batch<T, E>(
+        &self,
+        res: Self::Response,
+        len: usize,
+    ) -> Result<Vec<Response<T, E>>, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let _ = (res, len);
+        Err(E::from(
+            "Batch requests are not supported by the transport".to_string(),
+        ))
+    }
 }
 
 /// RPC Client
@@ -50,6 +90,11 @@ where
     pub const fn new(sender: S) -> Self {
         Self { sender }
     }
+
+    /// Returns the sender
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
 }
 
 impl<S> Client<S>
@@ -57,7 +102,38 @@ where
     S: Sender,
 {
     /// Calls a RPC method
-    pub async fn call<P, R, E>(&self, req: Request<P>) -> Response<R, E>
+    ///
+    /// The call is traced with its method and request ID, but not its payload.
+    pub async fn call<P, R, E>(&self, mut req: Request<P>) -> Response<R, E>
+    where
+        P: Serialize + Send,
+        R: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let sender = &self.sender;
+
+        let id = req.id.get_or_insert_with(|| sender.request_id()).clone();
+        let span = tracing::debug_span!("rpc.call", method = %req.method, request_id = %id);
+        let start = Instant::now();
+        let res = async {
+            let req = sender.encode_request::<P, E>(req).await?;
+            let res = sender.send::<E>(req).await?;
+            sender.decode_response::<R, E>(res).await
+        }
+        .instrument(span.clone())
+        .await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        let outcome = if res.is_ok() { "ok" } else { "err" };
+        tracing::debug!(parent: &span, outcome, latency_ms, "call finished");
+        res
+    }
+
+    /// Calls several RPC methods in one round trip
+    ///
+    /// Returns the responses in the order of the requests. The outer error is
+    /// returned if the whole batch failed. The requests without ID share the ID
+    /// of the batch.
+    pub async fn batch<P, R, E>(&self, mut reqs: Vec<Request<P>>) -> Result<Vec<Response<R, E>>, E>
     where
         P: Serialize + Send,
         R: DeserializeOwned,
@@ -65,8 +141,21 @@ where
     {
         let sender = &self.sender;
 
-        let req = sender.encode_request::<P, E>(req).await?;
-        let res = sender.send::<E>(req).await?;
-        sender.decode_response::<R, E>(res).await
+        let len = reqs.len();
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let id = sender.request_id();
+        for req in &mut reqs {
+            req.id.get_or_insert_with(|| id.clone());
+        }
+        let span = tracing::debug_span!("rpc.batch", len, request_id = %id);
+        async {
+            let req = sender.encode_batch::<P, E>(reqs).await?;
+            let res = sender.send::<E>(req).await?;
+            sender.decode_batch::<R, E>(res, len).await
+        }
+        .instrument(span)
+        .await
     }
 }
@@ -0,0 +1,207 @@
+//! Codecs
+//!
+//! A codec serializes the payloads, independently of the transport framing.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Payload codec
+pub trait Codec {
+    /// Returns the media type (eg. `application/json`)
+    fn content_type(&self) -> &'static str;
+
+    /// Encodes a value
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, String>
+    where
+        T: Serialize + ?Sized;
+
+    /// Decodes a value
+    fn decode<T>(&self, data: &[u8]) -> Result<T, String>
+    where
+        T: DeserializeOwned;
+}
+
+/// JSON codec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, String>
+    where
+        T: Serialize + ?Sized,
+    {
+        serde_json::to_vec(value).map_err(|err| err.to_string())
+    }
+
+    fn decode<T>(&self, data: &[u8]) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_slice(data).map_err(|err| err.to_string())
+    }
+}
+
+/// CBOR codec (binary)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn content_type(&self) -> &'static str {
+        "application/cbor"
+    }
+
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, String>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut data = vec![];
+        ciborium::ser::into_writer(value, &mut data).map_err(|err| err.to_string())?;
+        Ok(data)
+    }
+
+    fn decode<T>(&self, data: &[u8]) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        ciborium::de::from_reader(data).map_err(|err| err.to_string())
+    }
+}
+
+/// Codec chosen at runtime
+///
+/// The format is negotiated with the `Content-Type` and `Accept` headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// JSON
+    #[default]
+    Json,
+    /// CBOR
+    Cbor,
+}
+
+impl Format {
+    /// Supported formats, by order of preference
+    pub const ALL: [Format; 2] = [Format::Json, Format::Cbor];
+
+    /// Returns the format of a media type (`Content-Type` header)
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = media_type(content_type);
+        Self::ALL
+            .into_iter()
+            .find(|f| f.content_type().eq_ignore_ascii_case(media_type))
+    }
+
+    /// Returns the preferred format accepted by a client (`Accept` header)
+    ///
+    /// Returns [None] if no supported format is acceptable.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        let mut best: Option<(Format, f32)> = None;
+        for item in accept.split(',') {
+            let range = media_type(item);
+            let quality = item
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = Self::ALL.into_iter().find(|f| {
+                range == "*/*"
+                    || range == "application/*"
+                    || f.content_type().eq_ignore_ascii_case(range)
+            });
+            match (format, best) {
+                (Some(format), Some((_, q))) if quality > q => best = Some((format, quality)),
+                (Some(format), None) => best = Some((format, quality)),
+                _ => {}
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+}
+
+impl Codec for Format {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => JsonCodec.content_type(),
+            Format::Cbor => CborCodec.content_type(),
+        }
+    }
+
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, String>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Format::Json => JsonCodec.encode(value),
+            Format::Cbor => CborCodec.encode(value),
+        }
+    }
+
+    fn decode<T>(&self, data: &[u8]) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            Format::Json => JsonCodec.decode(data),
+            Format::Cbor => CborCodec.decode(data),
+        }
+    }
+}
+
+/// Returns the media type of a header value, without the parameters
+fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or_default().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_type() {
+        assert_eq!(
+            Format::from_content_type("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::from_content_type("application/cbor"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(Format::from_content_type("text/plain"), None);
+    }
+
+    #[test]
+    fn accept() {
+        assert_eq!(Format::from_accept("application/cbor"), Some(Format::Cbor));
+        assert_eq!(Format::from_accept("*/*"), Some(Format::Json));
+        assert_eq!(
+            Format::from_accept("application/json;q=0.5, application/cbor"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(
+            Format::from_accept("application/cbor;q=0, application/json"),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::from_accept("text/html"), None);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = ("key".to_string(), vec![1u8, 2, 3], Some(42i64));
+        for format in Format::ALL {
+            let data = format.encode(&value).unwrap();
+            let decoded: (String, Vec<u8>, Option<i64>) = format.decode(&data).unwrap();
+            assert_eq!(decoded, value);
+        }
+        let json = Format::Json.encode(&value).unwrap();
+        let cbor = Format::Cbor.encode(&value).unwrap();
+        assert!(cbor.len() < json.len());
+    }
+}
@@ -0,0 +1,177 @@
+//! Events
+//!
+//! The server publishes events to topics, and pushes them to the subscribed clients.
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc};
+
+/// Number of events buffered for a slow subscriber
+const TOPIC_CAPACITY: usize = 64;
+
+/// Event published to a topic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// Topic
+    pub topic: String,
+    /// Data
+    pub data: Value,
+}
+
+impl Event {
+    /// Decodes the event data
+    pub fn decode<T>(&self) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_value(self.data.clone()).map_err(|err| err.to_string())
+    }
+}
+
+/// Events broker
+///
+/// Dispatches the published events to the subscribers of their topic.
+/// Clones share the same subscribers.
+#[derive(Debug, Clone, Default)]
+pub struct Broker {
+    /// Topics with subscribers
+    topics: Arc<Mutex<HashMap<String, broadcast::Sender<Event>>>>,
+}
+
+impl Broker {
+    /// Instantiates a new [Broker]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes an event to a topic
+    ///
+    /// The event is dropped if the topic has no subscribers.
+    pub fn publish<T>(&self, topic: impl AsRef<str>, data: &T) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        let topic = topic.as_ref();
+        let data = serde_json::to_value(data).map_err(|err| err.to_string())?;
+
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(sender) = topics.get(topic) {
+            let event = Event {
+                topic: topic.to_string(),
+                data,
+            };
+            if sender.send(event).is_err() {
+                // All the subscribers are gone
+                topics.remove(topic);
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes to a topic
+    pub fn subscribe(&self, topic: impl AsRef<str>) -> broadcast::Receiver<Event> {
+        let mut topics = self.topics.lock().unwrap();
+        match topics.get(topic.as_ref()) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = broadcast::channel(TOPIC_CAPACITY);
+                topics.insert(topic.as_ref().to_string(), sender);
+                receiver
+            }
+        }
+    }
+}
+
+/// Stream of events pushed by the server
+///
+/// The events whose data cannot be decoded as `T` are skipped.
+/// The stream ends when the connection to the server is closed.
+#[derive(Debug)]
+pub struct EventStream<T> {
+    /// Received events
+    receiver: mpsc::UnboundedReceiver<Event>,
+    /// Event data
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T> EventStream<T>
+where
+    T: DeserializeOwned,
+{
+    /// Instantiates a new [EventStream]
+    pub(crate) fn new(receiver: mpsc::UnboundedReceiver<Event>) -> Self {
+        Self {
+            receiver,
+            data: PhantomData,
+        }
+    }
+
+    /// Returns the next event, or [None] once the stream has ended
+    pub async fn next(&mut self) -> Option<T> {
+        loop {
+            let event = self.receiver.recv().await?;
+            if let Ok(data) = event.decode() {
+                return Some(data);
+            }
+        }
+    }
+}
+
+impl<T> Stream for EventStream<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(event)) => {
+                    if let Ok(data) = event.decode() {
+                        return Poll::Ready(Some(data));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_subscribe() {
+        let broker = Broker::new();
+
+        // No subscribers
+        broker.publish("a", &1).unwrap();
+
+        let mut a = broker.subscribe("a");
+        let mut b = broker.subscribe("b");
+        broker.publish("a", &2).unwrap();
+        broker.publish("b", &"x").unwrap();
+
+        let event = a.recv().await.unwrap();
+        assert_eq!(event.topic, "a");
+        assert_eq!(event.decode::<i64>().unwrap(), 2);
+        assert_eq!(b.recv().await.unwrap().decode::<String>().unwrap(), "x");
+        assert!(a.try_recv().is_err());
+
+        // The topic is dropped with its last subscriber
+        drop(a);
+        broker.publish("a", &3).unwrap();
+        assert!(!broker.topics.lock().unwrap().contains_key("a"));
+    }
+}
@@ -3,16 +3,52 @@
 use serde::{Deserialize, Serialize};
 
 pub mod client;
+pub mod codec;
+pub mod events;
+pub mod limits;
+pub mod middleware;
+pub mod router;
 pub mod server;
+#[cfg(test)]
+mod testing;
+pub mod tls;
+pub mod trace;
 pub mod transports;
+pub mod version;
+
+pub use async_trait::async_trait;
+pub use client::*;
+pub use limits::Limits;
+pub use middleware::{Middleware, Next};
+pub use router::{Context, Router};
+pub use rpc_macros::service;
+pub use server::*;
+pub use trace::Trace;
+pub use transports::*;
+pub use version::Versions;
 
 /// RPC request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Request<T> {
+    /// Request ID, which correlates the client and server logs
+    ///
+    /// The [Client] sets it with [Sender::request_id] when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Version of the API of the client
+    ///
+    /// The server may reject the versions it does not support (see [Versions]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
     /// Method
     pub method: String,
     /// Authentication token
     pub token: Option<String>,
+    /// Subject of the client certificate
+    ///
+    /// This is only set by the receiver, once the certificate has been verified
+    #[serde(skip)]
+    pub certificate: Option<String>,
     /// Data
     pub data: T,
 }
@@ -21,12 +57,46 @@ impl<T> Request<T> {
     /// Instantiates a new [Request]
     pub fn new(method: impl AsRef<str>, token: Option<String>, data: T) -> Self {
         Self {
+            id: None,
+            version: None,
             method: method.as_ref().to_string(),
             token,
+            certificate: None,
             data,
         }
     }
+
+    /// Sets the version of the API of the client
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Returns the request credentials
+    ///
+    /// The bearer token takes precedence over the client certificate.
+    pub fn credentials(&self) -> Option<Credentials> {
+        match (&self.token, &self.certificate) {
+            (Some(token), _) => Some(Credentials::Token(token.clone())),
+            (None, Some(subject)) => Some(Credentials::Certificate(subject.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Request credentials
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Bearer token
+    Token(String),
+    /// Subject of a client certificate verified by the transport
+    Certificate(String),
 }
 
 /// RPC response
 pub type Response<T, E> = Result<T, E>;
+
+/// Returns a new request ID (UUID v4)
+pub(crate) fn new_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
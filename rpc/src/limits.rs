@@ -0,0 +1,481 @@
+//! Limits
+//!
+//! The limits protect a server from the clients which send too much, or too slowly.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::server::accept::Accept;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::{AcquireError, OwnedSemaphorePermit, Semaphore},
+    time::{Instant, Sleep},
+};
+
+use crate::tls::{ClientCertificate, PeerCertificate};
+
+/// Limits of a server
+///
+/// ```ignore
+/// let limits = Limits {
+///     max_body_size: 64 * 1024,
+///     ..Limits::default()
+/// };
+/// let receiver = HttpTransport::builder().limits(limits).build::<String>()?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of a request body, in bytes
+    ///
+    /// The larger requests are answered with `413 Payload Too Large`.
+    pub max_body_size: usize,
+    /// Timeout to receive the headers of a request
+    pub header_timeout: Option<Duration>,
+    /// Timeout to receive the body of a request, once its headers are received
+    pub body_timeout: Option<Duration>,
+    /// Timeout of a connection without any read or write
+    pub idle_timeout: Option<Duration>,
+    /// Maximum number of concurrent connections
+    ///
+    /// The other connections wait to be accepted. A connection takes its slot before
+    /// its TLS handshake.
+    pub max_connections: Option<usize>,
+    /// Maximum number of concurrent calls over a WebSocket connection
+    ///
+    /// The next calls are read once an in-flight call is answered.
+    pub max_socket_calls: Option<usize>,
+}
+
+impl Limits {
+    /// Default maximum size of a request body (1 MiB)
+    pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+    /// Default timeout to receive the headers of a request
+    pub const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Default timeout to receive the body of a request
+    pub const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Default timeout of an idle connection
+    pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Default maximum number of concurrent connections
+    pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+
+    /// Default maximum number of concurrent calls over a WebSocket connection
+    pub const DEFAULT_MAX_SOCKET_CALLS: usize = 64;
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body_size: Self::DEFAULT_MAX_BODY_SIZE,
+            header_timeout: Some(Self::DEFAULT_HEADER_TIMEOUT),
+            body_timeout: Some(Self::DEFAULT_BODY_TIMEOUT),
+            idle_timeout: Some(Self::DEFAULT_IDLE_TIMEOUT),
+            max_connections: Some(Self::DEFAULT_MAX_CONNECTIONS),
+            max_socket_calls: Some(Self::DEFAULT_MAX_SOCKET_CALLS),
+        }
+    }
+}
+
+/// Pending acquisition of a connection slot
+type Acquire = Pin<Box<dyn Future<Output = Result<OwnedSemaphorePermit, AcquireError>> + Send>>;
+
+/// Incoming connections, within the limits
+///
+/// A connection is only accepted once a slot is free, and is closed when idle.
+pub(crate) struct LimitedIncoming<I> {
+    /// Incoming connections
+    inner: I,
+    /// Connection slots
+    slots: Option<Arc<Semaphore>>,
+    /// Pending acquisition of a slot
+    acquire: Option<Acquire>,
+    /// Slot acquired for the next connection
+    permit: Option<OwnedSemaphorePermit>,
+    /// Timeout of an idle connection
+    idle_timeout: Option<Duration>,
+}
+
+impl<I> LimitedIncoming<I> {
+    /// Applies the limits to incoming connections
+    pub(crate) fn new(inner: I, limits: &Limits) -> Self {
+        Self {
+            inner,
+            slots: limits
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            acquire: None,
+            permit: None,
+            idle_timeout: limits.idle_timeout,
+        }
+    }
+}
+
+impl<I> Accept for LimitedIncoming<I>
+where
+    I: Accept + Unpin,
+{
+    type Conn = LimitedConn<I::Conn>;
+    type Error = I::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+
+        // Wait for a free slot before accepting
+        if let (Some(slots), None) = (&this.slots, &this.permit) {
+            let acquire = this
+                .acquire
+                .get_or_insert_with(|| Box::pin(slots.clone().acquire_owned()));
+            match acquire.as_mut().poll(cx) {
+                Poll::Ready(Ok(permit)) => {
+                    this.acquire = None;
+                    this.permit = Some(permit);
+                }
+                // The semaphore is never closed
+                Poll::Ready(Err(_)) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        match Pin::new(&mut this.inner).poll_accept(cx) {
+            Poll::Ready(Some(Ok(conn))) => Poll::Ready(Some(Ok(LimitedConn {
+                inner: conn,
+                _permit: this.permit.take(),
+                idle: this.idle_timeout.map(Idle::new),
+            }))),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Connection within the limits
+pub(crate) struct LimitedConn<C> {
+    /// Connection
+    inner: C,
+    /// Slot of the connection, freed when dropped
+    _permit: Option<OwnedSemaphorePermit>,
+    /// Idle timer
+    idle: Option<Idle>,
+}
+
+impl<C> LimitedConn<C> {
+    /// Checks the idle timer on a pending I/O, and resets it on a completed one
+    fn poll_io<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        res: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        let idle = match &mut self.idle {
+            Some(idle) => idle,
+            None => return res,
+        };
+        match res {
+            Poll::Ready(res) => {
+                idle.reset();
+                Poll::Ready(res)
+            }
+            Poll::Pending => match idle.sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Idle connection",
+                ))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl<C> AsyncRead for LimitedConn<C>
+where
+    C: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.poll_io(cx, res)
+    }
+}
+
+impl<C> AsyncWrite for LimitedConn<C>
+where
+    C: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.poll_io(cx, res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_flush(cx);
+        this.poll_io(cx, res)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<C> PeerCertificate for LimitedConn<C>
+where
+    C: PeerCertificate,
+{
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        self.inner.peer_certificate()
+    }
+}
+
+/// Idle timer of a connection
+struct Idle {
+    /// Timeout
+    timeout: Duration,
+    /// Timer, reset on each read or write
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl Idle {
+    /// Instantiates a new [Idle] timer
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            sleep: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+
+    /// Resets the timer
+    fn reset(&mut self) {
+        self.sleep.as_mut().reset(Instant::now() + self.timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::{http::HttpTransport, Receiver, RequestError, Router, Server};
+
+    /// Starts a server with limits, and returns its address
+    async fn start(limits: Limits) -> SocketAddr {
+        let (listener, addr) = crate::testing::listener().await;
+        let receiver = HttpTransport::builder()
+            .limits(limits)
+            .build::<String>()
+            .unwrap();
+        let router =
+            Router::new().method(
+                "echo",
+                |_ctx, data: String| async move { Ok::<_, String>(data) },
+            );
+        tokio::spawn(async move { Server::new(receiver, router).serve(listener).await });
+        addr
+    }
+
+    /// Sends a raw HTTP request and returns the raw response
+    async fn request(stream: &mut TcpStream, body: &str) -> String {
+        let req = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nX-RPC-METHOD: echo\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut buf = vec![0; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    }
+
+    /// Returns true if the server closes the connection within a duration
+    async fn closed_within(stream: &mut TcpStream, duration: Duration) -> bool {
+        let mut buf = vec![0; 1024];
+        matches!(
+            tokio::time::timeout(duration, stream.read(&mut buf)).await,
+            Ok(Ok(0) | Err(_))
+        )
+    }
+
+    #[tokio::test]
+    async fn body_too_large() {
+        let limits = Limits {
+            max_body_size: 16,
+            ..Limits::default()
+        };
+        let receiver = HttpTransport::builder()
+            .limits(limits)
+            .build::<String>()
+            .unwrap();
+
+        // Announced size
+        let req = hyper::Request::builder()
+            .header("X-RPC-METHOD", "echo")
+            .body(hyper::Body::from("\"0123456789abcdefghij\""))
+            .unwrap();
+        let err = receiver
+            .decode_request::<RequestError>(req)
+            .await
+            .unwrap_err();
+        let res = receiver.encode_err(err).await;
+        assert_eq!(res.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let err: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(err["kind"], "payload_too_large");
+        assert_eq!(err["message"], "Body too large (max 16 bytes)");
+
+        // Streamed body, without a size
+        let chunks = vec![Ok::<_, io::Error>("\"0123456789"), Ok("abcdefghij\"")];
+        let req = hyper::Request::builder()
+            .header("X-RPC-METHOD", "echo")
+            .body(hyper::Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let receiver = HttpTransport::builder()
+            .limits(limits)
+            .build::<String>()
+            .unwrap();
+        let err = receiver.decode_request::<String>(req).await.unwrap_err();
+        assert_eq!(err, "Body too large (max 16 bytes)");
+
+        // Over the network
+        let addr = start(limits).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let res = request(&mut stream, "\"0123456789abcdefghij\"").await;
+        assert!(res.starts_with("HTTP/1.1 413"), "{res}");
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let res = request(&mut stream, "\"0123456789\"").await;
+        assert!(res.starts_with("HTTP/1.1 200"), "{res}");
+    }
+
+    #[tokio::test]
+    async fn slow_body() {
+        let timeout = Duration::from_millis(200);
+        let addr = start(Limits {
+            body_timeout: Some(timeout),
+            ..Limits::default()
+        })
+        .await;
+
+        // A body which never completes is answered at the deadline
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let req = "POST / HTTP/1.1\r\nHost: localhost\r\nX-RPC-METHOD: echo\r\nContent-Length: 64\r\n\r\n\"";
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut buf = vec![0; 1024];
+        let n = tokio::time::timeout(timeout * 4, stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let res = String::from_utf8_lossy(&buf[..n]);
+        assert!(res.starts_with("HTTP/1.1 400"), "{res}");
+        assert!(res.contains("Body not received within 200 ms"), "{res}");
+        assert!(closed_within(&mut stream, timeout * 4).await);
+    }
+
+    #[tokio::test]
+    async fn tls_handshake_slots() {
+        use hyper::server::conn::AddrIncoming;
+        use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig};
+        use tokio_rustls::{rustls::ServerName, TlsConnector};
+
+        use crate::tls::TlsIncoming;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = Certificate(cert.serialize_der().unwrap());
+        let server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![der.clone()],
+                PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let mut roots = RootCertStore::empty();
+        roots.add(&der).unwrap();
+        let client_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let tcp = AddrIncoming::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = tcp.local_addr();
+        let limits = Limits {
+            max_connections: Some(1),
+            ..Limits::default()
+        };
+        let tcp = LimitedIncoming::new(tcp, &limits);
+        let mut incoming = TlsIncoming::new(tcp, Arc::new(server_config));
+
+        // A connection which does not handshake takes the only slot
+        let first = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let name = ServerName::try_from("localhost").unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let second = connector.connect(name, stream);
+        tokio::pin!(second);
+        let res = tokio::time::timeout(Duration::from_millis(300), &mut second).await;
+        assert!(res.is_err());
+
+        // The handshake starts once the slot is free
+        drop(first);
+        let res = tokio::time::timeout(Duration::from_secs(2), second).await;
+        assert!(res.unwrap().is_ok());
+        let accept = std::future::poll_fn(|cx| Pin::new(&mut incoming).poll_accept(cx));
+        let conn = tokio::time::timeout(Duration::from_secs(2), accept).await;
+        assert!(matches!(conn, Ok(Some(Ok(_)))));
+    }
+
+    #[tokio::test]
+    async fn slow_and_idle_connections() {
+        let timeout = Duration::from_millis(200);
+        let addr = start(Limits {
+            header_timeout: Some(timeout),
+            idle_timeout: Some(timeout * 2),
+            max_connections: Some(1),
+            ..Limits::default()
+        })
+        .await;
+
+        // Incomplete headers
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"POST / HTTP/1.1\r\n").await.unwrap();
+        assert!(closed_within(&mut stream, timeout * 4).await);
+
+        // The only connection slot is taken until the connection is idle
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let res = request(&mut first, "\"first\"").await;
+        assert!(res.starts_with("HTTP/1.1 200"), "{res}");
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let res = tokio::time::timeout(timeout, request(&mut second, "\"second\"")).await;
+        assert!(res.is_err());
+        assert!(closed_within(&mut first, timeout * 4).await);
+        let mut buf = vec![0; 1024];
+        let n = tokio::time::timeout(timeout * 4, second.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+    }
+}
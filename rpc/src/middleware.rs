@@ -0,0 +1,455 @@
+//! Middlewares
+//!
+//! A middleware wraps the [Handler] of a [Server]: it receives the decoded request
+//! before the handler, and the response after. It may also answer without calling
+//! the handler (eg. to reject a request).
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    codec::Format,
+    server::{Handler, Receiver, RequestError, Server},
+    Request, Response,
+};
+
+/// Server middleware
+///
+/// ```ignore
+/// struct Timing;
+///
+/// #[async_trait]
+/// impl Middleware for Timing {
+///     async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+///     where
+///         R: Receiver,
+///     {
+///         let method = req.method.clone();
+///         let start = Instant::now();
+///         let reply = next.run(req).await;
+///         println!("{method}: {:?} in {:?}", reply.outcome, start.elapsed());
+///         reply.response
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Handles a decoded request
+    ///
+    /// The request is passed on with [Next::run], or answered with the receiver
+    /// of [Next::receiver].
+    async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+    where
+        R: Receiver;
+}
+
+/// Rest of the middleware stack, down to the handler
+pub struct Next<'a, R>
+where
+    R: Receiver,
+{
+    /// Receiver of the request
+    receiver: Decoded<R>,
+    /// Next middleware, or the handler
+    handler: &'a dyn Chain<R>,
+}
+
+impl<'a, R> Next<'a, R>
+where
+    R: Receiver,
+{
+    /// Returns the receiver, to answer the request directly
+    pub fn receiver(&self) -> &R {
+        &self.receiver.receiver
+    }
+
+    /// Passes the request to the next middleware, or to the handler
+    pub async fn run(self, req: Request<Vec<u8>>) -> Reply<R::Response> {
+        let outcome = self.receiver.outcome.clone();
+        let response = self.handler.call(self.receiver, req).await;
+        let outcome = outcome.lock().unwrap().unwrap_or(Outcome::Ok);
+        Reply { response, outcome }
+    }
+}
+
+/// Response of the rest of the middleware stack
+#[derive(Debug)]
+pub struct Reply<T> {
+    /// Encoded response
+    pub response: T,
+    /// Outcome of the request
+    pub outcome: Outcome,
+}
+
+/// Outcome of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The handler returned a value
+    Ok,
+    /// The request failed
+    Err,
+    /// The method is not handled
+    UnknownMethod,
+}
+
+impl Outcome {
+    /// Returns the outcome as a string (eg. `unknown_method`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Err => "err",
+            Outcome::UnknownMethod => "unknown_method",
+        }
+    }
+}
+
+/// Handler wrapped by a middleware
+#[derive(Debug)]
+pub struct Layered<M, H> {
+    /// Middleware
+    middleware: Arc<M>,
+    /// Wrapped handler (or middleware stack)
+    handler: H,
+}
+
+impl<M, H> Clone for Layered<M, H>
+where
+    H: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            middleware: self.middleware.clone(),
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<M, H> Handler for Layered<M, H>
+where
+    M: Middleware,
+    H: Handler + Send + Sync,
+{
+    async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+    where
+        R: Receiver,
+    {
+        let req = match receiver.decode_request::<RequestError>(request).await {
+            Ok(ok) => ok,
+            Err(err) => return receiver.encode_err(err).await,
+        };
+        let next = Next {
+            receiver: Decoded::new(receiver),
+            handler: &self.handler,
+        };
+        self.middleware.handle(req, next).await
+    }
+}
+
+impl<H, R> Server<H, R>
+where
+    H: Handler,
+    R: Receiver,
+{
+    /// Wraps the handler with a middleware
+    ///
+    /// The middleware added last runs first: it wraps the handler and the
+    /// middlewares added before.
+    pub fn layer<M>(self, middleware: M) -> Server<Layered<M, H>, R>
+    where
+        M: Middleware,
+        H: Send + Sync,
+    {
+        Server {
+            receiver: self.receiver,
+            handler: Layered {
+                middleware: Arc::new(middleware),
+                handler: self.handler,
+            },
+        }
+    }
+}
+
+/// Handler called with a decoded request (object safe)
+trait Chain<R>: Send + Sync
+where
+    R: Receiver,
+{
+    /// Handles a decoded request
+    fn call<'a>(
+        &'a self,
+        receiver: Decoded<R>,
+        req: Request<Vec<u8>>,
+    ) -> Pin<Box<dyn Future<Output = R::Response> + Send + 'a>>
+    where
+        R: 'a;
+}
+
+impl<R, H> Chain<R> for H
+where
+    R: Receiver,
+    H: Handler + Send + Sync,
+{
+    fn call<'a>(
+        &'a self,
+        receiver: Decoded<R>,
+        req: Request<Vec<u8>>,
+    ) -> Pin<Box<dyn Future<Output = R::Response> + Send + 'a>>
+    where
+        R: 'a,
+    {
+        self.handle(receiver, req)
+    }
+}
+
+/// Receiver of an already decoded request
+///
+/// The encoding is delegated to the transport receiver, and its outcome recorded.
+struct Decoded<R> {
+    /// Transport receiver
+    receiver: R,
+    /// Outcome of the request, once encoded
+    outcome: Arc<Mutex<Option<Outcome>>>,
+}
+
+impl<R> Decoded<R> {
+    /// Instantiates a new [Decoded] receiver
+    fn new(receiver: R) -> Self {
+        Self {
+            receiver,
+            outcome: Arc::default(),
+        }
+    }
+
+    /// Records the outcome of the request
+    fn set_outcome(&self, outcome: Outcome) {
+        *self.outcome.lock().unwrap() = Some(outcome);
+    }
+}
+
+#[async_trait]
+impl<R> Receiver for Decoded<R>
+where
+    R: Receiver,
+{
+    type Request = Request<Vec<u8>>;
+    type Response = R::Response;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        Ok(req)
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        self.receiver.decode_payload(data).await
+    }
+
+    fn payload_format(&self) -> Option<Format> {
+        self.receiver.payload_format()
+    }
+
+    fn response_format(&self) -> Option<Format> {
+        self.receiver.response_format()
+    }
+
+    async fn encoded_ok(&self, data: Vec<u8>) -> Self::Response {
+        self.set_outcome(Outcome::Ok);
+        self.receiver.encoded_ok(data).await
+    }
+
+    async fn encoded_err(&self, data: Vec<u8>) -> Self::Response {
+        self.set_outcome(Outcome::Err);
+        self.receiver.encoded_err(data).await
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        self.set_outcome(Outcome::Ok);
+        self.receiver.encode_ok(value).await
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.set_outcome(Outcome::Err);
+        self.receiver.encode_err(error).await
+    }
+
+    async fn encode_unknown_method<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.set_outcome(Outcome::UnknownMethod);
+        self.receiver.encode_unknown_method(error).await
+    }
+
+    async fn encode_invalid_payload<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.set_outcome(Outcome::Err);
+        self.receiver.encode_invalid_payload(error).await
+    }
+
+    async fn encode_failure(&self, message: String) -> Self::Response {
+        self.set_outcome(Outcome::Err);
+        self.receiver.encode_failure(message).await
+    }
+
+    async fn encode_response<T, E>(&self, res: Response<T, E>) -> Self::Response
+    where
+        T: Serialize + Send,
+        E: Serialize + Send,
+    {
+        match res {
+            Ok(ok) => self.encode_ok(ok).await,
+            Err(err) => self.encode_err(err).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{local::LocalTransport, Client};
+
+    /// Handler with a public and a private method
+    #[derive(Clone)]
+    struct Echo;
+
+    #[async_trait]
+    impl Handler for Echo {
+        async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+        where
+            R: Receiver,
+        {
+            let req = match receiver.decode_request::<String>(request).await {
+                Ok(ok) => ok,
+                Err(err) => return receiver.encode_err(err).await,
+            };
+            match req.method.as_str() {
+                "public" | "private" => receiver.encode_ok(req.method).await,
+                "fail" => receiver.encode_err("failed".to_string()).await,
+                m => {
+                    let err = format!("Invalid method: {m}");
+                    receiver.encode_unknown_method(err).await
+                }
+            }
+        }
+    }
+
+    /// Records the requests and their outcome
+    #[derive(Default)]
+    struct Log {
+        /// Name of the middleware
+        name: &'static str,
+        /// Log entries
+        entries: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Middleware for Log {
+        async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+        where
+            R: Receiver,
+        {
+            let method = req.method.clone();
+            let name = self.name;
+            self.entries
+                .lock()
+                .unwrap()
+                .push(format!("{name} > {method}"));
+            let reply = next.run(req).await;
+            let entry = format!("{name} < {method}: {:?}", reply.outcome);
+            self.entries.lock().unwrap().push(entry);
+            reply.response
+        }
+    }
+
+    /// Rejects the private methods without a token
+    struct Auth;
+
+    #[async_trait]
+    impl Middleware for Auth {
+        async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+        where
+            R: Receiver,
+        {
+            if req.method == "private" && req.token.is_none() {
+                return next
+                    .receiver()
+                    .encode_err("Missing token".to_string())
+                    .await;
+            }
+            next.run(req).await.response
+        }
+    }
+
+    #[tokio::test]
+    async fn middleware_stack() {
+        let entries = Arc::<Mutex<Vec<String>>>::default();
+        let log = |name| Log {
+            name,
+            entries: entries.clone(),
+        };
+
+        let transport = LocalTransport::new();
+        let server = Server::new(transport.clone(), Echo)
+            .layer(log("inner"))
+            .layer(Auth)
+            .layer(log("outer"));
+        tokio::spawn(server.start());
+        let client = Client::new(transport);
+
+        let call = |method: &str, token: Option<&str>| {
+            let req = Request::new(method, token.map(String::from), ());
+            client.call::<(), String, String>(req)
+        };
+        assert_eq!(call("public", None).await.unwrap(), "public");
+        assert_eq!(call("private", None).await.unwrap_err(), "Missing token");
+        assert_eq!(call("private", Some("token")).await.unwrap(), "private");
+        assert_eq!(call("fail", None).await.unwrap_err(), "failed");
+        assert!(call("other", None).await.is_err());
+
+        let entries = entries.lock().unwrap().clone();
+        assert_eq!(
+            entries,
+            vec![
+                "outer > public",
+                "inner > public",
+                "inner < public: Ok",
+                "outer < public: Ok",
+                // Rejected before the inner middleware
+                "outer > private",
+                "outer < private: Err",
+                "outer > private",
+                "inner > private",
+                "inner < private: Ok",
+                "outer < private: Ok",
+                "outer > fail",
+                "inner > fail",
+                "inner < fail: Err",
+                "outer < fail: Err",
+                "outer > other",
+                "inner > other",
+                "inner < other: UnknownMethod",
+                "outer < other: UnknownMethod",
+            ]
+        );
+    }
+}
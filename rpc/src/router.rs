@@ -0,0 +1,410 @@
+//! Router
+//!
+//! A router dispatches the requests to typed async functions, registered by method name.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    codec::{Codec, Format},
+    server::{Handler, Receiver, RequestError},
+    Credentials, Request,
+};
+
+/// Separator between a namespace and a method name (eg. `secrets.add_secret`)
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+/// Context of a routed request: the request without its payload
+pub type Context = Request<()>;
+
+/// Result of a route
+type RouteResult = Result<Reply, RouteError>;
+
+/// Type-erased route, called with the response format of the receiver
+type Route = Arc<
+    dyn Fn(Context, Payload, Option<Format>) -> Pin<Box<dyn Future<Output = RouteResult> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Payload of a routed request
+enum Payload {
+    /// Encoded with the codec of the receiver, decoded straight into the method input
+    Encoded(Format, Vec<u8>),
+    /// Decoded by the receiver (see [Receiver::decode_payload])
+    Decoded(Value),
+}
+
+impl Payload {
+    /// Decodes the method input
+    fn decode<I>(self) -> Result<I, String>
+    where
+        I: DeserializeOwned,
+    {
+        match self {
+            Payload::Encoded(format, data) => format.decode::<I>(&data),
+            Payload::Decoded(value) => {
+                serde_json::from_value::<I>(value).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Method result or error, encoded for the receiver
+enum Reply {
+    /// Encoded with the codec of the receiver (see [Receiver::response_format])
+    Encoded(Vec<u8>),
+    /// Encoded by the receiver (see [Receiver::encode_ok])
+    Decoded(Value),
+}
+
+impl Reply {
+    /// Encodes a method result or error
+    fn encode<T>(value: &T, format: Option<Format>) -> Result<Self, String>
+    where
+        T: Serialize,
+    {
+        match format {
+            Some(format) => format.encode(value).map(Reply::Encoded),
+            None => serde_json::to_value(value)
+                .map(Reply::Decoded)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Error of a route
+enum RouteError {
+    /// The payload does not match the method input
+    Payload(String),
+    /// The request carries no credentials
+    Unauthorized(String),
+    /// The method failed
+    Method(Reply),
+    /// The method result cannot be encoded
+    Encode(String),
+}
+
+/// Method router
+///
+/// ```ignore
+/// let router = Router::new()
+///     .method("add", |_ctx, (a, b): (i64, i64)| async move { Ok::<_, Error>(a + b) })
+///     .nest("secrets", secrets_router);
+/// let server = Server::new(receiver, router);
+/// ```
+///
+/// The router decodes the payloads, encodes the responses, and answers the unknown
+/// methods. The payloads are decoded straight into the method inputs, and the results
+/// and errors encoded straight from the method outputs, with the codecs of the receiver
+/// (see [Receiver::payload_format] and [Receiver::response_format]). With a receiver
+/// without codecs, they transit through a JSON value.
+#[derive(Clone, Default)]
+pub struct Router {
+    /// Routes, by method name
+    routes: HashMap<String, Route>,
+}
+
+impl std::fmt::Debug for Router {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut methods = self.methods();
+        methods.sort_unstable();
+        f.debug_struct("Router").field("methods", &methods).finish()
+    }
+}
+
+impl Router {
+    /// Instantiates a new [Router]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a method
+    ///
+    /// The function receives the request [Context] and the decoded payload.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method is already registered.
+    pub fn method<F, Fut, I, O, E>(self, name: impl AsRef<str>, f: F) -> Self
+    where
+        F: Fn(Context, I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, E>> + Send + 'static,
+        I: DeserializeOwned + Send + 'static,
+        O: Serialize + 'static,
+        E: Serialize + 'static,
+    {
+        self.route(name, false, move |ctx, payload| Ok(f(ctx, payload)))
+    }
+
+    /// Registers a method which requires authentication
+    ///
+    /// The function also receives the credentials of the request. The requests
+    /// without credentials are rejected with an `unauthorized` error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method is already registered.
+    pub fn authenticated<F, Fut, I, O, E>(self, name: impl AsRef<str>, f: F) -> Self
+    where
+        F: Fn(Context, Credentials, I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, E>> + Send + 'static,
+        I: DeserializeOwned + Send + 'static,
+        O: Serialize + 'static,
+        E: Serialize + 'static,
+    {
+        self.route(name, true, move |ctx, payload| match ctx.credentials() {
+            Some(auth) => Ok(f(ctx, auth, payload)),
+            None => Err(RouteError::Unauthorized("Missing credentials".to_string())),
+        })
+    }
+
+    /// Registers a route, which calls the method future returned by `f`
+    ///
+    /// The credentials of an authenticated route are checked before its payload.
+    fn route<F, Fut, I, O, E>(mut self, name: impl AsRef<str>, auth: bool, f: F) -> Self
+    where
+        F: Fn(Context, I) -> Result<Fut, RouteError> + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, E>> + Send + 'static,
+        I: DeserializeOwned + Send + 'static,
+        O: Serialize + 'static,
+        E: Serialize + 'static,
+    {
+        let f = Arc::new(f);
+        let route: Route = Arc::new(move |ctx, payload, format| {
+            let f = f.clone();
+            Box::pin(async move {
+                if auth && ctx.credentials().is_none() {
+                    return Err(RouteError::Unauthorized("Missing credentials".to_string()));
+                }
+                let input = payload
+                    .decode::<I>()
+                    .map_err(|err| RouteError::Payload(format!("Invalid data: {err}")))?;
+                match f(ctx, input)?.await {
+                    Ok(ok) => Reply::encode(&ok, format)
+                        .map_err(|err| RouteError::Encode(format!("Cannot encode value: {err}"))),
+                    Err(err) => match Reply::encode(&err, format) {
+                        Ok(err) => Err(RouteError::Method(err)),
+                        Err(err) => Err(RouteError::Encode(format!("Cannot encode error: {err}"))),
+                    },
+                }
+            })
+        });
+        self.insert(name.as_ref().to_string(), route);
+        self
+    }
+
+    /// Mounts the methods of a router under a namespace (`<namespace>.<method>`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if a method is already registered.
+    pub fn nest(mut self, namespace: impl AsRef<str>, router: Router) -> Self {
+        for (name, route) in router.routes {
+            let name = format!("{}{NAMESPACE_SEPARATOR}{name}", namespace.as_ref());
+            self.insert(name, route);
+        }
+        self
+    }
+
+    /// Mounts the methods of a router without a namespace
+    ///
+    /// # Panics
+    ///
+    /// Panics if a method is already registered.
+    pub fn merge(mut self, router: Router) -> Self {
+        for (name, route) in router.routes {
+            self.insert(name, route);
+        }
+        self
+    }
+
+    /// Returns the registered method names
+    pub fn methods(&self) -> Vec<&str> {
+        self.routes.keys().map(String::as_str).collect()
+    }
+
+    /// Inserts a route
+    fn insert(&mut self, name: String, route: Route) {
+        if self.routes.insert(name.clone(), route).is_some() {
+            panic!("Method already registered: {name}");
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for Router {
+    async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+    where
+        R: Receiver,
+    {
+        let req = match receiver.decode_request::<RequestError>(request).await {
+            Ok(ok) => ok,
+            Err(err) => return receiver.encode_err(err).await,
+        };
+        let route = match self.routes.get(&req.method) {
+            Some(route) => route.clone(),
+            None => {
+                let err = RequestError::new("not_found", format!("Invalid method: {}", req.method));
+                return receiver.encode_unknown_method(err).await;
+            }
+        };
+        // An empty payload stands for `()`
+        let payload = if req.data.is_empty() {
+            Payload::Decoded(Value::Null)
+        } else if let Some(format) = receiver.payload_format() {
+            Payload::Encoded(format, req.data)
+        } else {
+            match receiver.decode_payload::<Value, String>(&req.data).await {
+                Ok(ok) => Payload::Decoded(ok),
+                Err(err) => {
+                    let err = RequestError::new("invalid_input", err);
+                    return receiver.encode_invalid_payload(err).await;
+                }
+            }
+        };
+
+        let ctx = Request {
+            id: req.id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            certificate: req.certificate,
+            data: (),
+        };
+        match route(ctx, payload, receiver.response_format()).await {
+            Ok(Reply::Encoded(data)) => receiver.encoded_ok(data).await,
+            Ok(Reply::Decoded(value)) => receiver.encode_ok(value).await,
+            Err(RouteError::Payload(err)) => {
+                let err = RequestError::new("invalid_input", err);
+                receiver.encode_invalid_payload(err).await
+            }
+            Err(RouteError::Unauthorized(err)) => {
+                receiver
+                    .encode_err(RequestError::new("unauthorized", err))
+                    .await
+            }
+            Err(RouteError::Method(Reply::Encoded(data))) => receiver.encoded_err(data).await,
+            Err(RouteError::Method(Reply::Decoded(err))) => receiver.encode_err(err).await,
+            Err(RouteError::Encode(err)) => receiver.encode_failure(err).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Format, local::LocalTransport, Client, Server};
+
+    /// Returns a router with a nested namespace
+    fn router() -> Router {
+        let math = Router::new()
+            .method("add", |_ctx, (a, b): (i64, i64)| async move {
+                Ok::<_, String>(a + b)
+            })
+            .method("div", |_ctx, (a, b): (i64, i64)| async move {
+                a.checked_div(b)
+                    .ok_or_else(|| "Division by zero".to_string())
+            });
+        Router::new()
+            .method("whoami", |ctx: Context, ()| async move {
+                ctx.token.ok_or_else(|| "Missing token".to_string())
+            })
+            .authenticated("secret", |_ctx, _auth, ()| async {
+                Ok::<_, String>("secret")
+            })
+            .nest("math", math)
+    }
+
+    #[tokio::test]
+    async fn router_dispatch() {
+        for format in Format::ALL {
+            let transport = LocalTransport::new().format(format);
+            tokio::spawn(Server::new(transport.clone(), router()).start());
+            let client = Client::new(transport);
+
+            let req = Request::new("math.add", None, (1, 2));
+            assert_eq!(client.call::<_, i64, String>(req).await, Ok(3));
+            let req = Request::new("math.div", None, (1, 0));
+            let err = client.call::<_, i64, String>(req).await.unwrap_err();
+            assert_eq!(err, "Division by zero");
+
+            let req = Request::new("whoami", Some("token".to_string()), ());
+            assert_eq!(
+                client.call::<_, String, String>(req).await.unwrap(),
+                "token"
+            );
+
+            // Errors of the router
+            #[derive(Debug, serde::Deserialize)]
+            struct Error {
+                kind: Option<String>,
+                message: String,
+            }
+            impl From<String> for Error {
+                fn from(message: String) -> Self {
+                    Self {
+                        kind: None,
+                        message,
+                    }
+                }
+            }
+            let req = Request::new("add", None, (1, 2));
+            let err = client.call::<_, i64, Error>(req).await.unwrap_err();
+            assert_eq!(err.kind.as_deref(), Some("not_found"));
+            assert_eq!(err.message, "Invalid method: add");
+            let req = Request::new("math.add", None, "one");
+            let err = client.call::<_, i64, Error>(req).await.unwrap_err();
+            assert_eq!(err.kind.as_deref(), Some("invalid_input"));
+            assert!(err.message.starts_with("Invalid data"), "{}", err.message);
+            let req = Request::new("secret", None, ());
+            let err = client.call::<_, String, Error>(req).await.unwrap_err();
+            assert_eq!(err.kind.as_deref(), Some("unauthorized"));
+        }
+    }
+
+    #[tokio::test]
+    async fn router_codec_input() {
+        use std::collections::BTreeMap;
+
+        // A CBOR map with integer keys has no JSON equivalent
+        let router = Router::new().method("sum", |_ctx, map: BTreeMap<i64, i64>| async move {
+            Ok::<_, String>(map.values().sum::<i64>())
+        });
+        let transport = LocalTransport::new().format(Format::Cbor);
+        tokio::spawn(Server::new(transport.clone(), router).start());
+        let client = Client::new(transport);
+
+        let req = Request::new("sum", None, BTreeMap::from([(1, 2), (3, 4)]));
+        assert_eq!(client.call::<_, i64, String>(req).await, Ok(6));
+    }
+
+    #[tokio::test]
+    async fn router_codec_output() {
+        use std::collections::BTreeMap;
+
+        // A map with array keys has no JSON equivalent
+        let router = Router::new().method("pairs", |_ctx, ()| async {
+            Ok::<_, String>(BTreeMap::from([((1, 2), 3)]))
+        });
+        let transport = LocalTransport::new().format(Format::Cbor);
+        tokio::spawn(Server::new(transport.clone(), router).start());
+        let client = Client::new(transport);
+
+        let req = Request::new("pairs", None, ());
+        let res = client
+            .call::<_, BTreeMap<(i64, i64), i64>, String>(req)
+            .await;
+        assert_eq!(res, Ok(BTreeMap::from([((1, 2), 3)])));
+    }
+
+    #[test]
+    #[should_panic(expected = "Method already registered: math.add")]
+    fn duplicate_method() {
+        let math = Router::new().method("add", |_ctx, ()| async { Ok::<_, String>(()) });
+        let _ = router().nest("math", math);
+    }
+}
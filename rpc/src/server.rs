@@ -1,9 +1,12 @@
 //! RPC server
 
 use async_trait::async_trait;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{Request, Response};
+use crate::{
+    codec::{Codec, Format},
+    Request, Response,
+};
 
 /// RPC service handler
 #[async_trait]
@@ -26,7 +29,7 @@ pub trait Receiver: Send + Sync {
     /// Decodes a request
     async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
     where
-        E: From<String>;
+        E: From<RequestError>;
 
     /// Decodes the request payload
     async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
@@ -34,16 +37,79 @@ pub trait Receiver: Send + Sync {
         T: DeserializeOwned,
         E: From<String>;
 
+    /// Returns the codec of the request payloads
+    ///
+    /// A [Router](crate::Router) decodes the payloads with it, straight into the method
+    /// inputs. By default, the payloads are decoded with [Receiver::decode_payload] instead.
+    fn payload_format(&self) -> Option<Format> {
+        None
+    }
+
+    /// Returns the codec of the responses
+    ///
+    /// A [Router](crate::Router) encodes the method results and errors with it, and answers
+    /// them with [Receiver::encoded_ok] and [Receiver::encoded_err]. By default, they are
+    /// answered with [Receiver::encode_ok] and [Receiver::encode_err] instead.
+    fn response_format(&self) -> Option<Format> {
+        None
+    }
+
+    /// Answers a value encoded with the [Receiver::response_format]
+    ///
+    /// By default, there is no response format, and this is answered with [Receiver::encode_failure].
+    async fn encoded_ok(&self, _data: Vec<u8>) -> Self::Response {
+        self.encode_failure("No response format".to_string()).await
+    }
+
+    /// Answers an error encoded with the [Receiver::response_format]
+    ///
+    /// By default, there is no response format, and this is answered with [Receiver::encode_failure].
+    async fn encoded_err(&self, _data: Vec<u8>) -> Self::Response {
+        self.encode_failure("No response format".to_string()).await
+    }
+
     /// Encodes a value
+    ///
+    /// A value which cannot be encoded is answered with [Receiver::encode_failure].
     async fn encode_ok<T>(&self, value: T) -> Self::Response
     where
         T: Serialize + Send;
 
     /// Encodes an error
+    ///
+    /// An error which cannot be encoded is answered with [Receiver::encode_failure].
     async fn encode_err<E>(&self, error: E) -> Self::Response
     where
         E: Serialize + Send;
 
+    /// Encodes the failure of the server to answer (eg. a value which cannot be encoded)
+    ///
+    /// By default, this is encoded as an `internal` error (`500` over HTTP).
+    async fn encode_failure(&self, message: String) -> Self::Response {
+        self.encode_err(RequestError::new("internal", message))
+            .await
+    }
+
+    /// Encodes the error of an unknown method
+    ///
+    /// By default, this is encoded as any other error.
+    async fn encode_unknown_method<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.encode_err(error).await
+    }
+
+    /// Encodes the error of a payload which does not match the method
+    ///
+    /// By default, this is encoded as any other error.
+    async fn encode_invalid_payload<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.encode_err(error).await
+    }
+
     /// Encodes a service response
     async fn encode_response<T, E>(&self, res: Response<T, E>) -> Self::Response
     where
@@ -57,6 +123,88 @@ pub trait Receiver: Send + Sync {
     }
 }
 
+/// Status codes of the error kinds
+///
+/// An error which serializes with a `kind` field (eg. `{ "kind": "not_found", ... }`)
+/// is answered with the status of its kind by the transports which have one (eg. HTTP).
+/// The other errors are answered with `400 Bad Request`.
+pub const ERROR_STATUS: &[(&str, u16)] = &[
+    ("invalid_input", 400),
+    ("payload_too_large", 413),
+    ("unauthorized", 401),
+    ("forbidden", 403),
+    ("not_found", 404),
+    ("conflict", 409),
+    ("incompatible_version", 400),
+    ("internal", 500),
+];
+
+/// Status of an error without a known kind
+const DEFAULT_ERROR_STATUS: u16 = 400;
+
+/// Returns the status code of an error, from its `kind` field
+pub(crate) fn error_status<E>(error: &E) -> u16
+where
+    E: Serialize + ?Sized,
+{
+    let value = match serde_json::to_value(error) {
+        Ok(ok) => ok,
+        Err(_) => return DEFAULT_ERROR_STATUS,
+    };
+    kind_status(value.get("kind").and_then(|kind| kind.as_str()))
+}
+
+/// Returns the status code of an encoded error, from its `kind` field
+///
+/// Only the kind is decoded, the other fields are skipped.
+pub(crate) fn encoded_error_status(format: Format, data: &[u8]) -> u16 {
+    /// Kind of an error
+    #[derive(Deserialize)]
+    struct Kind {
+        /// Kind
+        kind: Option<String>,
+    }
+
+    match format.decode::<Kind>(data) {
+        Ok(error) => kind_status(error.kind.as_deref()),
+        Err(_) => DEFAULT_ERROR_STATUS,
+    }
+}
+
+/// Returns the status code of an error kind
+fn kind_status(kind: Option<&str>) -> u16 {
+    kind.and_then(|kind| ERROR_STATUS.iter().find(|(k, _)| *k == kind))
+        .map_or(DEFAULT_ERROR_STATUS, |(_, status)| *status)
+}
+
+/// Error of a request rejected before reaching the service
+///
+/// The error is encoded as `{ "kind": "...", "message": "..." }`, like the service errors,
+/// so that a client decodes its kind. It converts to a [String] with its message.
+#[derive(Debug, Serialize)]
+pub struct RequestError {
+    /// Kind (see [ERROR_STATUS])
+    kind: &'static str,
+    /// Message
+    message: String,
+}
+
+impl RequestError {
+    /// Instantiates a new [RequestError] of a given kind
+    pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<RequestError> for String {
+    fn from(error: RequestError) -> Self {
+        error.message
+    }
+}
+
 /// RPC server
 #[derive(Debug, Clone)]
 pub struct Server<H, R>
@@ -0,0 +1,15 @@
+//! Test helpers
+
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+
+/// Binds a listener on an ephemeral local port, and returns it with its address
+///
+/// The listener is handed to the server (see [Server::serve](crate::Server)): the
+/// connections wait in its backlog until the server accepts them, without retries.
+pub(crate) async fn listener() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
@@ -0,0 +1,238 @@
+//! TLS
+
+use std::{
+    fs, future, io,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::server::{accept::Accept, conn::AddrStream};
+use rustls::{
+    server::AllowAnyAnonymousOrAuthenticatedClient, Certificate, ClientConfig, PrivateKey,
+    RootCertStore, ServerConfig,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+/// Maximum duration of a TLS handshake, until the connection is queued
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of the queue of established connections
+const ACCEPT_QUEUE: usize = 64;
+
+/// Loads the certificates of a PEM file
+pub fn load_certs(path: &Path) -> io::Result<Vec<Certificate>> {
+    let data = fs::read(path)?;
+    let certs = rustls_pemfile::certs(&mut data.as_slice())?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!(
+            "No certificate found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Loads the first private key of a PEM file
+pub fn load_key(path: &Path) -> io::Result<PrivateKey> {
+    let data = fs::read(path)?;
+    let mut reader = data.as_slice();
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(invalid_data(format!(
+        "No private key found in {}",
+        path.display()
+    )))
+}
+
+/// Returns the server TLS configuration
+///
+/// If `client_ca` is set, the clients may authenticate with a certificate signed by this CA.
+/// Clients without a certificate are still accepted.
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> io::Result<ServerConfig> {
+    let certs = load_certs(cert)?;
+    let key = load_key(key)?;
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(client_ca) => builder.with_client_cert_verifier(
+            AllowAnyAnonymousOrAuthenticatedClient::new(load_roots(client_ca)?),
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(certs, key)
+        .map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Returns the client TLS configuration
+pub fn client_config(roots: RootCertStore) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth()
+}
+
+/// Returns the client TLS configuration, authenticating with a client certificate
+pub fn client_config_with_cert(
+    roots: RootCertStore,
+    cert: &Path,
+    key: &Path,
+) -> io::Result<ClientConfig> {
+    let certs = load_certs(cert)?;
+    let key = load_key(key)?;
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_single_cert(certs, key)
+        .map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Returns the subject common name of a certificate
+pub fn certificate_subject(cert: &Certificate) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(&cert.0).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(|s| s.to_string())
+}
+
+/// Returns the Mozilla root certificates
+pub fn webpki_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    roots
+}
+
+/// Loads the root certificates of a PEM file (eg. a pinned CA)
+pub fn load_roots(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|err| invalid_data(err.to_string()))?;
+    }
+    Ok(roots)
+}
+
+/// Returns an [io::ErrorKind::InvalidData] error
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Subject of the verified client certificate
+///
+/// This is set as a request extension by the receiver.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientCertificate(pub(crate) String);
+
+/// Connection which may be authenticated with a client certificate
+pub(crate) trait PeerCertificate {
+    /// Returns the client certificate, if any
+    fn peer_certificate(&self) -> Option<ClientCertificate>;
+}
+
+impl PeerCertificate for AddrStream {
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
+}
+
+impl<C> PeerCertificate for TlsStream<C> {
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        // The certificates have been verified during the handshake
+        let (_, conn) = self.get_ref();
+        let cert = conn.peer_certificates()?.first()?;
+        certificate_subject(cert).map(ClientCertificate)
+    }
+}
+
+/// Incoming TLS connections
+///
+/// The TLS handshakes are performed concurrently, so that a slow client
+/// does not block the other ones. The connections are accepted from an incoming
+/// stream within the limits (see [LimitedIncoming](crate::limits::LimitedIncoming)),
+/// so that the handshakes hold a connection slot.
+pub(crate) struct TlsIncoming<C> {
+    /// Established connections
+    rx: mpsc::Receiver<TlsStream<C>>,
+}
+
+impl<C> TlsIncoming<C>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    /// Performs the TLS handshakes of incoming connections
+    pub(crate) fn new<I>(mut incoming: I, config: Arc<ServerConfig>) -> Self
+    where
+        I: Accept<Conn = C> + Unpin + Send + 'static,
+        I::Error: Send,
+    {
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, rx) = mpsc::channel(ACCEPT_QUEUE);
+
+        tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = tx.closed() => break,
+                    conn = future::poll_fn(|cx| Pin::new(&mut incoming).poll_accept(cx)) => {
+                        match conn {
+                            Some(Ok(stream)) => stream,
+                            Some(Err(_)) => {
+                                // Eg. too many open files => wait before accepting again
+                                tokio::time::sleep(Duration::from_millis(100)).await;
+                                continue;
+                            }
+                            None => break,
+                        }
+                    }
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    // Failed or stalled handshakes are dropped, with their slot
+                    let _ = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+                        if let Ok(stream) = acceptor.accept(stream).await {
+                            let _ = tx.send(stream).await;
+                        }
+                    })
+                    .await;
+                });
+            }
+        });
+
+        Self { rx }
+    }
+}
+
+impl<C> Accept for TlsIncoming<C> {
+    type Conn = TlsStream<C>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.rx.poll_recv(cx).map(|conn| conn.map(Ok))
+    }
+}
@@ -0,0 +1,122 @@
+//! Request tracing
+
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tracing::{field, Instrument};
+
+use crate::{
+    middleware::{Middleware, Next},
+    server::Receiver,
+    Credentials, Request,
+};
+
+/// Middleware which traces the requests
+///
+/// Each request runs in a `rpc` span, with its method and request ID, and ends
+/// with an event recording its outcome and latency. The payloads, tokens and
+/// errors are never recorded, as they may hold secret values.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trace;
+
+#[async_trait]
+impl Middleware for Trace {
+    async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+    where
+        R: Receiver,
+    {
+        let auth = match req.credentials() {
+            Some(Credentials::Token(_)) => "token",
+            Some(Credentials::Certificate(_)) => "certificate",
+            None => "none",
+        };
+        let span = tracing::info_span!(
+            "rpc",
+            method = %req.method,
+            request_id = field::Empty,
+            auth,
+        );
+        if let Some(id) = &req.id {
+            span.record("request_id", field::display(id));
+        }
+
+        let start = Instant::now();
+        let reply = next.run(req).instrument(span.clone()).await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        tracing::info!(
+            parent: &span,
+            outcome = reply.outcome.as_str(),
+            latency_ms,
+            "request handled"
+        );
+        reply.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{local::LocalTransport, Client, Router, Server};
+
+    /// Log output shared with the subscriber
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn trace_without_payloads() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let router = Router::new()
+            .method("add_secret", |_ctx, _value: String| async {
+                Ok::<_, String>(())
+            })
+            .method("fail", |_ctx, value: String| async move {
+                Err::<(), _>(format!("Cannot store {value}"))
+            });
+        let transport = LocalTransport::new();
+        let server = Server::new(transport.clone(), router).layer(Trace);
+        tokio::spawn(server.start());
+        let client = Client::new(transport);
+
+        let mut req = Request::new("add_secret", Some("t0ken".to_string()), "s3cr3t");
+        req.id = Some("req-1".to_string());
+        client.call::<_, (), String>(req).await.unwrap();
+        let req = Request::new("fail", None, "s3cr3t");
+        client.call::<_, (), String>(req).await.unwrap_err();
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines = logs.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{logs}");
+        assert!(lines[0].contains("method=add_secret"), "{logs}");
+        assert!(lines[0].contains("request_id=req-1"), "{logs}");
+        assert!(lines[0].contains("auth=\"token\""), "{logs}");
+        assert!(lines[0].contains("outcome=\"ok\""), "{logs}");
+        assert!(lines[0].contains("latency_ms="), "{logs}");
+        assert!(lines[1].contains("method=fail"), "{logs}");
+        assert!(lines[1].contains("outcome=\"err\""), "{logs}");
+        assert!(!logs.contains("s3cr3t"), "{logs}");
+        assert!(!logs.contains("t0ken"), "{logs}");
+    }
+}
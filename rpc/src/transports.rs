@@ -1,3 +1,8 @@
 //! Transports
 
-pub mod json;
+pub mod http;
+pub mod jsonrpc;
+pub mod local;
+#[cfg(unix)]
+pub mod unix;
+pub mod ws;
@@ -0,0 +1,1354 @@
+//! HTTP transport
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    error::Error as StdError,
+    fmt,
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures_util::future::Either;
+use hyper::{
+    body::{Bytes, HttpBody},
+    client::HttpConnector,
+    header,
+    header::HeaderValue,
+    server::{accept::Accept, conn::AddrIncoming},
+    service::service_fn,
+    HeaderMap, Uri,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rustls::{ClientConfig, ServerConfig};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{mpsc, watch},
+    task::JoinSet,
+};
+
+use crate::{
+    client::Sender,
+    codec::{Codec, Format},
+    limits::{LimitedIncoming, Limits},
+    new_request_id,
+    server::{encoded_error_status, error_status, Handler, Receiver, RequestError, Server},
+    tls::{self, ClientCertificate, PeerCertificate, TlsIncoming},
+    Request, Response,
+};
+
+/// HTTP transport
+///
+/// The payloads are encoded in JSON or CBOR (see [HttpTransportBuilder::format]). As a
+/// [Sender], the transport sends its format as `Content-Type`, and accepts it or JSON.
+/// As a [Receiver], it decodes the `Content-Type` format (JSON without one), and answers
+/// in the preferred `Accept` format, else in the request format.
+///
+/// The transport speaks HTTP, or HTTPS if TLS is configured:
+/// - as a [Receiver], with [HttpTransportBuilder::server_cert]
+/// - as a [Sender], with a `https://` URL
+///
+/// Over TLS, the clients may authenticate with a certificate
+/// (see [HttpTransportBuilder::client_ca] and [HttpTransportBuilder::client_cert]).
+///
+/// The errors are answered with the HTTP status of their kind (see [ERROR_STATUS](crate::ERROR_STATUS)).
+///
+/// As a [Receiver], the transport enforces the [Limits] set with [HttpTransportBuilder::limits].
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    /// Server URL
+    url: Option<Uri>,
+    /// HTTP client (pooled connections)
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    /// Client TLS configuration
+    client_tls: Arc<ClientConfig>,
+    /// Request timeout
+    timeout: Option<Duration>,
+    /// Server TLS configuration
+    tls: Option<Arc<ServerConfig>>,
+    /// Format of the encoded payloads
+    encoding: Format,
+    /// Format of the decoded payloads, [None] if not supported
+    decoding: Option<Format>,
+    /// Server limits
+    limits: Limits,
+    /// ID of the request being handled, echoed in the response
+    request_id: Option<String>,
+    /// HTTP routes, served beside the RPC requests
+    routes: Routes,
+}
+
+impl HttpTransport {
+    /// RPC method
+    const HEADER_METHOD: &str = "X-RPC-METHOD";
+
+    /// Request ID
+    pub(crate) const HEADER_REQUEST_ID: &'static str = "X-RPC-REQUEST-ID";
+
+    /// API version of the client
+    pub(crate) const HEADER_VERSION: &'static str = "X-RPC-VERSION";
+
+    /// Instantiates a new [HttpTransport]
+    ///
+    /// To send requests, the server URL must be set via [HttpTransport::builder].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a [HttpTransportBuilder]
+    pub fn builder() -> HttpTransportBuilder {
+        HttpTransportBuilder::default()
+    }
+
+    /// Returns the server URL
+    pub(crate) fn url(&self) -> Option<&Uri> {
+        self.url.as_ref()
+    }
+
+    /// Returns the server TLS configuration
+    pub(crate) fn tls(&self) -> Option<Arc<ServerConfig>> {
+        self.tls.clone()
+    }
+
+    /// Returns the client TLS configuration
+    pub(crate) fn client_tls(&self) -> Arc<ClientConfig> {
+        self.client_tls.clone()
+    }
+
+    /// Returns the request timeout
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns the server limits
+    pub(crate) fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Returns the `Accept` header of the requests
+    ///
+    /// The request format is preferred, JSON is accepted as a fallback.
+    fn accept(&self) -> String {
+        match self.encoding {
+            Format::Json => Format::Json.content_type().to_string(),
+            format => format!(
+                "{}, {};q=0.5",
+                format.content_type(),
+                Format::Json.content_type()
+            ),
+        }
+    }
+
+    /// Returns the HTTP client
+    fn http_client(
+        tls_config: Arc<ClientConfig>,
+        connect_timeout: Option<Duration>,
+    ) -> hyper::Client<HttpsConnector<HttpConnector>> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(connect_timeout);
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config((*tls_config).clone())
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(http);
+        hyper::Client::builder().build(connector)
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        let client_tls = Arc::new(tls::client_config(tls::webpki_roots()));
+        Self {
+            url: None,
+            client: Self::http_client(client_tls.clone(), None),
+            client_tls,
+            timeout: None,
+            tls: None,
+            encoding: Format::default(),
+            decoding: Some(Format::default()),
+            limits: Limits::default(),
+            request_id: None,
+            routes: Routes::default(),
+        }
+    }
+}
+
+/// [HttpTransport] builder
+#[derive(Debug, Clone, Default)]
+pub struct HttpTransportBuilder {
+    /// Server URL
+    url: Option<String>,
+    /// Request timeout
+    timeout: Option<Duration>,
+    /// Connect timeout
+    connect_timeout: Option<Duration>,
+    /// Pinned CA certificate
+    ca_cert: Option<PathBuf>,
+    /// Client certificate and private key
+    client_cert: Option<(PathBuf, PathBuf)>,
+    /// Server certificate and private key
+    server_cert: Option<(PathBuf, PathBuf)>,
+    /// CA certificate used to verify the client certificates
+    client_ca: Option<PathBuf>,
+    /// Format of the requests
+    format: Format,
+    /// Server limits
+    limits: Limits,
+    /// HTTP routes
+    routes: Routes,
+}
+
+impl HttpTransportBuilder {
+    /// Sets the server URL (eg. `http://localhost:6666`)
+    pub fn url(mut self, url: impl AsRef<str>) -> Self {
+        self.url = Some(url.as_ref().to_string());
+        self
+    }
+
+    /// Sets the request timeout
+    ///
+    /// The timeout covers the whole request, until the response headers are received.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the connect timeout
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Pins the CA certificate (PEM file) used to verify the server
+    ///
+    /// By default, the server certificate is verified against the Mozilla root certificates.
+    pub fn ca_cert(mut self, path: impl AsRef<Path>) -> Self {
+        self.ca_cert = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the client certificate and private key (PEM files) to authenticate with
+    pub fn client_cert(mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Self {
+        self.client_cert = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Sets the server certificate and private key (PEM files) to serve over TLS
+    pub fn server_cert(mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Self {
+        self.server_cert = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Accepts the client certificates signed by a CA (PEM file)
+    ///
+    /// The subject of a verified certificate is set on [Request::certificate].
+    pub fn client_ca(mut self, path: impl AsRef<Path>) -> Self {
+        self.client_ca = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the format of the requests (JSON by default)
+    ///
+    /// The server is asked to answer in the same format.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the limits of the server (see [Limits::default])
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Serves the `GET` requests of a path with a function, instead of the handler
+    ///
+    /// The routes answer plain HTTP requests beside the RPC endpoint (eg. `/metrics`),
+    /// which only accepts `POST` requests. A route replaces the previous one of its path.
+    pub fn route<F, Fut>(mut self, path: impl AsRef<str>, f: F) -> Self
+    where
+        F: Fn(hyper::Request<hyper::Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+    {
+        self.routes.insert(path, f);
+        self
+    }
+
+    /// Sets the HTTP routes
+    pub(crate) fn routes(mut self, routes: Routes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Builds the [HttpTransport]
+    pub fn build<E>(self) -> Result<HttpTransport, E>
+    where
+        E: From<String>,
+    {
+        let url = match self.url {
+            Some(url) => match url.parse::<Uri>() {
+                Ok(ok) => Some(ok),
+                Err(err) => {
+                    return Err(E::from(format!("Invalid URL '{url}': {err}")));
+                }
+            },
+            None => None,
+        };
+
+        let roots = match &self.ca_cert {
+            Some(ca_cert) => match tls::load_roots(ca_cert) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    return Err(E::from(format!("Invalid CA certificate: {err}")));
+                }
+            },
+            None => tls::webpki_roots(),
+        };
+
+        let client_tls = match &self.client_cert {
+            Some((cert, key)) => match tls::client_config_with_cert(roots, cert, key) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    return Err(E::from(format!("Invalid client certificate: {err}")));
+                }
+            },
+            None => tls::client_config(roots),
+        };
+        let client_tls = Arc::new(client_tls);
+
+        let tls = match &self.server_cert {
+            Some((cert, key)) => match tls::server_config(cert, key, self.client_ca.as_deref()) {
+                Ok(ok) => Some(Arc::new(ok)),
+                Err(err) => {
+                    return Err(E::from(format!("Invalid server certificate: {err}")));
+                }
+            },
+            None => None,
+        };
+
+        Ok(HttpTransport {
+            url,
+            client: HttpTransport::http_client(client_tls.clone(), self.connect_timeout),
+            client_tls,
+            timeout: self.timeout,
+            tls,
+            encoding: self.format,
+            decoding: Some(self.format),
+            limits: self.limits,
+            request_id: None,
+            routes: self.routes,
+        })
+    }
+}
+
+impl<H> Server<H, HttpTransport>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+{
+    /// Starts the server
+    ///
+    /// The server listens over TLS if the receiver has a server certificate.
+    pub async fn start(self, addr: &SocketAddr) -> io::Result<()> {
+        self.start_with_shutdown(addr, future::pending()).await
+    }
+
+    /// Starts the server, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops accepting connections and returns once the
+    /// in-flight requests are answered.
+    pub async fn start_with_shutdown<S>(self, addr: &SocketAddr, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_with_shutdown(listener, signal).await
+    }
+
+    /// Serves the connections of a bound listener (eg. on port 0)
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        self.serve_with_shutdown(listener, future::pending()).await
+    }
+
+    /// Serves the connections of a bound listener, until the shutdown signal resolves
+    pub async fn serve_with_shutdown<S>(self, listener: TcpListener, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let http = self.receiver.clone();
+        let handler = self.handler;
+        let receiver = self.receiver;
+        listen(listener, &http, signal, move |req| {
+            let handler = handler.clone();
+            let receiver = receiver.negotiate(req.headers());
+            async move { handler.handle(receiver, req).await }
+        })
+        .await
+    }
+}
+
+/// Serves the incoming connections over HTTP, until the shutdown signal resolves
+pub(crate) async fn serve<H, R, I, S>(
+    handler: H,
+    receiver: R,
+    incoming: I,
+    http: &HttpTransport,
+    signal: S,
+) -> io::Result<()>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+    R: Receiver<Request = hyper::Request<hyper::Body>, Response = hyper::Response<hyper::Body>>
+        + Negotiate
+        + 'static,
+    I: Accept + Unpin,
+    I::Conn: PeerCertificate + AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S: Future<Output = ()>,
+{
+    let incoming = LimitedIncoming::new(incoming, &http.limits);
+    serve_fn(incoming, http, signal, move |req| {
+        let handler = handler.clone();
+        let receiver = receiver.negotiate(req.headers());
+        async move { handler.handle(receiver, req).await }
+    })
+    .await
+}
+
+/// Accepts the connections of a TCP listener, over TLS if configured, and serves the requests
+/// with a function
+///
+/// The TLS configuration, limits and routes are those of the HTTP transport.
+pub(crate) async fn listen<S, F, Fut>(
+    listener: TcpListener,
+    http: &HttpTransport,
+    signal: S,
+    f: F,
+) -> io::Result<()>
+where
+    S: Future<Output = ()>,
+    F: Fn(hyper::Request<hyper::Body>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+{
+    // The TLS handshakes hold a connection slot
+    let incoming = AddrIncoming::from_listener(listener).map_err(io_error)?;
+    let incoming = LimitedIncoming::new(incoming, &http.limits);
+    match http.tls() {
+        Some(tls) => serve_fn(TlsIncoming::new(incoming, tls), http, signal, f).await,
+        None => serve_fn(incoming, http, signal, f).await,
+    }
+}
+
+/// Serves the incoming connections with a function
+///
+/// The connections must be accepted within the limits (see [LimitedIncoming]).
+/// The requests matching a route of the HTTP transport are served by the route.
+/// The verified client certificate and the [Tasks] of the server are set as request
+/// extensions. Once the shutdown signal resolves, the server stops accepting
+/// connections and drains the open ones.
+///
+/// The connections are tasks of the returned future: dropping it (eg. when a
+/// shutdown deadline is exceeded) aborts them.
+pub(crate) async fn serve_fn<I, S, F, Fut>(
+    mut incoming: I,
+    http: &HttpTransport,
+    signal: S,
+    f: F,
+) -> io::Result<()>
+where
+    S: Future<Output = ()>,
+    I: Accept + Unpin,
+    I::Conn: PeerCertificate + AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I::Error: Into<Box<dyn StdError + Send + Sync>>,
+    F: Fn(hyper::Request<hyper::Body>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+{
+    let limits = http.limits;
+    let mut server = hyper::server::conn::Http::new();
+    if let Some(timeout) = limits.header_timeout {
+        server.http1_header_read_timeout(timeout);
+    }
+
+    let (shutdown, _) = watch::channel(());
+    let (spawner, mut spawned) = mpsc::unbounded_channel();
+    let handle = Tasks {
+        spawner,
+        shutdown: shutdown.subscribe(),
+    };
+    let mut tasks = JoinSet::new();
+    tokio::pin!(signal);
+    loop {
+        tokio::select! {
+            conn = future::poll_fn(|cx| Pin::new(&mut incoming).poll_accept(cx)) => {
+                let conn = match conn {
+                    Some(Ok(conn)) => conn,
+                    Some(Err(err)) => return Err(io::Error::other(err)),
+                    None => break,
+                };
+                let service = service(&conn, &http.routes, &handle, f.clone());
+                let conn = server.serve_connection(conn, service).with_upgrades();
+                let mut shutdown = shutdown.subscribe();
+                tasks.spawn(async move {
+                    tokio::pin!(conn);
+                    let res = tokio::select! {
+                        res = conn.as_mut() => res,
+                        _ = shutdown.changed() => {
+                            conn.as_mut().graceful_shutdown();
+                            conn.await
+                        }
+                    };
+                    if let Err(err) = res {
+                        tracing::debug!("connection error: {err}");
+                    }
+                });
+            }
+            Some(task) = spawned.recv() => {
+                tasks.spawn(task);
+            }
+            Some(_) = tasks.join_next() => {}
+            _ = &mut signal => break,
+        }
+    }
+
+    // Stop listening, and drain the connections
+    drop(incoming);
+    let _ = shutdown.send(());
+    loop {
+        tokio::select! {
+            Some(task) = spawned.recv() => {
+                tasks.spawn(task);
+            }
+            res = tasks.join_next() => {
+                if res.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the service of a connection
+fn service<C, F, Fut>(
+    conn: &C,
+    routes: &Routes,
+    tasks: &Tasks,
+    f: F,
+) -> impl hyper::service::Service<
+    hyper::Request<hyper::Body>,
+    Response = hyper::Response<hyper::Body>,
+    Error = Infallible,
+    Future = impl Future<Output = Result<hyper::Response<hyper::Body>, Infallible>> + Send,
+> + Send
+where
+    C: PeerCertificate,
+    F: Fn(hyper::Request<hyper::Body>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+{
+    let certificate = conn.peer_certificate();
+    let routes = routes.clone();
+    let tasks = tasks.clone();
+    service_fn(move |mut req: hyper::Request<hyper::Body>| {
+        if let Some(certificate) = certificate.clone() {
+            req.extensions_mut().insert(certificate);
+        }
+        req.extensions_mut().insert(tasks.clone());
+
+        let res = match routes.get(&req) {
+            Some(route) => Either::Left(route(req)),
+            None => Either::Right(f(req)),
+        };
+        async move { Ok::<_, Infallible>(res.await) }
+    })
+}
+
+/// Task of a server
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Tasks of a server, beside its connections (eg. the upgraded connections)
+///
+/// The tasks are drained with the connections on shutdown, and aborted with them.
+#[derive(Debug, Clone)]
+pub(crate) struct Tasks {
+    /// Spawns the tasks on the server
+    spawner: mpsc::UnboundedSender<Task>,
+    /// Shutdown of the server
+    shutdown: watch::Receiver<()>,
+}
+
+impl Tasks {
+    /// Spawns a task on the server
+    pub(crate) fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let _ = self.spawner.send(Box::pin(task));
+    }
+
+    /// Resolves once the server shuts down
+    pub(crate) fn shutdown(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut shutdown = self.shutdown.clone();
+        async move {
+            let _ = shutdown.changed().await;
+        }
+    }
+}
+
+/// HTTP route
+type Route = Arc<
+    dyn Fn(
+            hyper::Request<hyper::Body>,
+        ) -> Pin<Box<dyn Future<Output = hyper::Response<hyper::Body>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// HTTP routes, by path
+#[derive(Clone, Default)]
+pub(crate) struct Routes(Arc<HashMap<String, Route>>);
+
+impl Routes {
+    /// Inserts a route, which replaces the previous one of its path
+    pub(crate) fn insert<F, Fut>(&mut self, path: impl AsRef<str>, f: F)
+    where
+        F: Fn(hyper::Request<hyper::Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+    {
+        let route: Route = Arc::new(move |req| Box::pin(f(req)));
+        Arc::make_mut(&mut self.0).insert(path.as_ref().to_string(), route);
+    }
+
+    /// Returns the route of a `GET` or `HEAD` request
+    fn get(&self, req: &hyper::Request<hyper::Body>) -> Option<&Route> {
+        if req.method() != hyper::Method::GET && req.method() != hyper::Method::HEAD {
+            return None;
+        }
+        self.0.get(req.uri().path())
+    }
+}
+
+impl fmt::Debug for Routes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut paths = self.0.keys().collect::<Vec<_>>();
+        paths.sort_unstable();
+        f.debug_tuple("Routes").field(&paths).finish()
+    }
+}
+
+/// Maximum length of a request ID sent by a client
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Returns the request ID of the headers, or a new one if missing or invalid
+///
+/// A request ID is at most 128 visible ASCII characters, as it ends up in the logs.
+pub(crate) fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(HttpTransport::HEADER_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(String::from)
+        .unwrap_or_else(new_request_id)
+}
+
+/// Sets the request ID header of a response
+pub(crate) fn set_request_id(res: &mut hyper::Response<hyper::Body>, id: &str) {
+    if let Ok(value) = HeaderValue::from_str(id) {
+        res.headers_mut()
+            .insert(HttpTransport::HEADER_REQUEST_ID, value);
+    }
+}
+
+/// Returns the API version of the headers, [None] if missing
+pub(crate) fn api_version(headers: &HeaderMap) -> Result<Option<u32>, String> {
+    match headers.get(HttpTransport::HEADER_VERSION) {
+        Some(value) => match value.to_str().ok().and_then(|v| v.parse().ok()) {
+            Some(version) => Ok(Some(version)),
+            None => Err(format!("Invalid version header: {value:?}")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the bearer token of the `Authorization` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Result<Option<String>, String> {
+    match headers.get(header::AUTHORIZATION) {
+        Some(m) => match m.to_str() {
+            Ok(ok) => match ok.strip_prefix("Bearer ") {
+                Some(s) => Ok(Some(s.to_owned())),
+                None => Err(format!("Invalid auth header: {ok}")),
+            },
+            Err(err) => Err(format!("Invalid auth header: {err}")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Error of a request body
+#[derive(Debug)]
+pub(crate) enum BodyError {
+    /// The body is larger than the maximum size
+    TooLarge(usize),
+    /// The body is not received before the timeout
+    Timeout(Duration),
+    /// The body cannot be read
+    Invalid(hyper::Error),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge(max) => write!(f, "Body too large (max {max} bytes)"),
+            BodyError::Timeout(timeout) => {
+                write!(f, "Body not received within {} ms", timeout.as_millis())
+            }
+            BodyError::Invalid(err) => write!(f, "Invalid body: {err}"),
+        }
+    }
+}
+
+/// Reads a request body, within the size and time limits
+///
+/// The announced `Content-Length` is checked before reading.
+pub(crate) async fn read_body(body: hyper::Body, limits: &Limits) -> Result<Bytes, BodyError> {
+    match limits.body_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, read_data(body, limits.max_body_size))
+            .await
+            .map_err(|_| BodyError::Timeout(timeout))?,
+        None => read_data(body, limits.max_body_size).await,
+    }
+}
+
+/// Reads a request body, up to a maximum size
+async fn read_data(mut body: hyper::Body, max: usize) -> Result<Bytes, BodyError> {
+    if body.size_hint().lower() > max as u64 {
+        return Err(BodyError::TooLarge(max));
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(BodyError::Invalid)?;
+        if data.len() + chunk.len() > max {
+            return Err(BodyError::TooLarge(max));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data.into())
+}
+
+/// Receiver which negotiates the payload formats of each request
+pub(crate) trait Negotiate: Clone {
+    /// Returns the receiver for a request
+    fn negotiate(&self, headers: &HeaderMap) -> Self;
+}
+
+impl Negotiate for HttpTransport {
+    /// Negotiates the formats from the request headers
+    ///
+    /// A request without `Content-Type` is decoded as JSON, and a request without
+    /// `Accept` is answered in the request format. The request ID is also read
+    /// (or generated) here, to be echoed in the response.
+    fn negotiate(&self, headers: &HeaderMap) -> Self {
+        let decoding = match headers.get(header::CONTENT_TYPE) {
+            Some(value) => value.to_str().ok().and_then(Format::from_content_type),
+            None => Some(Format::default()),
+        };
+        let encoding = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::from_accept)
+            .or(decoding)
+            .unwrap_or_default();
+
+        Self {
+            encoding,
+            decoding,
+            request_id: Some(request_id(headers)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Converts a [hyper::Error] to an [io::Error]
+pub(crate) fn io_error(err: hyper::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+#[async_trait]
+impl Receiver for HttpTransport {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        if self.decoding.is_none() {
+            let content_type = req
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            return Err(E::from(RequestError::new(
+                "invalid_input",
+                format!("Unsupported content type: {content_type}"),
+            )));
+        }
+
+        let method = match req.headers().get(Self::HEADER_METHOD) {
+            Some(m) => match m.to_str() {
+                Ok(ok) => ok.to_owned(),
+                Err(err) => {
+                    return Err(E::from(RequestError::new(
+                        "invalid_input",
+                        format!("Invalid method header: {err}"),
+                    )));
+                }
+            },
+            None => {
+                return Err(E::from(RequestError::new(
+                    "invalid_input",
+                    "Missing method header".to_string(),
+                )));
+            }
+        };
+
+        // Extract bearer token from request
+        let token = bearer_token(req.headers())
+            .map_err(|err| E::from(RequestError::new("unauthorized", err)))?;
+
+        // Extract the API version of the client
+        let version = api_version(req.headers())
+            .map_err(|err| E::from(RequestError::new("invalid_input", err)))?;
+
+        // Extract the client certificate verified by the server
+        let certificate = req
+            .extensions()
+            .get::<ClientCertificate>()
+            .map(|c| c.0.clone());
+
+        // Extract body as bytes
+        let data = match read_body(req.into_body(), &self.limits).await {
+            Ok(ok) => ok.to_vec(),
+            Err(err @ BodyError::TooLarge(_)) => {
+                return Err(E::from(RequestError::new(
+                    "payload_too_large",
+                    err.to_string(),
+                )));
+            }
+            Err(err) => {
+                return Err(E::from(RequestError::new("invalid_input", err.to_string())));
+            }
+        };
+
+        Ok(Request {
+            id: self.request_id.clone(),
+            version,
+            method,
+            token,
+            certificate,
+            data,
+        })
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        let format = self.decoding.unwrap_or_default();
+        let value = match format.decode::<T>(data) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Invalid body: {}", err)));
+            }
+        };
+        Ok(value)
+    }
+
+    fn payload_format(&self) -> Option<Format> {
+        self.decoding
+    }
+
+    fn response_format(&self) -> Option<Format> {
+        Some(self.encoding)
+    }
+
+    async fn encoded_ok(&self, data: Vec<u8>) -> Self::Response {
+        self.response(hyper::StatusCode::OK, data)
+    }
+
+    async fn encoded_err(&self, data: Vec<u8>) -> Self::Response {
+        let status = hyper::StatusCode::from_u16(encoded_error_status(self.encoding, &data))
+            .unwrap_or(hyper::StatusCode::BAD_REQUEST);
+        self.response(status, data)
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        match self.encoding.encode(&value) {
+            Ok(data) => self.response(hyper::StatusCode::OK, data),
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode value: {err}"))
+                    .await
+            }
+        }
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        let status = hyper::StatusCode::from_u16(error_status(&error))
+            .unwrap_or(hyper::StatusCode::BAD_REQUEST);
+        match self.encoding.encode(&error) {
+            Ok(data) => self.response(status, data),
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode error: {err}"))
+                    .await
+            }
+        }
+    }
+
+    async fn encode_failure(&self, message: String) -> Self::Response {
+        // The error only holds strings: it always encodes, but it must not fail here
+        let error = RequestError::new("internal", message);
+        let data = self.encoding.encode(&error).unwrap_or_default();
+        self.response(hyper::StatusCode::INTERNAL_SERVER_ERROR, data)
+    }
+}
+
+impl HttpTransport {
+    /// Returns a response with an encoded body
+    fn response(&self, status: hyper::StatusCode, data: Vec<u8>) -> hyper::Response<hyper::Body> {
+        let len = data.len();
+        let mut res = hyper::Response::new(hyper::Body::from(data));
+        *res.status_mut() = status;
+        let headers = res.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(self.encoding.content_type()),
+        );
+        headers.insert(header::CONTENT_LENGTH, len.into());
+        if let Some(id) = &self.request_id {
+            set_request_id(&mut res, id);
+        }
+        res
+    }
+}
+
+#[async_trait]
+impl Sender for HttpTransport {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+
+    async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
+    where
+        E: From<String>,
+    {
+        let res = self.client.request(req);
+        let res = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, res).await {
+                Ok(ok) => ok,
+                Err(_) => {
+                    return Err(E::from(format!("Request timed out after {timeout:?}")));
+                }
+            },
+            None => res.await,
+        };
+        res.map_err(|err| E::from(err.to_string()))
+    }
+
+    async fn encode_request<T, E>(&self, req: Request<T>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let url = match &self.url {
+            Some(url) => url.clone(),
+            None => {
+                return Err(E::from("Missing server URL".to_string()));
+            }
+        };
+
+        let bytes = match self.encoding.encode(&req.data) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+
+        let mut builder = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(url)
+            .header(Self::HEADER_METHOD, req.method)
+            .header(header::CONTENT_TYPE, self.encoding.content_type())
+            .header(header::ACCEPT, self.accept())
+            .header(header::CONTENT_LENGTH, bytes.len());
+        if let Some(token) = req.token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        if let Some(id) = req.id {
+            builder = builder.header(Self::HEADER_REQUEST_ID, id);
+        }
+        if let Some(version) = req.version {
+            builder = builder.header(Self::HEADER_VERSION, version);
+        }
+        builder
+            .body(bytes.into())
+            .map_err(|err| E::from(format!("Invalid request: {err}")))
+    }
+
+    async fn decode_response<T, E>(&self, res: Self::Response) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let status = res.status();
+        let format = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::from_content_type)
+            .unwrap_or(self.encoding);
+
+        let bytes = match hyper::body::to_bytes(res.into_body()).await {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot read response body: {}", err)));
+            }
+        };
+
+        if status.is_success() {
+            let value = match format.decode::<T>(&bytes) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    return Err(E::from(format!("Cannot deserialize value: {}", err)));
+                }
+            };
+            Ok(value)
+        } else {
+            let error = match format.decode::<E>(&bytes) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    return Err(E::from(format!("Cannot deserialize value: {}", err)));
+                }
+            };
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_invalid_url() {
+        let res = HttpTransport::builder().url("not a url").build::<String>();
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn request_without_url() {
+        let transport = HttpTransport::new();
+        let req = Request::new("status", None, ());
+        let res = transport.encode_request::<(), String>(req).await;
+        assert_eq!(res.unwrap_err(), "Missing server URL");
+    }
+
+    #[tokio::test]
+    async fn request_with_token() {
+        let transport = HttpTransport::builder()
+            .url("http://localhost:6666")
+            .timeout(Duration::from_secs(1))
+            .connect_timeout(Duration::from_secs(1))
+            .build::<String>()
+            .unwrap();
+        let req = Request::new("status", Some("abc".to_string()), ());
+        let req = transport.encode_request::<(), String>(req).await.unwrap();
+        assert_eq!(req.uri(), "http://localhost:6666/");
+        assert_eq!(req.headers()[header::AUTHORIZATION], "Bearer abc");
+    }
+
+    #[tokio::test]
+    async fn negotiate_cbor() {
+        let sender = HttpTransport::builder()
+            .url("http://localhost:6666")
+            .format(Format::Cbor)
+            .build::<String>()
+            .unwrap();
+        let req = Request::new("echo", None, vec!["a".to_string(), "b".to_string()]);
+        let req = sender.encode_request::<_, String>(req).await.unwrap();
+        assert_eq!(req.headers()[header::CONTENT_TYPE], "application/cbor");
+
+        // The receiver decodes and answers in CBOR
+        let receiver = HttpTransport::new().negotiate(req.headers());
+        let req = receiver.decode_request::<String>(req).await.unwrap();
+        let data = receiver
+            .decode_payload::<Vec<String>, String>(&req.data)
+            .await
+            .unwrap();
+        let res = receiver.encode_ok(data).await;
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/cbor");
+
+        let res = sender
+            .decode_response::<Vec<String>, String>(res)
+            .await
+            .unwrap();
+        assert_eq!(res, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn router_cbor_error() {
+        use crate::Router;
+
+        #[derive(Serialize)]
+        struct Error {
+            kind: &'static str,
+            message: &'static str,
+        }
+        let router = Router::new().method("find", |_ctx, ()| async {
+            Err::<(), _>(Error {
+                kind: "not_found",
+                message: "Not found",
+            })
+        });
+        let req = hyper::Request::builder()
+            .header(HttpTransport::HEADER_METHOD, "find")
+            .header(header::ACCEPT, "application/cbor")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        // The error is encoded in CBOR by the router, and answered with its status
+        let receiver = HttpTransport::new().negotiate(req.headers());
+        let res = router.handle(receiver, req).await;
+        assert_eq!(res.status(), hyper::StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/cbor");
+        let sender = HttpTransport::builder()
+            .format(Format::Cbor)
+            .build::<String>()
+            .unwrap();
+        let err = sender
+            .decode_response::<(), serde_json::Value>(res)
+            .await
+            .unwrap_err();
+        assert_eq!(err["message"], "Not found");
+    }
+
+    #[tokio::test]
+    async fn negotiate_unsupported() {
+        let req = hyper::Request::builder()
+            .header(HttpTransport::HEADER_METHOD, "status")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::ACCEPT, "text/html")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        // Unsupported content types are rejected, and answered in JSON
+        let receiver = HttpTransport::new().negotiate(req.headers());
+        let err = receiver.decode_request::<String>(req).await.unwrap_err();
+        assert_eq!(err, "Unsupported content type: text/plain");
+        let res = receiver.encode_err(err).await;
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    /// Decodes a request built from raw headers and body
+    async fn decode(
+        headers: &[(&str, &[u8])],
+        body: &'static [u8],
+    ) -> Result<(Request<Vec<u8>>, HttpTransport), String> {
+        let mut req = hyper::Request::builder();
+        for (name, value) in headers {
+            req = req.header(*name, header::HeaderValue::from_bytes(value).unwrap());
+        }
+        let req = req.body(hyper::Body::from(body)).unwrap();
+        let receiver = HttpTransport::new().negotiate(req.headers());
+        let req = receiver.decode_request::<String>(req).await?;
+        Ok((req, receiver))
+    }
+
+    #[tokio::test]
+    async fn request_id_echo() {
+        let sender = HttpTransport::builder()
+            .url("http://localhost:6666")
+            .build::<String>()
+            .unwrap();
+        let mut req = Request::new("status", None, ());
+        req.id = Some("req-1".to_string());
+        let req = sender.encode_request::<_, String>(req).await.unwrap();
+        assert_eq!(req.headers()[HttpTransport::HEADER_REQUEST_ID], "req-1");
+
+        // The receiver decodes the ID and echoes it
+        let receiver = HttpTransport::new().negotiate(req.headers());
+        let req = receiver.decode_request::<String>(req).await.unwrap();
+        assert_eq!(req.id.as_deref(), Some("req-1"));
+        let res = receiver.encode_err("error".to_string()).await;
+        assert_eq!(res.headers()[HttpTransport::HEADER_REQUEST_ID], "req-1");
+
+        // A missing or invalid ID is replaced
+        let mut headers = HeaderMap::new();
+        let id = request_id(&headers);
+        assert_eq!(id.len(), 36);
+        headers.insert(
+            HttpTransport::HEADER_REQUEST_ID,
+            HeaderValue::from_static("a b"),
+        );
+        assert_ne!(request_id(&headers), "a b");
+        let long = HeaderValue::from_str(&"a".repeat(200)).unwrap();
+        headers.insert(HttpTransport::HEADER_REQUEST_ID, long);
+        assert_eq!(request_id(&headers).len(), 36);
+    }
+
+    #[tokio::test]
+    async fn malformed_requests() {
+        let method = HttpTransport::HEADER_METHOD;
+
+        // Headers
+        let err = decode(&[], b"{}").await.unwrap_err();
+        assert_eq!(err, "Missing method header");
+        let err = decode(&[(method, b"st\xffatus")], b"{}").await.unwrap_err();
+        assert!(err.starts_with("Invalid method header"), "{err}");
+        let headers: &[(&str, &[u8])] = &[(method, b"status"), ("authorization", b"Basic abc")];
+        let err = decode(headers, b"{}").await.unwrap_err();
+        assert!(err.starts_with("Invalid auth header"), "{err}");
+        let headers: &[(&str, &[u8])] = &[(method, b"status"), ("authorization", b"Bearer \xff")];
+        let err = decode(headers, b"{}").await.unwrap_err();
+        assert!(err.starts_with("Invalid auth header"), "{err}");
+        let headers: &[(&str, &[u8])] = &[(method, b"status"), ("content-type", b"\xff")];
+        let err = decode(headers, b"{}").await.unwrap_err();
+        assert!(err.starts_with("Unsupported content type"), "{err}");
+        let headers: &[(&str, &[u8])] = &[(method, b"status"), ("x-rpc-version", b"v1")];
+        let err = decode(headers, b"{}").await.unwrap_err();
+        assert!(err.starts_with("Invalid version header"), "{err}");
+
+        // Bodies: the request decodes, but not its payload
+        let bodies: &[(&[u8], &'static [u8])] = &[
+            (b"application/json", b"{\"a\": "),
+            (b"application/json", b"\"\xff\xfe\""),
+            (b"application/json", b""),
+            (b"application/cbor", b"\xff\xff"),
+        ];
+        for (content_type, body) in bodies {
+            let headers: &[(&str, &[u8])] = &[(method, b"status"), ("content-type", content_type)];
+            let (req, receiver) = decode(headers, body).await.unwrap();
+            let res = receiver.decode_payload::<String, String>(&req.data).await;
+            let err = res.unwrap_err();
+            assert!(err.starts_with("Invalid body"), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn encode_failures() {
+        use std::collections::HashMap;
+
+        // JSON maps only have string keys
+        let value = HashMap::from([((1, 2), 3)]);
+        let receiver = HttpTransport::new();
+        let res = receiver.encode_ok(value.clone()).await;
+        assert_eq!(res.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        let res = receiver.decode_response::<(), serde_json::Value>(res).await;
+        let err = res.unwrap_err();
+        assert_eq!(err["kind"], "internal");
+        assert!(err["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Cannot encode value"));
+
+        let res = receiver.encode_err(value).await;
+        assert_eq!(res.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+
+        // A token which is not a valid header value
+        let sender = HttpTransport::builder()
+            .url("http://localhost:6666")
+            .build::<String>()
+            .unwrap();
+        let req = Request::new("status", Some("abc\ndef".to_string()), ());
+        let err = sender.encode_request::<_, String>(req).await.unwrap_err();
+        assert!(err.starts_with("Invalid request"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown() {
+        use tokio::{net::TcpStream, sync::oneshot};
+
+        use crate::{Client, Router};
+
+        let (listener, addr) = crate::testing::listener().await;
+        let router = Router::new().method("sleep", |_ctx, ms: u64| async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok::<_, String>(ms)
+        });
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let server = Server::new(HttpTransport::new(), router);
+        let server =
+            tokio::spawn(async move { server.serve_with_shutdown(listener, signal).await });
+
+        // The in-flight request is answered after the shutdown signal
+        let sender = HttpTransport::builder()
+            .url(format!("http://{addr}"))
+            .build::<String>()
+            .unwrap();
+        let client = Client::new(sender);
+        let call = tokio::spawn(async move {
+            let req = Request::new("sleep", None, 300);
+            client.call::<_, u64, String>(req).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(()).unwrap();
+        assert_eq!(call.await.unwrap(), Ok(300));
+
+        // The server stops, and refuses the new connections
+        let res = tokio::time::timeout(Duration::from_secs(2), server).await;
+        assert!(res.unwrap().unwrap().is_ok());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn routes() {
+        use crate::Router;
+
+        let (listener, addr) = crate::testing::listener().await;
+        let receiver = HttpTransport::builder()
+            .route("/ping", |_req| async {
+                hyper::Response::new("pong".into())
+            })
+            .build::<String>()
+            .unwrap();
+        let router = Router::new().method("ping", |_ctx, ()| async { Ok::<_, String>("rpc") });
+        tokio::spawn(async move { Server::new(receiver, router).serve(listener).await });
+
+        // GET requests of the route
+        let client = hyper::Client::new();
+        let res = client
+            .get(format!("http://{addr}/ping").parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "pong");
+
+        // The RPC requests are still handled
+        let sender = HttpTransport::builder()
+            .url(format!("http://{addr}/ping"))
+            .build::<String>()
+            .unwrap();
+        let req = Request::new("ping", None, ());
+        let client = crate::Client::new(sender);
+        let res = client.call::<_, String, String>(req).await;
+        assert_eq!(res.unwrap(), "rpc");
+    }
+}
@@ -0,0 +1,896 @@
+//! JSON-RPC 2.0 transport
+//!
+//! See the [specification](https://www.jsonrpc.org/specification).
+
+use std::{
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use hyper::header;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tokio::net::TcpListener;
+
+use crate::{
+    client::Sender,
+    http::{self, BodyError, HttpTransport},
+    server::{Handler, Receiver, RequestError, Server},
+    tls::ClientCertificate,
+    Request, Response,
+};
+
+/// JSON-RPC version
+const VERSION: &str = "2.0";
+
+/// Invalid JSON
+const PARSE_ERROR: i64 = -32700;
+
+/// Not a valid request object
+const INVALID_REQUEST: i64 = -32600;
+
+/// Method not found
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Invalid method parameters
+const INVALID_PARAMS: i64 = -32602;
+
+/// Internal error
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Error returned by a service method
+const SERVER_ERROR: i64 = -32000;
+
+/// JSON-RPC 2.0 transport
+///
+/// The calls are sent as JSON-RPC request objects (or batch arrays) in the body of HTTP
+/// `POST` requests. The bearer token is sent in the `Authorization` header, and applies
+/// to all the calls of a batch.
+///
+/// The transport wraps a [HttpTransport], which holds the server URL, the TLS configuration
+/// and the timeouts.
+#[derive(Debug, Clone)]
+pub struct JsonRpcTransport {
+    /// HTTP transport
+    http: HttpTransport,
+    /// ID of the call being handled
+    id: Value,
+    /// Set if the parameters of the call being handled are invalid
+    invalid_params: Arc<AtomicBool>,
+}
+
+impl JsonRpcTransport {
+    /// Instantiates a new [JsonRpcTransport] over a configured [HttpTransport]
+    pub fn new(http: HttpTransport) -> Self {
+        Self {
+            http,
+            id: Value::Null,
+            invalid_params: Arc::default(),
+        }
+    }
+
+    /// Returns a receiver for a call
+    fn for_call(&self, id: Value) -> Self {
+        Self {
+            http: self.http.clone(),
+            id,
+            invalid_params: Arc::default(),
+        }
+    }
+
+    /// Handles a HTTP request, and echoes its request ID
+    async fn handle_http<H>(
+        &self,
+        handler: &H,
+        req: hyper::Request<hyper::Body>,
+    ) -> hyper::Response<hyper::Body>
+    where
+        H: Handler,
+    {
+        let request_id = http::request_id(req.headers());
+        let mut res = self.handle_calls(handler, req, &request_id).await;
+        http::set_request_id(&mut res, &request_id);
+        res
+    }
+
+    /// Handles a call or a batch of calls
+    ///
+    /// The calls of a batch share the request ID of the HTTP request.
+    async fn handle_calls<H>(
+        &self,
+        handler: &H,
+        req: hyper::Request<hyper::Body>,
+        request_id: &str,
+    ) -> hyper::Response<hyper::Body>
+    where
+        H: Handler,
+    {
+        let token = match http::bearer_token(req.headers()) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return json_response(&JsonRpcResponse::error(INVALID_REQUEST, err, Value::Null));
+            }
+        };
+        let version = match http::api_version(req.headers()) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return json_response(&JsonRpcResponse::error(INVALID_REQUEST, err, Value::Null));
+            }
+        };
+        let certificate = req
+            .extensions()
+            .get::<ClientCertificate>()
+            .map(|c| c.0.clone());
+
+        let body = match http::read_body(req.into_body(), &self.http.limits()).await {
+            Ok(ok) => ok,
+            Err(err @ BodyError::TooLarge(_)) => {
+                let err = JsonRpcResponse::error(INVALID_REQUEST, err.to_string(), Value::Null);
+                let mut res = json_response(&err);
+                *res.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
+                return res;
+            }
+            Err(err) => {
+                let err = err.to_string();
+                return json_response(&JsonRpcResponse::error(PARSE_ERROR, err, Value::Null));
+            }
+        };
+        let value = match serde_json::from_slice::<Value>(&body) {
+            Ok(ok) => ok,
+            Err(err) => {
+                let err = format!("Parse error: {err}");
+                return json_response(&JsonRpcResponse::error(PARSE_ERROR, err, Value::Null));
+            }
+        };
+
+        match value {
+            Value::Array(items) if items.is_empty() => json_response(&JsonRpcResponse::error(
+                INVALID_REQUEST,
+                "Empty batch".to_string(),
+                Value::Null,
+            )),
+            Value::Array(items) => {
+                // The calls of a batch are handled in order
+                let mut responses = vec![];
+                for item in items {
+                    let res = self
+                        .handle_call(handler, item, &token, &certificate, request_id, version)
+                        .await;
+                    responses.extend(res);
+                }
+                if responses.is_empty() {
+                    no_content()
+                } else {
+                    json_response(&responses)
+                }
+            }
+            value => match self
+                .handle_call(handler, value, &token, &certificate, request_id, version)
+                .await
+            {
+                Some(res) => json_response(&res),
+                None => no_content(),
+            },
+        }
+    }
+
+    /// Handles a call
+    ///
+    /// Returns [None] for a notification (call without ID).
+    async fn handle_call<H>(
+        &self,
+        handler: &H,
+        value: Value,
+        token: &Option<String>,
+        certificate: &Option<String>,
+        request_id: &str,
+        version: Option<u32>,
+    ) -> Option<JsonRpcResponse>
+    where
+        H: Handler,
+    {
+        let id = value.get("id").cloned().unwrap_or_default();
+        let call = match serde_json::from_value::<JsonRpcCall>(value) {
+            Ok(call) if call.jsonrpc == VERSION => call,
+            Ok(_) => {
+                let err = format!("Unsupported JSON-RPC version, expected {VERSION}");
+                return Some(JsonRpcResponse::error(INVALID_REQUEST, err, id));
+            }
+            Err(err) => {
+                let err = format!("Invalid request: {err}");
+                return Some(JsonRpcResponse::error(INVALID_REQUEST, err, id));
+            }
+        };
+
+        let notification = call.id.is_none();
+        let receiver = self.for_call(call.id.clone().unwrap_or_default());
+        let call = JsonRpcCall {
+            token: token.clone(),
+            certificate: certificate.clone(),
+            request_id: Some(request_id.to_string()),
+            version,
+            ..call
+        };
+        let res = handler.handle(receiver, call).await;
+        if notification {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    /// Returns a HTTP request carrying a JSON-RPC body
+    fn http_request<E>(
+        &self,
+        token: Option<String>,
+        request_id: Option<String>,
+        version: Option<u32>,
+        body: Vec<u8>,
+    ) -> Result<hyper::Request<hyper::Body>, E>
+    where
+        E: From<String>,
+    {
+        let url = match self.http.url() {
+            Some(url) => url.clone(),
+            None => {
+                return Err(E::from("Missing server URL".to_string()));
+            }
+        };
+
+        let mut builder = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_LENGTH, body.len());
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        if let Some(id) = request_id {
+            builder = builder.header(HttpTransport::HEADER_REQUEST_ID, id);
+        }
+        if let Some(version) = version {
+            builder = builder.header(HttpTransport::HEADER_VERSION, version);
+        }
+        builder
+            .body(body.into())
+            .map_err(|err| E::from(format!("Cannot build request: {err}")))
+    }
+
+    /// Reads the body of a HTTP response
+    async fn read_body<E>(res: hyper::Response<hyper::Body>) -> Result<Vec<u8>, E>
+    where
+        E: From<String>,
+    {
+        let status = res.status();
+        if !status.is_success() {
+            return Err(E::from(format!("Unexpected HTTP status: {status}")));
+        }
+        match hyper::body::to_bytes(res.into_body()).await {
+            Ok(ok) => Ok(ok.to_vec()),
+            Err(err) => Err(E::from(format!("Cannot read response body: {}", err))),
+        }
+    }
+}
+
+impl<H> Server<H, JsonRpcTransport>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+{
+    /// Starts the server
+    ///
+    /// The server listens over TLS if the HTTP transport has a server certificate.
+    pub async fn start(self, addr: &SocketAddr) -> io::Result<()> {
+        self.start_with_shutdown(addr, future::pending()).await
+    }
+
+    /// Starts the server, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops accepting connections and returns once the
+    /// in-flight requests are answered.
+    pub async fn start_with_shutdown<S>(self, addr: &SocketAddr, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_with_shutdown(listener, signal).await
+    }
+
+    /// Serves the connections of a bound listener (eg. on port 0)
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        self.serve_with_shutdown(listener, future::pending()).await
+    }
+
+    /// Serves the connections of a bound listener, until the shutdown signal resolves
+    pub async fn serve_with_shutdown<S>(self, listener: TcpListener, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let http = self.receiver.http.clone();
+        let handler = self.handler;
+        let receiver = self.receiver;
+        http::listen(listener, &http, signal, move |req| {
+            let handler = handler.clone();
+            let receiver = receiver.clone();
+            async move { receiver.handle_http(&handler, req).await }
+        })
+        .await
+    }
+}
+
+/// JSON-RPC request object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcCall {
+    /// JSON-RPC version (`2.0`)
+    pub jsonrpc: String,
+    /// Method
+    pub method: String,
+    /// Parameters (array or object)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+    /// ID, [None] for a notification
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    pub id: Option<Value>,
+    /// Bearer token of the HTTP request
+    #[serde(skip)]
+    pub token: Option<String>,
+    /// Subject of the client certificate
+    #[serde(skip)]
+    pub certificate: Option<String>,
+    /// Request ID of the HTTP request
+    #[serde(skip)]
+    pub request_id: Option<String>,
+    /// API version of the HTTP request
+    #[serde(skip)]
+    pub version: Option<u32>,
+}
+
+impl JsonRpcCall {
+    /// Instantiates a new [JsonRpcCall] from a RPC request
+    ///
+    /// Parameters which are not structured are wrapped in an array, as required
+    /// by the specification.
+    fn new<T, E>(req: Request<T>, id: usize) -> Result<Self, E>
+    where
+        T: Serialize,
+        E: From<String>,
+    {
+        let params = match serde_json::to_value(&req.data) {
+            Ok(Value::Null) => None,
+            Ok(value @ (Value::Array(_) | Value::Object(_))) => Some(value),
+            Ok(value) => Some(Value::Array(vec![value])),
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+        Ok(Self {
+            jsonrpc: VERSION.to_string(),
+            method: req.method,
+            params,
+            id: Some(id.into()),
+            token: None,
+            certificate: None,
+            request_id: None,
+            version: None,
+        })
+    }
+}
+
+/// JSON-RPC response object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    /// JSON-RPC version (`2.0`)
+    pub jsonrpc: String,
+    /// Result, on success
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    pub result: Option<Value>,
+    /// Error, on failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+    /// ID of the call
+    pub id: Value,
+}
+
+impl JsonRpcResponse {
+    /// Instantiates a successful response
+    fn ok(result: Value, id: Value) -> Self {
+        Self {
+            jsonrpc: VERSION.to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    /// Instantiates an error response
+    fn error(code: i64, message: String, id: Value) -> Self {
+        Self {
+            jsonrpc: VERSION.to_string(),
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+            id,
+        }
+    }
+
+    /// Returns the result of the call
+    fn into_result<T, E>(self) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        if let Some(error) = self.error {
+            return Err(error.into_error());
+        }
+        match serde_json::from_value::<T>(self.result.unwrap_or_default()) {
+            Ok(ok) => Ok(ok),
+            Err(err) => Err(E::from(format!("Cannot deserialize value: {}", err))),
+        }
+    }
+}
+
+/// JSON-RPC error object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    /// Error code
+    pub code: i64,
+    /// Message
+    pub message: String,
+    /// Service error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    /// Returns the service error, or an error built from the message
+    fn into_error<E>(self) -> E
+    where
+        E: DeserializeOwned + From<String>,
+    {
+        self.data
+            .and_then(|data| serde_json::from_value::<E>(data).ok())
+            .unwrap_or_else(|| E::from(self.message))
+    }
+}
+
+/// Deserializes a field which may be `null`, but is [None] only if absent
+fn present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// Returns a HTTP response with a JSON body
+fn json_response<T>(value: &T) -> hyper::Response<hyper::Body>
+where
+    T: Serialize,
+{
+    match serde_json::to_vec(value) {
+        Ok(data) => {
+            let len = data.len();
+            let mut res = hyper::Response::new(hyper::Body::from(data));
+            let headers = res.headers_mut();
+            headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+            headers.insert(header::CONTENT_LENGTH, len.into());
+            res
+        }
+        Err(_) => {
+            let mut res = hyper::Response::new(hyper::Body::empty());
+            *res.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+            res
+        }
+    }
+}
+
+/// Returns an empty HTTP response (to notifications)
+fn no_content() -> hyper::Response<hyper::Body> {
+    let mut res = hyper::Response::new(hyper::Body::empty());
+    *res.status_mut() = hyper::StatusCode::NO_CONTENT;
+    res
+}
+
+#[async_trait]
+impl Receiver for JsonRpcTransport {
+    type Request = JsonRpcCall;
+    type Response = JsonRpcResponse;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        let data = match serde_json::to_vec(&req.params.unwrap_or_default()) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(RequestError::new(
+                    "invalid_input",
+                    format!("Invalid params: {}", err),
+                )));
+            }
+        };
+
+        Ok(Request {
+            id: req.request_id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            certificate: req.certificate,
+            data,
+        })
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        let err = match serde_json::from_slice::<T>(data) {
+            Ok(ok) => return Ok(ok),
+            Err(err) => err,
+        };
+
+        // Single parameter, wrapped in an array
+        if let Ok(Value::Array(mut values)) = serde_json::from_slice::<Value>(data) {
+            if values.len() == 1 {
+                if let Ok(ok) = serde_json::from_value::<T>(values.remove(0)) {
+                    return Ok(ok);
+                }
+            }
+        }
+
+        self.invalid_params.store(true, Ordering::Relaxed);
+        Err(E::from(format!("Invalid params: {}", err)))
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        match serde_json::to_value(&value) {
+            Ok(result) => JsonRpcResponse::ok(result, self.id.clone()),
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode value: {err}"))
+                    .await
+            }
+        }
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        let code = if self.invalid_params.load(Ordering::Relaxed) {
+            INVALID_PARAMS
+        } else {
+            SERVER_ERROR
+        };
+        error_response(code, &error, self.id.clone())
+    }
+
+    async fn encode_unknown_method<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        error_response(METHOD_NOT_FOUND, &error, self.id.clone())
+    }
+
+    async fn encode_failure(&self, message: String) -> Self::Response {
+        JsonRpcResponse::error(INTERNAL_ERROR, message, self.id.clone())
+    }
+
+    async fn encode_invalid_payload<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        error_response(INVALID_PARAMS, &error, self.id.clone())
+    }
+}
+
+/// Returns an error response for a service error
+///
+/// The message of the service error is used if it has one, and the service error
+/// is set as the error data.
+fn error_response<E>(code: i64, error: &E, id: Value) -> JsonRpcResponse
+where
+    E: Serialize,
+{
+    let data = match serde_json::to_value(error) {
+        Ok(ok) => ok,
+        Err(err) => {
+            return JsonRpcResponse::error(
+                INTERNAL_ERROR,
+                format!("Cannot encode error: {err}"),
+                id,
+            );
+        }
+    };
+    let message = match &data {
+        Value::String(message) => message.clone(),
+        value => match value.get("message") {
+            Some(Value::String(message)) => message.clone(),
+            _ => "Server error".to_string(),
+        },
+    };
+
+    let mut res = JsonRpcResponse::error(code, message, id);
+    if let Some(error) = &mut res.error {
+        error.data = Some(data);
+    }
+    res
+}
+
+#[async_trait]
+impl Sender for JsonRpcTransport {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+
+    async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
+    where
+        E: From<String>,
+    {
+        self.http.send(req).await
+    }
+
+    async fn encode_request<T, E>(&self, req: Request<T>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let token = req.token.clone();
+        let request_id = req.id.clone();
+        let version = req.version;
+        let call = JsonRpcCall::new::<T, E>(req, 0)?;
+        let body = match serde_json::to_vec(&call) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+        self.http_request(token, request_id, version, body)
+    }
+
+    async fn decode_response<T, E>(&self, res: Self::Response) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let body = Self::read_body::<E>(res).await?;
+        match serde_json::from_slice::<JsonRpcResponse>(&body) {
+            Ok(res) => res.into_result(),
+            Err(err) => Err(E::from(format!("Invalid JSON-RPC response: {}", err))),
+        }
+    }
+
+    async fn encode_batch<T, E>(&self, reqs: Vec<Request<T>>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let token = reqs.first().and_then(|req| req.token.clone());
+        if reqs.iter().any(|req| req.token != token) {
+            return Err(E::from(
+                "The requests of a batch must share the same token".to_string(),
+            ));
+        }
+
+        // The batch is sent with the ID and version of its first request
+        let request_id = reqs.first().and_then(|req| req.id.clone());
+        let version = reqs.first().and_then(|req| req.version);
+        let calls = reqs
+            .into_iter()
+            .enumerate()
+            .map(|(id, req)| JsonRpcCall::new::<T, E>(req, id))
+            .collect::<Result<Vec<_>, E>>()?;
+        let body = match serde_json::to_vec(&calls) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+        self.http_request(token, request_id, version, body)
+    }
+
+    async fn decode_batch<T, E>(
+        &self,
+        res: Self::Response,
+        len: usize,
+    ) -> Result<Vec<Response<T, E>>, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let body = Self::read_body::<E>(res).await?;
+        let responses = match serde_json::from_slice::<Value>(&body) {
+            // The whole batch was rejected
+            Ok(value @ Value::Object(_)) => {
+                return match serde_json::from_value::<JsonRpcResponse>(value) {
+                    Ok(res) => Err(res
+                        .error
+                        .map(|err| err.into_error())
+                        .unwrap_or_else(|| E::from("Invalid JSON-RPC response".to_string()))),
+                    Err(err) => Err(E::from(format!("Invalid JSON-RPC response: {}", err))),
+                };
+            }
+            Ok(value) => match serde_json::from_value::<Vec<JsonRpcResponse>>(value) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    return Err(E::from(format!("Invalid JSON-RPC response: {}", err)));
+                }
+            },
+            Err(err) => {
+                return Err(E::from(format!("Invalid JSON-RPC response: {}", err)));
+            }
+        };
+
+        // The responses may be in any order
+        let mut results = (0..len).map(|_| None).collect::<Vec<_>>();
+        for res in responses {
+            let slot = res
+                .id
+                .as_u64()
+                .and_then(|id| results.get_mut(usize::try_from(id).ok()?));
+            if let Some(slot) = slot {
+                *slot = Some(res.into_result());
+            }
+        }
+        Ok(results
+            .into_iter()
+            .enumerate()
+            .map(|(id, res)| {
+                res.unwrap_or_else(|| Err(E::from(format!("Missing response to call {id}"))))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+
+    /// Handler with an `add` method, taking 2 integers
+    #[derive(Clone)]
+    struct Adder;
+
+    #[async_trait]
+    impl Handler for Adder {
+        async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+        where
+            R: Receiver,
+        {
+            let req = match receiver.decode_request::<String>(request).await {
+                Ok(ok) => ok,
+                Err(err) => return receiver.encode_err(err).await,
+            };
+            match req.method.as_str() {
+                "add" => match receiver
+                    .decode_payload::<(i64, i64), String>(&req.data)
+                    .await
+                {
+                    Ok((a, b)) => receiver.encode_ok(a + b).await,
+                    Err(err) => receiver.encode_err(err).await,
+                },
+                "fail" => receiver.encode_err("failed".to_string()).await,
+                m => {
+                    let err = format!("Invalid method: {m}");
+                    receiver.encode_unknown_method(err).await
+                }
+            }
+        }
+    }
+
+    /// Sends a raw JSON-RPC body to the handler
+    async fn handle(body: &str) -> (hyper::StatusCode, Value) {
+        let req = hyper::Request::builder()
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        let res = JsonRpcTransport::new(HttpTransport::new())
+            .handle_http(&Adder, req)
+            .await;
+        let status = res.status();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let value = serde_json::from_slice(&bytes).unwrap_or_default();
+        (status, value)
+    }
+
+    #[tokio::test]
+    async fn call() {
+        let (_, res) =
+            handle(r#"{"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1}"#).await;
+        assert_eq!(
+            res,
+            serde_json::json!({"jsonrpc": "2.0", "result": 3, "id": 1})
+        );
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        let (_, res) = handle(r#"{"jsonrpc": "2.0", "method": "add", "params": [1, "#).await;
+        assert_eq!(res["error"]["code"], PARSE_ERROR);
+        assert_eq!(res["id"], Value::Null);
+
+        let (_, res) = handle(r#"{"jsonrpc": "1.0", "method": "add", "id": 1}"#).await;
+        assert_eq!(res["error"]["code"], INVALID_REQUEST);
+
+        let (_, res) = handle(r#"{"jsonrpc": "2.0", "method": "sub", "id": "a"}"#).await;
+        assert_eq!(res["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(res["id"], "a");
+
+        let (_, res) =
+            handle(r#"{"jsonrpc": "2.0", "method": "add", "params": ["a"], "id": 2}"#).await;
+        assert_eq!(res["error"]["code"], INVALID_PARAMS);
+
+        let (_, res) = handle(r#"{"jsonrpc": "2.0", "method": "fail", "id": 3}"#).await;
+        assert_eq!(res["error"]["code"], SERVER_ERROR);
+        assert_eq!(res["error"]["message"], "failed");
+        assert_eq!(res["error"]["data"], "failed");
+    }
+
+    #[tokio::test]
+    async fn batch_and_notifications() {
+        let (_, res) = handle(
+            r#"[
+                {"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1},
+                {"jsonrpc": "2.0", "method": "add", "params": [3, 4]},
+                {"foo": "bar"},
+                {"jsonrpc": "2.0", "method": "add", "params": [5, 6], "id": 2}
+            ]"#,
+        )
+        .await;
+        let res = res.as_array().unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0]["result"], 3);
+        assert_eq!(res[1]["error"]["code"], INVALID_REQUEST);
+        assert_eq!(res[2]["result"], 11);
+
+        let (status, _) = handle(r#"{"jsonrpc": "2.0", "method": "add", "params": [1, 2]}"#).await;
+        assert_eq!(status, hyper::StatusCode::NO_CONTENT);
+
+        let (_, res) = handle("[]").await;
+        assert_eq!(res["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn client_batch() {
+        let (listener, addr) = crate::testing::listener().await;
+        let server = Server::new(JsonRpcTransport::new(HttpTransport::new()), Adder);
+        tokio::spawn(async move { server.serve(listener).await.unwrap() });
+
+        let http = HttpTransport::builder()
+            .url(format!("http://{addr}"))
+            .build::<String>()
+            .unwrap();
+        let client = Client::new(JsonRpcTransport::new(http));
+
+        let req = Request::new("add", None, (1, 2));
+        assert_eq!(client.call::<(i64, i64), i64, String>(req).await, Ok(3));
+
+        let reqs = vec![
+            Request::new("add", None, (1, 2)),
+            Request::new("sub", None, (1, 2)),
+            Request::new("add", None, (3, 4)),
+        ];
+        let res = client.batch::<(i64, i64), i64, String>(reqs).await.unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0], Ok(3));
+        assert_eq!(res[1], Err("Invalid method: sub".to_string()));
+        assert_eq!(res[2], Ok(7));
+    }
+}
@@ -0,0 +1,378 @@
+//! In-process transport
+
+use std::{
+    future::{self, Future},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    client::Sender,
+    codec::{Codec, Format},
+    server::{Handler, Receiver, RequestError, Server},
+    Request, Response,
+};
+
+/// Request sent over the channel, with the channel of its response
+type Envelope = (LocalRequest, oneshot::Sender<LocalResponse>);
+
+/// In-process transport
+///
+/// The client and the server exchange messages over a channel, without any socket.
+/// The payloads are still encoded and decoded (JSON by default), as over the network.
+///
+/// The same transport (or a clone) is the [Sender] of the client and the [Receiver]
+/// of the server. The requests sent before the server is started are queued.
+#[derive(Debug, Clone)]
+pub struct LocalTransport {
+    /// Request channel
+    sender: mpsc::UnboundedSender<Envelope>,
+    /// Request queue, taken by the server
+    queue: Arc<Mutex<Option<mpsc::UnboundedReceiver<Envelope>>>>,
+    /// Format of the payloads
+    format: Format,
+    /// Subject of the client certificate set on the requests
+    certificate: Option<String>,
+}
+
+impl LocalTransport {
+    /// Instantiates a new [LocalTransport]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a server with a handler in the background, and returns its transport
+    pub fn serve<H>(handler: H) -> Self
+    where
+        H: Handler + Clone + Send + Sync + 'static,
+    {
+        let transport = Self::new();
+        let server = Server::new(transport.clone(), handler);
+        tokio::spawn(server.start());
+        transport
+    }
+
+    /// Sets the format of the payloads (JSON by default)
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the subject of a client certificate, as if verified by the server
+    pub fn certificate(mut self, subject: impl AsRef<str>) -> Self {
+        self.certificate = Some(subject.as_ref().to_string());
+        self
+    }
+}
+
+impl Default for LocalTransport {
+    fn default() -> Self {
+        let (sender, queue) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            queue: Arc::new(Mutex::new(Some(queue))),
+            format: Format::default(),
+            certificate: None,
+        }
+    }
+}
+
+impl<H> Server<H, LocalTransport>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+{
+    /// Starts the server
+    ///
+    /// The requests are handled concurrently. A transport can only be served once.
+    pub async fn start(self) -> io::Result<()> {
+        self.start_with_shutdown(future::pending()).await
+    }
+
+    /// Starts the server, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops receiving requests. The in-flight requests
+    /// are still answered.
+    pub async fn start_with_shutdown<S>(self, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let queue = self.receiver.queue.lock().unwrap().take();
+        let mut queue = match queue {
+            Some(queue) => queue,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "Server already started",
+                ));
+            }
+        };
+
+        tokio::pin!(signal);
+        loop {
+            let (req, reply) = tokio::select! {
+                msg = queue.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = &mut signal => break,
+            };
+            let handler = self.handler.clone();
+            let receiver = self.receiver.clone();
+            tokio::spawn(async move {
+                let res = handler.handle(receiver, req).await;
+                let _ = reply.send(res);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Request exchanged over a [LocalTransport]
+#[derive(Debug, Clone)]
+pub struct LocalRequest {
+    /// Request ID
+    pub id: Option<String>,
+    /// API version
+    pub version: Option<u32>,
+    /// Method
+    pub method: String,
+    /// Authentication token
+    pub token: Option<String>,
+    /// Subject of the client certificate
+    pub certificate: Option<String>,
+    /// Encoded payload
+    pub data: Vec<u8>,
+}
+
+/// Response exchanged over a [LocalTransport]
+#[derive(Debug, Clone)]
+pub enum LocalResponse {
+    /// Encoded value
+    Ok(Vec<u8>),
+    /// Encoded error
+    Err(Vec<u8>),
+}
+
+#[async_trait]
+impl Receiver for LocalTransport {
+    type Request = LocalRequest;
+    type Response = LocalResponse;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        Ok(Request {
+            id: req.id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            certificate: req.certificate,
+            data: req.data,
+        })
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        self.format
+            .decode::<T>(data)
+            .map_err(|err| E::from(format!("Invalid data: {}", err)))
+    }
+
+    fn payload_format(&self) -> Option<Format> {
+        Some(self.format)
+    }
+
+    fn response_format(&self) -> Option<Format> {
+        Some(self.format)
+    }
+
+    async fn encoded_ok(&self, data: Vec<u8>) -> Self::Response {
+        LocalResponse::Ok(data)
+    }
+
+    async fn encoded_err(&self, data: Vec<u8>) -> Self::Response {
+        LocalResponse::Err(data)
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        match self.format.encode(&value) {
+            Ok(data) => LocalResponse::Ok(data),
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode value: {err}"))
+                    .await
+            }
+        }
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        match self.format.encode(&error) {
+            Ok(data) => LocalResponse::Err(data),
+            Err(err) => {
+                let message = format!("Cannot encode error: {err}");
+                LocalResponse::Err(self.format.encode(&message).unwrap_or_default())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sender for LocalTransport {
+    type Request = LocalRequest;
+    type Response = LocalResponse;
+
+    async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
+    where
+        E: From<String>,
+    {
+        let (reply, res) = oneshot::channel();
+        if self.sender.send((req, reply)).is_err() {
+            return Err(E::from("Server stopped".to_string()));
+        }
+        res.await.map_err(|_| E::from("Server stopped".to_string()))
+    }
+
+    async fn encode_request<T, E>(&self, req: Request<T>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let data = match self.format.encode(&req.data) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+        Ok(LocalRequest {
+            id: req.id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            certificate: self.certificate.clone(),
+            data,
+        })
+    }
+
+    async fn decode_response<T, E>(&self, res: Self::Response) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        match res {
+            LocalResponse::Ok(data) => match self.format.decode::<T>(&data) {
+                Ok(ok) => Ok(ok),
+                Err(err) => Err(E::from(format!("Cannot deserialize value: {}", err))),
+            },
+            LocalResponse::Err(data) => match self.format.decode::<E>(&data) {
+                Ok(err) => Err(err),
+                Err(err) => Err(E::from(format!("Cannot deserialize error: {}", err))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::Client;
+
+    /// Handler which echoes a structured payload
+    #[derive(Clone)]
+    struct Echo;
+
+    /// Payload which does not deserialize as it serializes
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Lossy {
+        /// Skipped field, missing when decoded
+        #[serde(skip_serializing)]
+        value: String,
+    }
+
+    #[async_trait]
+    impl Handler for Echo {
+        async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+        where
+            R: Receiver,
+        {
+            let req = match receiver.decode_request::<String>(request).await {
+                Ok(ok) => ok,
+                Err(err) => return receiver.encode_err(err).await,
+            };
+            match req.method.as_str() {
+                "echo" => match receiver
+                    .decode_payload::<(String, u8), String>(&req.data)
+                    .await
+                {
+                    Ok(data) => receiver.encode_ok(data).await,
+                    Err(err) => receiver.encode_err(err).await,
+                },
+                "whoami" => receiver.encode_ok(req.credentials().is_some()).await,
+                "lossy" => match receiver.decode_payload::<Lossy, String>(&req.data).await {
+                    Ok(data) => receiver.encode_ok(data.value).await,
+                    Err(err) => receiver.encode_err(err).await,
+                },
+                m => receiver.encode_err(format!("Invalid method: {m}")).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn local_roundtrip() {
+        for format in Format::ALL {
+            let transport = LocalTransport::new().format(format);
+
+            // Queued until the server starts
+            let client = Client::new(transport.clone());
+            let req = Request::new("echo", None, ("hello".to_string(), 42u8));
+            let call = tokio::spawn(async move {
+                client.call::<(String, u8), (String, u8), String>(req).await
+            });
+            let server = Server::new(transport.clone(), Echo);
+            tokio::spawn(server.start());
+            assert_eq!(call.await.unwrap().unwrap(), ("hello".to_string(), 42));
+
+            let again = Server::new(transport, Echo);
+            assert!(again.start().await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn local_encoding() {
+        let client = Client::new(LocalTransport::serve(Echo));
+
+        // The payload goes through the codec
+        let req = Request::new(
+            "lossy",
+            None,
+            Lossy {
+                value: "lost".to_string(),
+            },
+        );
+        let err = client.call::<Lossy, String, String>(req).await.unwrap_err();
+        assert!(err.starts_with("Invalid data"), "{err}");
+
+        let req = Request::new("echo", None, ("hello".to_string(), 1000u16));
+        assert!(client.call::<_, (String, u8), String>(req).await.is_err());
+
+        let req = Request::new("whoami", None, ());
+        assert!(!client.call::<(), bool, String>(req).await.unwrap());
+        let client = Client::new(LocalTransport::serve(Echo).certificate("client"));
+        let req = Request::new("whoami", None, ());
+        assert!(client.call::<(), bool, String>(req).await.unwrap());
+    }
+}
@@ -0,0 +1,511 @@
+//! Unix socket transport
+
+use std::{
+    fs,
+    future::{self, Future},
+    io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use hyper::{
+    client::connect::{Connected, Connection},
+    server::accept::Accept,
+    Uri,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    client::Sender,
+    codec::Format,
+    http::{self, HttpTransport, Negotiate, Routes},
+    limits::Limits,
+    server::{Handler, Receiver, RequestError, Server},
+    tls::{ClientCertificate, PeerCertificate},
+    Request, Response,
+};
+
+/// Default permissions of the socket file (owner only)
+const DEFAULT_MODE: u32 = 0o600;
+
+/// Unix socket transport
+///
+/// The transport speaks the same HTTP protocol as [HttpTransport], over a Unix socket.
+/// No TCP port is opened: the access is controlled by the permissions of the socket file
+/// (see [UnixTransportBuilder::mode]).
+#[derive(Debug, Clone)]
+pub struct UnixTransport {
+    /// Socket path
+    path: Option<PathBuf>,
+    /// HTTP client
+    client: hyper::Client<UnixConnector>,
+    /// HTTP encoding
+    http: HttpTransport,
+    /// Request timeout
+    timeout: Option<Duration>,
+    /// Permissions of the socket file
+    mode: u32,
+}
+
+impl UnixTransport {
+    /// Base URL of the requests (the host is ignored)
+    const URL: &str = "http://localhost";
+
+    /// Instantiates a new [UnixTransport]
+    ///
+    /// To send requests, the socket path must be set via [UnixTransport::builder].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a [UnixTransportBuilder]
+    pub fn builder() -> UnixTransportBuilder {
+        UnixTransportBuilder::default()
+    }
+}
+
+impl Default for UnixTransport {
+    fn default() -> Self {
+        UnixTransportBuilder::default().build()
+    }
+}
+
+/// [UnixTransport] builder
+#[derive(Debug, Clone, Default)]
+pub struct UnixTransportBuilder {
+    /// Socket path
+    path: Option<PathBuf>,
+    /// Request timeout
+    timeout: Option<Duration>,
+    /// Permissions of the socket file
+    mode: Option<u32>,
+    /// Format of the requests
+    format: Format,
+    /// Server limits
+    limits: Limits,
+    /// HTTP routes
+    routes: Routes,
+}
+
+impl UnixTransportBuilder {
+    /// Sets the path of the server socket
+    pub fn path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the request timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the permissions of the socket file created by the server (eg. `0o660`)
+    ///
+    /// Only the users allowed to write to the socket file can connect.
+    /// Defaults to `0o600` (owner only).
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the format of the requests (JSON by default)
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the limits of the server (see [Limits::default])
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Serves the `GET` requests of a path with a function (see [HttpTransportBuilder::route](crate::http::HttpTransportBuilder::route))
+    pub fn route<F, Fut>(mut self, path: impl AsRef<str>, f: F) -> Self
+    where
+        F: Fn(hyper::Request<hyper::Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+    {
+        self.routes.insert(path, f);
+        self
+    }
+
+    /// Builds the [UnixTransport]
+    pub fn build(self) -> UnixTransport {
+        let connector = UnixConnector {
+            path: self.path.clone().map(Arc::new),
+        };
+        let http = HttpTransport::builder()
+            .url(UnixTransport::URL)
+            .format(self.format)
+            .limits(self.limits)
+            .routes(self.routes)
+            .build::<String>()
+            .expect("valid URL");
+
+        UnixTransport {
+            path: self.path,
+            client: hyper::Client::builder().build(connector),
+            http,
+            timeout: self.timeout,
+            mode: self.mode.unwrap_or(DEFAULT_MODE),
+        }
+    }
+}
+
+impl<H> Server<H, UnixTransport>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+{
+    /// Starts the server on a Unix socket
+    ///
+    /// A stale socket file is replaced, but the server fails if another server
+    /// is listening on the socket.
+    pub async fn start(self, path: &Path) -> io::Result<()> {
+        self.start_with_shutdown(path, future::pending()).await
+    }
+
+    /// Starts the server on a Unix socket, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops accepting connections and returns once the
+    /// in-flight requests are answered.
+    pub async fn start_with_shutdown<S>(self, path: &Path, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let incoming = UnixIncoming::bind(path, self.receiver.mode).await?;
+        let http = self.receiver.http.clone();
+        http::serve(self.handler, self.receiver, incoming, &http, signal).await
+    }
+}
+
+impl Negotiate for UnixTransport {
+    fn negotiate(&self, headers: &hyper::HeaderMap) -> Self {
+        Self {
+            http: self.http.negotiate(headers),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl Receiver for UnixTransport {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        self.http.decode_request(req).await
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        self.http.decode_payload(data).await
+    }
+
+    fn payload_format(&self) -> Option<Format> {
+        self.http.payload_format()
+    }
+
+    fn response_format(&self) -> Option<Format> {
+        self.http.response_format()
+    }
+
+    async fn encoded_ok(&self, data: Vec<u8>) -> Self::Response {
+        self.http.encoded_ok(data).await
+    }
+
+    async fn encoded_err(&self, data: Vec<u8>) -> Self::Response {
+        self.http.encoded_err(data).await
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        self.http.encode_ok(value).await
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        self.http.encode_err(error).await
+    }
+
+    async fn encode_failure(&self, message: String) -> Self::Response {
+        self.http.encode_failure(message).await
+    }
+}
+
+#[async_trait]
+impl Sender for UnixTransport {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+
+    async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
+    where
+        E: From<String>,
+    {
+        let res = self.client.request(req);
+        let res = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, res).await {
+                Ok(ok) => ok,
+                Err(_) => {
+                    return Err(E::from(format!("Request timed out after {timeout:?}")));
+                }
+            },
+            None => res.await,
+        };
+        res.map_err(|err| E::from(err.to_string()))
+    }
+
+    async fn encode_request<T, E>(&self, req: Request<T>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        if self.path.is_none() {
+            return Err(E::from("Missing socket path".to_string()));
+        }
+        self.http.encode_request(req).await
+    }
+
+    async fn decode_response<T, E>(&self, res: Self::Response) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        self.http.decode_response(res).await
+    }
+}
+
+/// Connector to the server socket
+#[derive(Debug, Clone)]
+struct UnixConnector {
+    /// Socket path
+    path: Option<Arc<PathBuf>>,
+}
+
+impl hyper::service::Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move {
+            let path = path.ok_or_else(|| io::Error::other("Missing socket path"))?;
+            let stream = UnixStream::connect(&*path).await?;
+            Ok(UnixConnection(stream))
+        })
+    }
+}
+
+/// Client connection to the server socket
+struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl PeerCertificate for UnixStream {
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
+}
+
+/// Incoming Unix socket connections
+struct UnixIncoming {
+    /// Socket listener
+    listener: UnixListener,
+}
+
+impl UnixIncoming {
+    /// Binds to a socket path and sets the permissions of the socket file
+    ///
+    /// The missing parent directories are created with `0o700` permissions.
+    async fn bind(path: &Path, mode: u32) -> io::Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Not a socket: {}", path.display()),
+                ));
+            }
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("Socket already in use: {}", path.display()),
+                ));
+            }
+            // Stale socket from a previous run
+            fs::remove_file(path)?;
+        }
+        // The missing directories are only accessible by the owner
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+
+        // The socket is bound in a private directory, and moved in place once its
+        // permissions are set: it is never reachable with the permissions of the umask
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let private = dir.join(format!(".{name}.bind"));
+        if fs::symlink_metadata(&private).is_ok() {
+            // Left by a previous run
+            fs::remove_dir_all(&private)?;
+        }
+        fs::DirBuilder::new().mode(0o700).create(&private)?;
+        let bound = private.join(path.file_name().unwrap_or_default());
+        let res = UnixListener::bind(&bound).and_then(|listener| {
+            fs::set_permissions(&bound, fs::Permissions::from_mode(mode))?;
+            fs::rename(&bound, path)?;
+            Ok(listener)
+        });
+        let cleanup = fs::remove_dir_all(&private);
+        let listener = res?;
+        cleanup?;
+        Ok(Self { listener })
+    }
+}
+
+impl Accept for UnixIncoming {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.listener
+            .poll_accept(cx)
+            .map(|res| Some(res.map(|(stream, _addr)| stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+
+    /// Echo handler
+    #[derive(Clone)]
+    struct Echo;
+
+    #[async_trait]
+    impl Handler for Echo {
+        async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+        where
+            R: Receiver,
+        {
+            let req = match receiver.decode_request::<String>(request).await {
+                Ok(ok) => ok,
+                Err(err) => return receiver.encode_err(err).await,
+            };
+            match receiver.decode_payload::<String, String>(&req.data).await {
+                Ok(data) => receiver.encode_ok(format!("{}: {data}", req.method)).await,
+                Err(err) => receiver.encode_err(err).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_without_path() {
+        let transport = UnixTransport::new();
+        let req = Request::new("echo", None, ());
+        let res = transport.encode_request::<(), String>(req).await;
+        assert_eq!(res.unwrap_err(), "Missing socket path");
+    }
+
+    #[tokio::test]
+    async fn unix_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("run");
+        let path = dir.join("test.sock");
+
+        let server = Server::new(UnixTransport::builder().mode(0o660).build(), Echo);
+        let server_path = path.clone();
+        tokio::spawn(async move { server.start(&server_path).await.unwrap() });
+
+        let client = Client::new(
+            UnixTransport::builder()
+                .path(&path)
+                .format(Format::Cbor)
+                .build(),
+        );
+        let mut attempts = 0;
+        let res = loop {
+            let req = Request::new("echo", None, "hello".to_string());
+            match client.call::<String, String, String>(req).await {
+                Ok(ok) => break ok,
+                Err(err) if attempts == 50 => panic!("{err}"),
+                Err(_) => {
+                    attempts += 1;
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        };
+        assert_eq!(res, "echo: hello");
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A second server cannot take over the socket
+        let other = Server::new(UnixTransport::new(), Echo);
+        assert!(other.start(&path).await.is_err());
+    }
+}
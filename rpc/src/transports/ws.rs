@@ -0,0 +1,1009 @@
+//! WebSocket transport
+
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use hyper::{header, upgrade::Upgraded, HeaderMap};
+use rustls::ServerName;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, oneshot, Semaphore},
+    task::{AbortHandle, JoinSet},
+};
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::derive_accept_key,
+        protocol::{frame::coding::CloseCode, CloseFrame, Role, WebSocketConfig},
+        Error as WsError, Message,
+    },
+    WebSocketStream,
+};
+
+use crate::{
+    client::Sender,
+    codec::Format,
+    events::{Broker, Event, EventStream},
+    http::{self, HttpTransport, Negotiate},
+    new_request_id,
+    server::{Handler, Receiver, RequestError, Server},
+    tls::ClientCertificate,
+    Request, Response,
+};
+
+/// Service method which authorizes the subscriptions
+///
+/// The method receives the topic as payload. The subscription is registered if
+/// the method succeeds.
+pub const SUBSCRIBE_METHOD: &str = "subscribe";
+
+/// WebSocket transport
+///
+/// The calls are multiplexed over a single WebSocket connection, and matched to
+/// their responses by ID. The connection is opened on the first call, and reopened
+/// if it was closed.
+///
+/// Over the same connection, the server pushes the events published to the topics
+/// the client subscribed to (see [WsTransport::subscribe]).
+///
+/// The transport wraps a [HttpTransport], which holds the server URL (`http://`, or
+/// `https://` for TLS), the TLS configuration and the timeouts. The server also
+/// serves the plain HTTP requests, so HTTP and WebSocket clients share the same port.
+#[derive(Debug, Clone)]
+pub struct WsTransport {
+    /// HTTP transport
+    http: HttpTransport,
+    /// Events broker
+    broker: Broker,
+    /// ID of the call being handled
+    id: u64,
+    /// ID of the next call
+    next_id: Arc<AtomicU64>,
+    /// Connection to the server
+    conn: Arc<tokio::sync::Mutex<Option<Arc<Connection>>>>,
+}
+
+impl WsTransport {
+    /// Instantiates a new [WsTransport] over a configured [HttpTransport]
+    pub fn new(http: HttpTransport) -> Self {
+        Self {
+            http,
+            broker: Broker::default(),
+            id: 0,
+            next_id: Arc::new(AtomicU64::new(1)),
+            conn: Arc::default(),
+        }
+    }
+
+    /// Sets the broker whose events are pushed to the clients
+    pub fn broker(mut self, broker: Broker) -> Self {
+        self.broker = broker;
+        self
+    }
+
+    /// Subscribes to the events of a topic
+    ///
+    /// The server authorizes the subscription with the [SUBSCRIBE_METHOD] method of
+    /// the service, called with `token`, and again before each event: the stream ends
+    /// once the subscription is denied (eg. the token was revoked).
+    pub async fn subscribe<T, E>(
+        &self,
+        topic: impl AsRef<str>,
+        token: Option<String>,
+    ) -> Result<EventStream<T>, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let topic = topic.as_ref().to_string();
+        let conn = self.connection::<E>().await?;
+
+        // Listen before subscribing, not to miss any event
+        let (sender, receiver) = mpsc::unbounded_channel();
+        conn.listeners
+            .lock()
+            .unwrap()
+            .entry(topic.clone())
+            .or_default()
+            .push(sender);
+
+        let frame = WsFrame::Subscribe {
+            id: self.next_id(),
+            topic,
+            token,
+        };
+        let res = conn.request::<E>(frame, self.http.timeout()).await?;
+        self.decode_response::<(), E>(res).await?;
+        Ok(EventStream::new(receiver))
+    }
+
+    /// Returns the ID of the next call
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns a receiver for a call
+    fn for_call(&self, id: u64) -> Self {
+        Self { id, ..self.clone() }
+    }
+
+    /// Returns the connection to the server, opened if needed
+    async fn connection<E>(&self) -> Result<Arc<Connection>, E>
+    where
+        E: From<String>,
+    {
+        let mut conn = self.conn.lock().await;
+        if let Some(conn) = conn.as_ref().filter(|c| !c.sender.is_closed()) {
+            return Ok(conn.clone());
+        }
+
+        let connect = Connection::open(&self.http);
+        let new_conn = match self.http.timeout() {
+            Some(timeout) => match tokio::time::timeout(timeout, connect).await {
+                Ok(ok) => ok,
+                Err(_) => {
+                    return Err(E::from(format!("Connection timed out after {timeout:?}")));
+                }
+            },
+            None => connect.await,
+        };
+        let new_conn = Arc::new(new_conn.map_err(E::from)?);
+        *conn = Some(new_conn.clone());
+        Ok(new_conn)
+    }
+
+    /// Upgrades a HTTP request to a WebSocket connection
+    fn upgrade<H>(
+        &self,
+        handler: H,
+        req: hyper::Request<hyper::Body>,
+    ) -> hyper::Response<hyper::Body>
+    where
+        H: Handler + Send + Sync + 'static,
+    {
+        let key = match req.headers().get("Sec-WebSocket-Key") {
+            Some(key) => key.as_bytes().to_vec(),
+            None => {
+                let mut res = hyper::Response::new(hyper::Body::from("Missing WebSocket key"));
+                *res.status_mut() = hyper::StatusCode::BAD_REQUEST;
+                return res;
+            }
+        };
+        let certificate = req
+            .extensions()
+            .get::<ClientCertificate>()
+            .map(|c| c.0.clone());
+
+        let tasks = match req.extensions().get::<http::Tasks>() {
+            Some(tasks) => tasks.clone(),
+            None => {
+                let mut res = hyper::Response::new(hyper::Body::from("Upgrade not supported"));
+                *res.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+                return res;
+            }
+        };
+
+        // The connection is a task of the server, closed on shutdown
+        let receiver = self.clone();
+        let limits = self.http.limits();
+        let shutdown = tasks.shutdown();
+        tasks.spawn(async move {
+            if let Ok(upgraded) = hyper::upgrade::on(req).await {
+                let config = WebSocketConfig {
+                    max_message_size: Some(limits.max_body_size),
+                    max_frame_size: Some(limits.max_body_size),
+                    ..WebSocketConfig::default()
+                };
+                let socket =
+                    WebSocketStream::from_raw_socket(upgraded, Role::Server, Some(config)).await;
+                receiver
+                    .serve_socket(handler, socket, certificate, shutdown)
+                    .await;
+            }
+        });
+
+        let mut res = hyper::Response::new(hyper::Body::empty());
+        *res.status_mut() = hyper::StatusCode::SWITCHING_PROTOCOLS;
+        let headers = res.headers_mut();
+        headers.insert(header::UPGRADE, "websocket".parse().unwrap());
+        headers.insert(header::CONNECTION, "Upgrade".parse().unwrap());
+        headers.insert(
+            "Sec-WebSocket-Accept",
+            derive_accept_key(&key).parse().unwrap(),
+        );
+        res
+    }
+
+    /// Serves a WebSocket connection
+    ///
+    /// The calls are handled concurrently, up to the limit of the server. The connection
+    /// is pinged within the idle timeout of the server, not to be closed while waiting
+    /// for events. A message larger than the maximum body size closes the connection.
+    ///
+    /// Once the shutdown signal resolves, the connection is closed after answering the
+    /// in-flight calls. The tasks of the connection are aborted if it is dropped.
+    async fn serve_socket<H>(
+        &self,
+        handler: H,
+        socket: WebSocketStream<Upgraded>,
+        certificate: Option<String>,
+        shutdown: impl Future<Output = ()>,
+    ) where
+        H: Handler + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let (mut sink, mut stream) = socket.split();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+        let limits = self.http.limits();
+        let keepalive = limits.idle_timeout.map(|timeout| timeout / 2);
+        let calls = limits
+            .max_socket_calls
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let mut tasks = JoinSet::new();
+        tasks.spawn(async move {
+            loop {
+                let msg = match keepalive {
+                    Some(period) => match tokio::time::timeout(period, receiver.recv()).await {
+                        Ok(msg) => msg,
+                        Err(_) => Some(Message::Ping(vec![])),
+                    },
+                    None => receiver.recv().await,
+                };
+                let msg = match msg {
+                    Some(msg) => msg,
+                    None => break,
+                };
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut subscriptions = HashMap::<String, AbortHandle>::new();
+        tokio::pin!(shutdown);
+        loop {
+            let msg = tokio::select! {
+                msg = stream.next() => msg,
+                Some(_) = tasks.join_next() => continue,
+                _ = &mut shutdown => {
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "Server shutting down".into(),
+                    })));
+                    break;
+                }
+            };
+            let frame = match msg {
+                Some(msg) => msg,
+                None => break,
+            };
+            let frame = match frame {
+                Ok(Message::Text(text)) => serde_json::from_str::<WsFrame>(&text),
+                Ok(Message::Binary(data)) => serde_json::from_slice::<WsFrame>(&data),
+                Err(WsError::Capacity(err)) => {
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Size,
+                        reason: err.to_string().into(),
+                    })));
+                    break;
+                }
+                Ok(Message::Close(_)) | Err(_) => break,
+                Ok(_) => continue,
+            };
+            let frame = match frame {
+                Ok(ok) => ok,
+                Err(err) => {
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Protocol,
+                        reason: format!("Invalid frame: {err}").into(),
+                    })));
+                    break;
+                }
+            };
+
+            match frame {
+                WsFrame::Call {
+                    id,
+                    request_id,
+                    version,
+                    method,
+                    token,
+                    data,
+                } => {
+                    let call = WsCall {
+                        request_id: request_id.or_else(|| Some(new_request_id())),
+                        version,
+                        method,
+                        token,
+                        certificate: certificate.clone(),
+                        data,
+                    };
+                    let permit = match &calls {
+                        Some(calls) => match calls.clone().acquire_owned().await {
+                            Ok(ok) => Some(ok),
+                            // The semaphore is never closed
+                            Err(_) => break,
+                        },
+                        None => None,
+                    };
+                    let receiver = self.for_call(id);
+                    let handler = handler.clone();
+                    let sender = sender.clone();
+                    tasks.spawn(async move {
+                        let res = handler.handle(receiver, call).await;
+                        let _ = sender.send(res.to_server_message());
+                        drop(permit);
+                    });
+                }
+                WsFrame::Subscribe { id, topic, token } => {
+                    let call = WsCall {
+                        request_id: Some(new_request_id()),
+                        version: None,
+                        method: SUBSCRIBE_METHOD.to_string(),
+                        token,
+                        certificate: certificate.clone(),
+                        data: Value::String(topic.clone()),
+                    };
+                    let res = handler.handle(self.for_call(id), call.clone()).await;
+                    let subscribed = subscriptions
+                        .get(&topic)
+                        .is_some_and(|task| !task.is_finished());
+                    if matches!(res, WsFrame::Ok { .. }) && !subscribed {
+                        let events = self.broker.subscribe(&topic);
+                        let task = tasks.spawn(forward_events(
+                            handler.clone(),
+                            self.for_call(id),
+                            call,
+                            events,
+                            sender.clone(),
+                        ));
+                        subscriptions.insert(topic, task);
+                    }
+                    let _ = sender.send(res.to_server_message());
+                }
+                WsFrame::Unsubscribe { topic } => {
+                    if let Some(task) = subscriptions.remove(&topic) {
+                        task.abort();
+                    }
+                }
+                WsFrame::Ok { .. } | WsFrame::Err { .. } | WsFrame::Event { .. } => {}
+            }
+        }
+
+        // The in-flight calls are answered, then the writer ends
+        for task in subscriptions.into_values() {
+            task.abort();
+        }
+        drop(sender);
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+/// Forwards the events of a topic to a WebSocket connection
+///
+/// The subscription is authorized again before each event, with the call which
+/// subscribed. Once denied (eg. the token was revoked), the subscription ends with an
+/// `unsubscribe` frame.
+async fn forward_events<H>(
+    handler: Arc<H>,
+    receiver: WsTransport,
+    call: WsCall,
+    mut events: broadcast::Receiver<Event>,
+    sender: mpsc::UnboundedSender<Message>,
+) where
+    H: Handler + Send + Sync + 'static,
+{
+    loop {
+        match events.recv().await {
+            Ok(Event { topic, data }) => {
+                let call = WsCall {
+                    request_id: Some(new_request_id()),
+                    ..call.clone()
+                };
+                if !matches!(
+                    handler.handle(receiver.clone(), call).await,
+                    WsFrame::Ok { .. }
+                ) {
+                    let _ = sender.send(WsFrame::Unsubscribe { topic }.to_server_message());
+                    break;
+                }
+                let frame = WsFrame::Event { topic, data };
+                if sender.send(frame.to_server_message()).is_err() {
+                    break;
+                }
+            }
+            // Slow client, the oldest events were dropped
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Returns true if the request asks for a WebSocket connection
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    let has_token = |name: header::HeaderName, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    has_token(header::CONNECTION, "upgrade") && has_token(header::UPGRADE, "websocket")
+}
+
+impl<H> Server<H, WsTransport>
+where
+    H: Handler + Clone + Send + Sync + 'static,
+{
+    /// Starts the server
+    ///
+    /// The server listens over TLS if the HTTP transport has a server certificate.
+    /// The requests which are not WebSocket upgrades are served by the HTTP transport.
+    pub async fn start(self, addr: &SocketAddr) -> io::Result<()> {
+        self.start_with_shutdown(addr, future::pending()).await
+    }
+
+    /// Starts the server, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops accepting connections and returns once the
+    /// in-flight requests are answered.
+    pub async fn start_with_shutdown<S>(self, addr: &SocketAddr, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_with_shutdown(listener, signal).await
+    }
+
+    /// Serves the connections of a bound listener (eg. on port 0)
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        self.serve_with_shutdown(listener, future::pending()).await
+    }
+
+    /// Serves the connections of a bound listener, until the shutdown signal resolves
+    pub async fn serve_with_shutdown<S>(self, listener: TcpListener, signal: S) -> io::Result<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let http = self.receiver.http.clone();
+        let handler = self.handler;
+        let receiver = self.receiver;
+        http::listen(listener, &http, signal, move |req| {
+            let handler = handler.clone();
+            let receiver = receiver.clone();
+            async move {
+                if is_upgrade(req.headers()) {
+                    receiver.upgrade(handler, req)
+                } else {
+                    let receiver = receiver.http.negotiate(req.headers());
+                    handler.handle(receiver, req).await
+                }
+            }
+        })
+        .await
+    }
+}
+
+/// WebSocket frame
+///
+/// The frames are JSON text messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsFrame {
+    /// Call (client)
+    Call {
+        /// ID
+        id: u64,
+        /// Request ID, which correlates the client and server logs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// API version of the client
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u32>,
+        /// Method
+        method: String,
+        /// Authentication token
+        #[serde(default)]
+        token: Option<String>,
+        /// Payload
+        #[serde(default)]
+        data: Value,
+    },
+    /// Subscription to a topic (client)
+    Subscribe {
+        /// ID
+        id: u64,
+        /// Topic
+        topic: String,
+        /// Authentication token
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// End of a subscription (client, or server once the subscription is denied)
+    Unsubscribe {
+        /// Topic
+        topic: String,
+    },
+    /// Successful response (server)
+    Ok {
+        /// ID of the call
+        id: u64,
+        /// Value
+        data: Value,
+    },
+    /// Error response (server)
+    Err {
+        /// ID of the call
+        id: u64,
+        /// Error
+        data: Value,
+    },
+    /// Event of a subscribed topic (server)
+    Event {
+        /// Topic
+        topic: String,
+        /// Data
+        data: Value,
+    },
+}
+
+impl WsFrame {
+    /// Returns the WebSocket message of the frame
+    fn to_message(&self) -> Result<Message, String> {
+        serde_json::to_string(self)
+            .map(Message::Text)
+            .map_err(|err| format!("Cannot encode frame: {err}"))
+    }
+
+    /// Returns the WebSocket message of a frame sent by the server
+    ///
+    /// A response which cannot be encoded is answered with an `internal` error, and
+    /// another frame closes the connection.
+    fn to_server_message(&self) -> Message {
+        let err = match self.to_message() {
+            Ok(msg) => return msg,
+            Err(err) => err,
+        };
+        if let WsFrame::Ok { id, .. } | WsFrame::Err { id, .. } = self {
+            let frame = WsFrame::Err {
+                id: *id,
+                data: json!({ "kind": "internal", "message": err }),
+            };
+            if let Ok(msg) = frame.to_message() {
+                return msg;
+            }
+        }
+        Message::Close(Some(CloseFrame {
+            code: CloseCode::Error,
+            reason: err.into(),
+        }))
+    }
+}
+
+/// Call received by the server
+#[derive(Debug, Clone)]
+pub struct WsCall {
+    /// Request ID
+    pub request_id: Option<String>,
+    /// API version
+    pub version: Option<u32>,
+    /// Method
+    pub method: String,
+    /// Authentication token
+    pub token: Option<String>,
+    /// Subject of the client certificate of the connection
+    pub certificate: Option<String>,
+    /// Payload
+    pub data: Value,
+}
+
+/// Client connection
+#[derive(Debug)]
+struct Connection {
+    /// Outgoing messages
+    sender: mpsc::UnboundedSender<Message>,
+    /// Calls waiting for a response
+    pending: Arc<Mutex<HashMap<u64, oneshot::Sender<WsFrame>>>>,
+    /// Event listeners, by topic
+    listeners: Arc<Mutex<HashMap<String, Vec<mpsc::UnboundedSender<Event>>>>>,
+}
+
+/// Client stream (TCP or TLS)
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S> Stream for S where S: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl Connection {
+    /// Opens a connection to the server of a HTTP transport
+    async fn open(http: &HttpTransport) -> Result<Self, String> {
+        let url = http.url().ok_or_else(|| "Missing server URL".to_string())?;
+        let host = url
+            .host()
+            .ok_or_else(|| format!("Missing host in URL: {url}"))?;
+        let secure = url.scheme_str() == Some("https");
+        let port = url.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .map_err(|err| format!("Cannot connect to {host}:{port}: {err}"))?;
+        let stream: Box<dyn Stream> = if secure {
+            let name = ServerName::try_from(host)
+                .map_err(|err| format!("Invalid server name '{host}': {err}"))?;
+            let tls = TlsConnector::from(http.client_tls())
+                .connect(name, tcp)
+                .await
+                .map_err(|err| format!("TLS handshake failed: {err}"))?;
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+
+        let ws_url = format!(
+            "{}://{}:{port}{}",
+            if secure { "wss" } else { "ws" },
+            host,
+            url.path()
+        );
+        let (socket, _) = tokio_tungstenite::client_async(ws_url, stream)
+            .await
+            .map_err(|err| format!("WebSocket handshake failed: {err}"))?;
+        let (mut sink, mut stream) = socket.split();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let conn = Self {
+            sender: sender.clone(),
+            pending: Arc::default(),
+            listeners: Arc::default(),
+        };
+        let pending = conn.pending.clone();
+        let listeners = conn.listeners.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                let frame = match msg {
+                    Message::Text(text) => serde_json::from_str::<WsFrame>(&text),
+                    Message::Binary(data) => serde_json::from_slice::<WsFrame>(&data),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                match frame {
+                    Ok(frame @ (WsFrame::Ok { id, .. } | WsFrame::Err { id, .. })) => {
+                        if let Some(call) = pending.lock().unwrap().remove(&id) {
+                            let _ = call.send(frame);
+                        }
+                    }
+                    // The event streams of the topic end
+                    Ok(WsFrame::Unsubscribe { topic }) => {
+                        listeners.lock().unwrap().remove(&topic);
+                    }
+                    Ok(WsFrame::Event { topic, data }) => {
+                        let mut listeners = listeners.lock().unwrap();
+                        if let Some(topic_listeners) = listeners.get_mut(&topic) {
+                            let event = Event {
+                                topic: topic.clone(),
+                                data,
+                            };
+                            topic_listeners.retain(|l| l.send(event.clone()).is_ok());
+                            if topic_listeners.is_empty() {
+                                listeners.remove(&topic);
+                                let frame = WsFrame::Unsubscribe { topic };
+                                if let Ok(msg) = frame.to_message() {
+                                    let _ = sender.send(msg);
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // The pending calls fail and the event streams end
+            writer.abort();
+            pending.lock().unwrap().clear();
+            listeners.lock().unwrap().clear();
+        });
+
+        Ok(conn)
+    }
+
+    /// Sends a frame and waits for its response
+    async fn request<E>(
+        &self,
+        frame: WsFrame,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<WsFrame, E>
+    where
+        E: From<String>,
+    {
+        let id = match &frame {
+            WsFrame::Call { id, .. } | WsFrame::Subscribe { id, .. } => *id,
+            _ => return Err(E::from("Not a request frame".to_string())),
+        };
+        let msg = frame.to_message().map_err(E::from)?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        if self.sender.send(msg).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(E::from("Connection closed".to_string()));
+        }
+
+        let res = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, receiver).await {
+                Ok(ok) => ok,
+                Err(_) => {
+                    self.pending.lock().unwrap().remove(&id);
+                    return Err(E::from(format!("Request timed out after {timeout:?}")));
+                }
+            },
+            None => receiver.await,
+        };
+        res.map_err(|_| E::from("Connection closed".to_string()))
+    }
+}
+
+#[async_trait]
+impl Receiver for WsTransport {
+    type Request = WsCall;
+    type Response = WsFrame;
+
+    async fn decode_request<E>(&self, req: Self::Request) -> Result<Request<Vec<u8>>, E>
+    where
+        E: From<RequestError>,
+    {
+        let data = match serde_json::to_vec(&req.data) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(RequestError::new(
+                    "invalid_input",
+                    format!("Invalid data: {}", err),
+                )));
+            }
+        };
+
+        Ok(Request {
+            id: req.request_id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            certificate: req.certificate,
+            data,
+        })
+    }
+
+    async fn decode_payload<T, E>(&self, data: &[u8]) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<String>,
+    {
+        serde_json::from_slice::<T>(data).map_err(|err| E::from(format!("Invalid data: {}", err)))
+    }
+
+    fn payload_format(&self) -> Option<Format> {
+        Some(Format::Json)
+    }
+
+    async fn encode_ok<T>(&self, value: T) -> Self::Response
+    where
+        T: Serialize + Send,
+    {
+        match serde_json::to_value(&value) {
+            Ok(data) => WsFrame::Ok { id: self.id, data },
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode value: {err}"))
+                    .await
+            }
+        }
+    }
+
+    async fn encode_err<E>(&self, error: E) -> Self::Response
+    where
+        E: Serialize + Send,
+    {
+        match serde_json::to_value(&error) {
+            Ok(data) => WsFrame::Err { id: self.id, data },
+            Err(err) => {
+                self.encode_failure(format!("Cannot encode error: {err}"))
+                    .await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sender for WsTransport {
+    type Request = WsFrame;
+    type Response = WsFrame;
+
+    async fn send<E>(&self, req: Self::Request) -> Result<Self::Response, E>
+    where
+        E: From<String>,
+    {
+        let conn = self.connection::<E>().await?;
+        conn.request(req, self.http.timeout()).await
+    }
+
+    async fn encode_request<T, E>(&self, req: Request<T>) -> Result<Self::Request, E>
+    where
+        T: Serialize + Send,
+        E: From<String>,
+    {
+        let data = match serde_json::to_value(&req.data) {
+            Ok(ok) => ok,
+            Err(err) => {
+                return Err(E::from(format!("Cannot encode value: {}", err)));
+            }
+        };
+        Ok(WsFrame::Call {
+            id: self.next_id(),
+            request_id: req.id,
+            version: req.version,
+            method: req.method,
+            token: req.token,
+            data,
+        })
+    }
+
+    async fn decode_response<T, E>(&self, res: Self::Response) -> Response<T, E>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        match res {
+            WsFrame::Ok { data, .. } => serde_json::from_value::<T>(data)
+                .map_err(|err| E::from(format!("Cannot deserialize value: {}", err))),
+            WsFrame::Err { data, .. } => match serde_json::from_value::<E>(data.clone()) {
+                Ok(err) => Err(err),
+                Err(_) => Err(E::from(match data {
+                    Value::String(message) => message,
+                    data => data.to_string(),
+                })),
+            },
+            _ => Err(E::from("Unexpected response frame".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::Client;
+
+    /// Handler which echoes the payload, and authorizes the subscriptions with a token
+    #[derive(Clone)]
+    struct Echo;
+
+    #[async_trait]
+    impl Handler for Echo {
+        async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
+        where
+            R: Receiver,
+        {
+            let req = match receiver.decode_request::<String>(request).await {
+                Ok(ok) => ok,
+                Err(err) => return receiver.encode_err(err).await,
+            };
+            match req.method.as_str() {
+                SUBSCRIBE_METHOD if req.token.as_deref() == Some("secret") => {
+                    receiver.encode_ok(()).await
+                }
+                SUBSCRIBE_METHOD => receiver.encode_err("Invalid token".to_string()).await,
+                _ => match receiver.decode_payload::<String, String>(&req.data).await {
+                    Ok(data) => receiver.encode_ok(format!("{}: {data}", req.method)).await,
+                    Err(err) => receiver.encode_err(err).await,
+                },
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_and_events() {
+        let (listener, addr) = crate::testing::listener().await;
+        let broker = Broker::new();
+        let server = Server::new(
+            WsTransport::new(HttpTransport::new()).broker(broker.clone()),
+            Echo,
+        );
+        tokio::spawn(async move { server.serve(listener).await.unwrap() });
+
+        let http = HttpTransport::builder()
+            .url(format!("http://{addr}"))
+            .timeout(Duration::from_secs(5))
+            .build::<String>()
+            .unwrap();
+        let transport = WsTransport::new(http.clone());
+        let client = Client::new(transport.clone());
+
+        let req = Request::new("echo", None, "hello".to_string());
+        let res = client.call::<String, String, String>(req).await;
+        assert_eq!(res.unwrap(), "echo: hello");
+
+        // Concurrent calls over the same connection
+        let calls = (0..10).map(|i| {
+            let req = Request::new("echo", None, i.to_string());
+            client.call::<String, String, String>(req)
+        });
+        let res = futures_util::future::join_all(calls).await;
+        for (i, res) in res.into_iter().enumerate() {
+            assert_eq!(res.unwrap(), format!("echo: {i}"));
+        }
+
+        // Plain HTTP on the same port
+        let res = Client::new(http)
+            .call::<String, String, String>(Request::new("echo", None, "http".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(res, "echo: http");
+
+        // Subscriptions
+        let err = transport
+            .subscribe::<i64, String>("counter", None)
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Invalid token");
+        let mut events = transport
+            .subscribe::<i64, String>("counter", Some("secret".to_string()))
+            .await
+            .unwrap();
+        broker.publish("other", &0).unwrap();
+        broker.publish("counter", &1).unwrap();
+        broker.publish("counter", &2).unwrap();
+        assert_eq!(events.next().await, Some(1));
+        assert_eq!(events.next().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn message_too_large() {
+        let (listener, addr) = crate::testing::listener().await;
+        let limits = crate::Limits {
+            max_body_size: 128,
+            ..crate::Limits::default()
+        };
+        let http = HttpTransport::builder()
+            .limits(limits)
+            .build::<String>()
+            .unwrap();
+        let server = Server::new(WsTransport::new(http), Echo);
+        tokio::spawn(async move { server.serve(listener).await.unwrap() });
+
+        let http = HttpTransport::builder()
+            .url(format!("http://{addr}"))
+            .timeout(Duration::from_secs(5))
+            .build::<String>()
+            .unwrap();
+        let client = Client::new(WsTransport::new(http));
+
+        let req = Request::new("echo", None, "hello".to_string());
+        client.call::<String, String, String>(req).await.unwrap();
+
+        // The connection is closed, and reopened by the next call
+        let req = Request::new("echo", None, "a".repeat(256));
+        assert!(client.call::<String, String, String>(req).await.is_err());
+        let req = Request::new("echo", None, "hello".to_string());
+        let res = client.call::<String, String, String>(req).await.unwrap();
+        assert_eq!(res, "echo: hello");
+    }
+}
@@ -0,0 +1,92 @@
+//! API versions
+
+use std::ops::RangeInclusive;
+
+use async_trait::async_trait;
+
+use crate::{
+    middleware::{Middleware, Next},
+    server::{Receiver, RequestError},
+    Request,
+};
+
+/// Middleware which rejects the requests of unsupported API versions
+///
+/// A request with a version outside of the supported range is answered with an
+/// `incompatible_version` error, before its payload is decoded. A request without
+/// version (eg. sent with `curl`) is handled as is.
+#[derive(Debug, Clone)]
+pub struct Versions {
+    /// Supported versions
+    supported: RangeInclusive<u32>,
+}
+
+impl Versions {
+    /// Instantiates new [Versions], which support the `supported` range
+    pub fn new(supported: RangeInclusive<u32>) -> Self {
+        Self { supported }
+    }
+
+    /// Returns the supported versions
+    pub fn supported(&self) -> &RangeInclusive<u32> {
+        &self.supported
+    }
+
+    /// Returns true if the version is supported
+    pub fn is_supported(&self, version: u32) -> bool {
+        self.supported.contains(&version)
+    }
+}
+
+#[async_trait]
+impl Middleware for Versions {
+    async fn handle<R>(&self, req: Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+    where
+        R: Receiver,
+    {
+        match req.version {
+            Some(version) if !self.is_supported(version) => {
+                let err = RequestError::new(
+                    "incompatible_version",
+                    format!(
+                        "API version {version} is not supported (supported: {} to {})",
+                        self.supported.start(),
+                        self.supported.end()
+                    ),
+                );
+                next.receiver().encode_err(err).await
+            }
+            _ => next.run(req).await.response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{local::LocalTransport, Client, Router, Server};
+
+    #[tokio::test]
+    async fn incompatible_version() {
+        let router = Router::new().method("status", |_ctx, _: ()| async { Ok::<_, String>(()) });
+        let transport = LocalTransport::new();
+        let server = Server::new(transport.clone(), router).layer(Versions::new(2..=3));
+        tokio::spawn(server.start());
+        let client = Client::new(transport);
+
+        for version in [None, Some(2), Some(3)] {
+            let mut req = Request::new("status", None, ());
+            req.version = version;
+            client.call::<_, (), Value>(req).await.unwrap();
+        }
+        for version in [1, 4] {
+            let req = Request::new("status", None, ()).with_version(version);
+            let err = client.call::<_, (), Value>(req).await.unwrap_err();
+            assert_eq!(err["kind"], "incompatible_version");
+            let message = format!("API version {version} is not supported (supported: 2 to 3)");
+            assert_eq!(err["message"], message);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Authorization
+//!
+//! A request is authenticated as a [Principal]: a user, or a service token. A
+//! user has access to the resources of its organizations, within its [Role]. A
+//! service token only has access to its organization (or project), within its
+//! scope and environment. Beyond these, the [Grant]s of a principal on a project
+//! give access to the project and its secrets.
+
+use service::{Error, Grant, Permission, Role, ServiceToken};
+
+/// Authenticated caller
+#[derive(Debug, Clone)]
+pub(crate) enum Principal {
+    /// User
+    User {
+        /// User ID
+        id: i64,
+        /// Session of the token, [None] for a client certificate
+        session_id: Option<i64>,
+    },
+    /// Service token
+    ServiceToken(ServiceToken),
+}
+
+/// Kind of access to a resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Read
+    Read,
+    /// Create, update or delete
+    Write,
+    /// Manage the members, service tokens, grants and read the audit trail
+    Admin,
+}
+
+impl Access {
+    /// Returns the minimum role of a user for this access to a resource
+    pub(crate) fn min_role(self, resource: Resource<'_>) -> Role {
+        match (self, resource) {
+            (Access::Read, _) => Role::Viewer,
+            (Access::Write, Resource::Secret { .. }) => Role::Member,
+            (Access::Write, Resource::Organization(_)) => Role::Owner,
+            (Access::Write, _) | (Access::Admin, _) => Role::Admin,
+        }
+    }
+
+    /// Returns the minimum permission of a grant for this access to a resource
+    ///
+    /// Only an admin grant manages a project.
+    fn min_permission(self, resource: Resource<'_>) -> Permission {
+        match (self, resource) {
+            (Access::Read, _) => Permission::Read,
+            (Access::Write, Resource::Secret { .. }) => Permission::Write,
+            (Access::Write, _) | (Access::Admin, _) => Permission::Admin,
+        }
+    }
+
+    /// Returns the name of the access
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Admin => "admin",
+        }
+    }
+}
+
+/// Resource accessed by a request
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Resource<'a> {
+    /// Organization
+    Organization(&'a str),
+    /// Project
+    Project {
+        /// Organization ID
+        org_id: &'a str,
+        /// Project ID
+        project_id: &'a str,
+    },
+    /// Secret
+    Secret {
+        /// Organization ID
+        org_id: &'a str,
+        /// Project ID
+        project_id: Option<&'a str>,
+        /// Environment
+        environment: Option<&'a str>,
+        /// Key
+        key: &'a str,
+    },
+    /// Secret events of an organization, or of a project
+    Events {
+        /// Organization ID
+        org_id: &'a str,
+        /// Project ID
+        project_id: Option<&'a str>,
+    },
+}
+
+impl Resource<'_> {
+    /// Returns the ID of the organization of the resource
+    pub(crate) fn org_id(&self) -> &str {
+        match self {
+            Resource::Organization(org_id)
+            | Resource::Project { org_id, .. }
+            | Resource::Secret { org_id, .. }
+            | Resource::Events { org_id, .. } => org_id,
+        }
+    }
+
+    /// Returns the ID of the project of the resource, if any
+    pub(crate) fn project_id(&self) -> Option<&str> {
+        match self {
+            Resource::Organization(_) => None,
+            Resource::Project { project_id, .. } => Some(project_id),
+            Resource::Secret { project_id, .. } | Resource::Events { project_id, .. } => {
+                *project_id
+            }
+        }
+    }
+}
+
+impl Principal {
+    /// Returns the user ID, or fails for a service token
+    pub(crate) fn user_id(&self) -> Result<i64, Error> {
+        match self {
+            Principal::User { id, .. } => Ok(*id),
+            Principal::ServiceToken(_) => Err(Error::forbidden(
+                "Service tokens only have access to secrets",
+            )),
+        }
+    }
+
+    /// Returns the ID of the session of a user, [None] for a client certificate
+    pub(crate) fn session_id(&self) -> Option<i64> {
+        match self {
+            Principal::User { session_id, .. } => *session_id,
+            Principal::ServiceToken(_) => None,
+        }
+    }
+
+    /// Returns the ID of the service token, [None] for a user
+    pub(crate) fn service_token_id(&self) -> Option<&str> {
+        match self {
+            Principal::User { .. } => None,
+            Principal::ServiceToken(token) => Some(&token.id),
+        }
+    }
+
+    /// Checks the access to a resource
+    ///
+    /// `role` is the role of a user in the organization of the resource, [None]
+    /// if not a member. `grants` are the grants of the principal on the project
+    /// of the resource. A user without role nor grant gets the organization
+    /// reported as not found.
+    pub(crate) fn check(
+        &self,
+        access: Access,
+        resource: Resource<'_>,
+        role: Option<Role>,
+        grants: &[Grant],
+    ) -> Result<(), Error> {
+        match self.decide(access, resource, role, grants) {
+            Ok(_) => Ok(()),
+            // The organizations of the others are not disclosed
+            Err(_) if role.is_none() && grants.is_empty() && self.user_id().is_ok() => Err(
+                Error::not_found(format!("Organization not found: {}", resource.org_id())),
+            ),
+            Err(reason) => Err(Error::forbidden(reason)),
+        }
+    }
+
+    /// Decides on an access to a resource, see [Principal::check]
+    ///
+    /// Returns the reason of the decision: the role, scope or grant which allows
+    /// the access, or why none does.
+    pub(crate) fn decide(
+        &self,
+        access: Access,
+        resource: Resource<'_>,
+        role: Option<Role>,
+        grants: &[Grant],
+    ) -> Result<String, String> {
+        let denied = match self {
+            Principal::User { .. } => {
+                let min_role = access.min_role(resource);
+                match role {
+                    Some(role) if role >= min_role => {
+                        return Ok(format!("Role {role} in organization {}", resource.org_id()))
+                    }
+                    Some(role) => format!(
+                        "Role {role} has no {} access to this resource (requires {min_role})",
+                        access.as_str()
+                    ),
+                    None => format!("Not a member of organization {}", resource.org_id()),
+                }
+            }
+            Principal::ServiceToken(token) => {
+                if token_allows(token, access, resource) {
+                    return Ok(format!("Scope of service token {}", token.name));
+                }
+                format!(
+                    "Service token {} has no {} access to this resource",
+                    token.name,
+                    access.as_str()
+                )
+            }
+        };
+
+        match grants
+            .iter()
+            .find(|grant| grant_allows(grant, access, resource))
+        {
+            Some(grant) => Ok(format!(
+                "Grant {} ({} on project {}{}{})",
+                grant.id,
+                grant.permission,
+                grant.project_id,
+                grant
+                    .environment
+                    .as_ref()
+                    .map(|env| format!(", environment {env}"))
+                    .unwrap_or_default(),
+                grant
+                    .key_prefix
+                    .as_ref()
+                    .map(|prefix| format!(", keys {prefix}*"))
+                    .unwrap_or_default(),
+            )),
+            None if grants.is_empty() => Err(denied),
+            None => Err(format!("{denied}, and none of its grants matches")),
+        }
+    }
+}
+
+/// Returns true if the scope of a service token allows an access to a resource
+fn token_allows(token: &ServiceToken, access: Access, resource: Resource<'_>) -> bool {
+    match resource {
+        _ if access == Access::Admin => false,
+        // A project token does not read the rest of its organization
+        Resource::Organization(org_id) => {
+            access == Access::Read && org_id == token.org_id && token.project_id.is_none()
+        }
+        Resource::Project { org_id, project_id } => {
+            access == Access::Read && org_id == token.org_id && in_project(token, Some(project_id))
+        }
+        Resource::Secret {
+            org_id,
+            project_id,
+            environment,
+            ..
+        } => {
+            (access == Access::Read || token.scope.can_write())
+                && org_id == token.org_id
+                && in_project(token, project_id)
+                && token
+                    .environment
+                    .as_deref()
+                    .is_none_or(|env| environment == Some(env))
+        }
+        Resource::Events { org_id, project_id } => {
+            // The events of the other environments would leak their keys
+            access == Access::Read
+                && org_id == token.org_id
+                && in_project(token, project_id)
+                && token.environment.is_none()
+        }
+    }
+}
+
+/// Returns true if a project is within the project of a token
+fn in_project(token: &ServiceToken, project_id: Option<&str>) -> bool {
+    match &token.project_id {
+        Some(id) => project_id == Some(id.as_str()),
+        None => true,
+    }
+}
+
+/// Returns true if a grant allows an access to a resource
+///
+/// A grant narrowed to an environment or a key prefix only allows the access
+/// to these secrets, and reading the project.
+fn grant_allows(grant: &Grant, access: Access, resource: Resource<'_>) -> bool {
+    let narrowed = grant.environment.is_some() || grant.key_prefix.is_some();
+    resource.project_id() == Some(grant.project_id.as_str())
+        && grant.permission >= access.min_permission(resource)
+        && match resource {
+            Resource::Organization(_) => false,
+            Resource::Project { .. } => access == Access::Read || !narrowed,
+            Resource::Secret {
+                environment, key, ..
+            } => {
+                access != Access::Admin
+                    && grant
+                        .environment
+                        .as_deref()
+                        .is_none_or(|env| environment == Some(env))
+                    && grant
+                        .key_prefix
+                        .as_deref()
+                        .is_none_or(|prefix| key.starts_with(prefix))
+            }
+            // The events of the other secrets would leak their keys
+            Resource::Events { .. } => access == Access::Read && !narrowed,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use service::TokenScope;
+
+    use super::*;
+
+    /// Returns a service token principal
+    fn token(project_id: Option<&str>, environment: Option<&str>, scope: TokenScope) -> Principal {
+        Principal::ServiceToken(ServiceToken {
+            id: "1".to_string(),
+            name: "deploy".to_string(),
+            org_id: "1".to_string(),
+            project_id: project_id.map(String::from),
+            environment: environment.map(String::from),
+            scope,
+            created_at: 0,
+            expires_at: 1,
+            last_used_at: None,
+            revoked_at: None,
+        })
+    }
+
+    /// Returns a secret resource
+    fn secret<'a>(
+        org_id: &'a str,
+        project_id: Option<&'a str>,
+        env: Option<&'a str>,
+    ) -> Resource<'a> {
+        Resource::Secret {
+            org_id,
+            project_id,
+            environment: env,
+            key: "API_KEY",
+        }
+    }
+
+    #[test]
+    fn access() {
+        use Access::*;
+
+        let user = Principal::User {
+            id: 1,
+            session_id: None,
+        };
+        assert_eq!(user.user_id().unwrap(), 1);
+        let org_2 = Resource::Organization("2");
+        assert!(user.check(Write, org_2, Some(Role::Owner), &[]).is_ok());
+        assert!(user.check(Write, org_2, Some(Role::Admin), &[]).is_err());
+        assert!(user.check(Read, org_2, Some(Role::Viewer), &[]).is_ok());
+        assert!(user.check(Read, org_2, None, &[]).is_err());
+        let secret_2 = secret("2", None, None);
+        assert!(user.check(Write, secret_2, Some(Role::Member), &[]).is_ok());
+        assert!(user
+            .check(Write, secret_2, Some(Role::Viewer), &[])
+            .is_err());
+        assert!(user
+            .check(Admin, secret_2, Some(Role::Member), &[])
+            .is_err());
+
+        // Organization token
+        let org = token(None, None, TokenScope::Read);
+        assert!(org.user_id().is_err());
+        assert!(org
+            .check(Read, Resource::Organization("1"), None, &[])
+            .is_ok());
+        assert!(org
+            .check(Write, Resource::Organization("1"), None, &[])
+            .is_err());
+        assert!(org
+            .check(Admin, Resource::Organization("1"), None, &[])
+            .is_err());
+        assert!(org
+            .check(Read, Resource::Organization("2"), None, &[])
+            .is_err());
+        assert!(org
+            .check(Read, secret("1", Some("3"), Some("prod")), None, &[])
+            .is_ok());
+        assert!(org
+            .check(Write, secret("1", None, None), None, &[])
+            .is_err());
+        assert!(org.check(Read, secret("2", None, None), None, &[]).is_err());
+        let events = Resource::Events {
+            org_id: "1",
+            project_id: None,
+        };
+        assert!(org.check(Read, events, None, &[]).is_ok());
+
+        // Project and environment token
+        let project = token(Some("3"), Some("prod"), TokenScope::ReadWrite);
+        assert!(project
+            .check(Write, secret("1", Some("3"), Some("prod")), None, &[])
+            .is_ok());
+        assert!(project
+            .check(Read, secret("1", Some("3"), None), None, &[])
+            .is_err());
+        assert!(project
+            .check(Read, secret("1", Some("4"), Some("prod")), None, &[])
+            .is_err());
+        assert!(project
+            .check(Read, secret("1", None, Some("prod")), None, &[])
+            .is_err());
+        let project_3 = Resource::Project {
+            org_id: "1",
+            project_id: "3",
+        };
+        assert!(project.check(Read, project_3, None, &[]).is_ok());
+        assert!(project
+            .check(Read, Resource::Organization("1"), None, &[])
+            .is_err());
+        assert!(project.check(Write, project_3, None, &[]).is_err());
+        assert!(project.check(Read, events, None, &[]).is_err());
+    }
+
+    #[test]
+    fn grants() {
+        use Access::*;
+
+        let grant = Grant {
+            id: "1".to_string(),
+            subject: service::Subject::User("1".to_string()),
+            project_id: "3".to_string(),
+            environment: Some("staging".to_string()),
+            key_prefix: Some("API_".to_string()),
+            permission: Permission::Read,
+            created_at: 0,
+        };
+        let grants = [grant];
+        let user = Principal::User {
+            id: 1,
+            session_id: None,
+        };
+        let staging = secret("1", Some("3"), Some("staging"));
+        let reason = user.decide(Read, staging, None, &grants).unwrap();
+        assert_eq!(
+            reason,
+            "Grant 1 (read on project 3, environment staging, keys API_*)"
+        );
+        assert!(user.check(Write, staging, None, &grants).is_err());
+        let prod = secret("1", Some("3"), Some("prod"));
+        let reason = user.decide(Read, prod, None, &grants).unwrap_err();
+        assert_eq!(
+            reason,
+            "Not a member of organization 1, and none of its grants matches"
+        );
+        let err = user.check(Read, prod, None, &grants).unwrap_err();
+        assert_eq!(err.kind, service::ErrorKind::Forbidden);
+        let other_key = Resource::Secret {
+            org_id: "1",
+            project_id: Some("3"),
+            environment: Some("staging"),
+            key: "DB_URL",
+        };
+        assert!(user.check(Read, other_key, None, &grants).is_err());
+
+        // A narrowed grant only reads its project
+        let project_3 = Resource::Project {
+            org_id: "1",
+            project_id: "3",
+        };
+        assert!(user.check(Read, project_3, None, &grants).is_ok());
+        let admin = Grant {
+            permission: Permission::Admin,
+            ..grants[0].clone()
+        };
+        assert!(user
+            .check(Admin, project_3, None, std::slice::from_ref(&admin))
+            .is_err());
+        let admin = Grant {
+            environment: None,
+            key_prefix: None,
+            ..admin
+        };
+        assert!(user.check(Admin, project_3, None, &[admin]).is_ok());
+        assert!(user
+            .check(Read, Resource::Organization("1"), None, &grants)
+            .is_err());
+    }
+}
@@ -1,10 +1,13 @@
 //! Configuration
 
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, time::Duration};
 
+use ::service::rpc;
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 
+use crate::PasswordCost;
+
 /// App directory
 const APP_DIR: &str = "secrets";
 
@@ -14,13 +17,59 @@ const CONFIG_FILE: &str = "server.toml";
 /// Database file
 const DB_FILE: &str = "data.db";
 
+/// Unix socket file
+const SOCKET_FILE: &str = "server.sock";
+
+/// Default deadline to finish the in-flight requests on shutdown, in seconds
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+
 /// Server configuration
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
-    /// Port
-    pub port: u16,
+    /// TCP port
+    pub port: Option<u16>,
+    /// Path to the Unix socket
+    pub socket: Option<PathBuf>,
+    /// Permissions of the Unix socket file (eg. `0o660`, defaults to `0o600`)
+    pub socket_mode: Option<u32>,
     /// Path to the database file (`****.db`)
     pub database: PathBuf,
+    /// Path to the TLS certificate (PEM)
+    pub cert: Option<PathBuf>,
+    /// Path to the TLS private key (PEM)
+    pub key: Option<PathBuf>,
+    /// Path to the CA certificate (PEM), which signs the client certificates
+    pub ca_cert: Option<PathBuf>,
+    /// Path to the CA private key (PEM)
+    pub ca_key: Option<PathBuf>,
+    /// Maximum size of a request body, in bytes (defaults to 1 MiB)
+    pub max_body_size: Option<usize>,
+    /// Timeout to receive the headers of a request, in seconds (defaults to 10)
+    pub header_timeout: Option<u64>,
+    /// Timeout to receive the body of a request, in seconds (defaults to 30)
+    pub body_timeout: Option<u64>,
+    /// Timeout of an idle connection, in seconds (defaults to 60)
+    pub idle_timeout: Option<u64>,
+    /// Maximum number of concurrent connections (defaults to 1024)
+    pub max_connections: Option<usize>,
+    /// Maximum number of concurrent calls over a WebSocket connection (defaults to 64)
+    pub max_socket_calls: Option<usize>,
+    /// Deadline to finish the in-flight requests on shutdown, in seconds (defaults to 30)
+    pub shutdown_timeout: Option<u64>,
+    /// Path of the Prometheus metrics (eg. `/metrics`), served on the TCP port and the Unix socket
+    pub metrics_path: Option<String>,
+    /// Separate TCP port of the metrics, served without TLS
+    pub metrics_port: Option<u16>,
+    /// TCP port of the JSON-RPC 2.0 endpoint, served with the TLS of the TCP port
+    pub jsonrpc_port: Option<u16>,
+    /// Memory cost of the password hashes, in KiB (defaults to 19456)
+    pub password_memory: Option<u32>,
+    /// Number of iterations of the password hashes (defaults to 2)
+    pub password_iterations: Option<u32>,
+    /// Degree of parallelism of the password hashes (defaults to 1)
+    pub password_parallelism: Option<u32>,
+    /// Lifetime of the sessions, in seconds (defaults to 30 days)
+    pub session_ttl: Option<u64>,
 }
 
 impl Config {
@@ -48,6 +97,65 @@ impl Config {
         config_file()
     }
 
+    /// Returns the default path to the Unix socket
+    pub fn socket_file(&self) -> anyhow::Result<PathBuf> {
+        socket_file()
+    }
+
+    /// Returns the limits of the server connections
+    pub fn limits(&self) -> rpc::Limits {
+        let defaults = rpc::Limits::default();
+        rpc::Limits {
+            max_body_size: self.max_body_size.unwrap_or(defaults.max_body_size),
+            header_timeout: self
+                .header_timeout
+                .map(Duration::from_secs)
+                .or(defaults.header_timeout),
+            body_timeout: self
+                .body_timeout
+                .map(Duration::from_secs)
+                .or(defaults.body_timeout),
+            idle_timeout: self
+                .idle_timeout
+                .map(Duration::from_secs)
+                .or(defaults.idle_timeout),
+            max_connections: self.max_connections.or(defaults.max_connections),
+            max_socket_calls: self.max_socket_calls.or(defaults.max_socket_calls),
+        }
+    }
+
+    /// Returns the deadline to finish the in-flight requests on shutdown
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT))
+    }
+
+    /// Returns the cost of the password hashes
+    pub fn password_cost(&self) -> PasswordCost {
+        let defaults = PasswordCost::default();
+        PasswordCost {
+            memory: self.password_memory.unwrap_or(defaults.memory),
+            iterations: self.password_iterations.unwrap_or(defaults.iterations),
+            parallelism: self.password_parallelism.unwrap_or(defaults.parallelism),
+        }
+    }
+
+    /// Returns the lifetime of the sessions
+    pub fn session_ttl(&self) -> Duration {
+        self.session_ttl
+            .map_or(crate::service::DEFAULT_SESSION_TTL, Duration::from_secs)
+    }
+
+    /// Returns the path of the metrics, [None] if disabled
+    ///
+    /// The metrics are enabled by a path or a port, and default to `/metrics`.
+    pub fn metrics_path(&self) -> Option<&str> {
+        match (&self.metrics_path, self.metrics_port) {
+            (Some(path), _) => Some(path),
+            (None, Some(_)) => Some(crate::DEFAULT_METRICS_PATH),
+            (None, None) => None,
+        }
+    }
+
     /// Returns as TOML
     pub fn toml(&self) -> anyhow::Result<String> {
         Ok(toml::to_string(self)?)
@@ -68,8 +176,28 @@ impl Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            port: 6666,
+            port: Some(6666),
+            socket: None,
+            socket_mode: None,
             database: db_file().unwrap(),
+            cert: None,
+            key: None,
+            ca_cert: None,
+            ca_key: None,
+            max_body_size: None,
+            header_timeout: None,
+            body_timeout: None,
+            idle_timeout: None,
+            max_connections: None,
+            max_socket_calls: None,
+            shutdown_timeout: None,
+            metrics_path: None,
+            metrics_port: None,
+            jsonrpc_port: None,
+            password_memory: None,
+            password_iterations: None,
+            password_parallelism: None,
+            session_ttl: None,
         }
     }
 }
@@ -91,6 +219,11 @@ fn data_dir() -> anyhow::Result<PathBuf> {
     Ok(data_dir.join(APP_DIR))
 }
 
+/// Returns the path to the Unix socket
+fn socket_file() -> anyhow::Result<PathBuf> {
+    Ok(data_dir()?.join(SOCKET_FILE))
+}
+
 /// Returns the path to the database file
 fn db_file() -> anyhow::Result<PathBuf> {
     Ok(data_dir()?.join(DB_FILE))
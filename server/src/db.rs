@@ -1,41 +1,88 @@
 //! Database
 
-use std::path::Path;
+use std::{fs, path::Path, str::FromStr};
 
 use anyhow::anyhow;
-use sqlx::{sqlite::SqlitePoolOptions, Executor, Pool, Sqlite};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Executor, Pool, Sqlite,
+};
 
+pub mod audit;
+pub mod certificates;
+pub mod grants;
+pub mod members;
 pub mod orgs;
 pub mod projects;
 pub mod secrets;
+pub mod service_tokens;
+pub mod sessions;
+pub mod teams;
 pub mod users;
 
 /// DB connection pool
 pub type DbConn = Pool<Sqlite>;
 
+/// Version of the schema, stored as the `user_version` of the database
+pub const SCHEMA_VERSION: i64 = 6;
+
 /// Returns a database connection pool
 pub async fn conn_pool(db_path: &Path) -> anyhow::Result<DbConn> {
     let db_path_str = db_path.to_str().ok_or_else(|| anyhow!("Invalid DB path"))?;
     let db_conn_str = format!("sqlite:{db_path_str}");
-    Ok(SqlitePoolOptions::new().connect(&db_conn_str).await?)
+    if let Some(dir) = db_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let options = SqliteConnectOptions::from_str(&db_conn_str)?.create_if_missing(true);
+    Ok(SqlitePoolOptions::new().connect_with(options).await?)
 }
 
 /// Initializes the DB
 ///
-/// This creates the schema
+/// This creates the schema (or migrates it from a previous version), and sets its version
 pub async fn init(db: &DbConn) -> anyhow::Result<()> {
     db.execute("PRAGMA foreign_keys = ON;").await?;
+    let version = schema_version(db).await?;
+    if version < 2 {
+        users::migrate_v1(db).await?;
+    }
+    if version < 3 {
+        sessions::migrate_v2(db).await?;
+    }
+    if version < 4 {
+        secrets::migrate_v3(db).await?;
+    }
     users::create_table(db).await?;
     orgs::create_table(db).await?;
     projects::create_table(db).await?;
     secrets::create_table(db).await?;
+    sessions::create_table(db).await?;
+    certificates::create_table(db).await?;
+    service_tokens::create_table(db).await?;
+    audit::create_table(db).await?;
+    members::create_table(db).await?;
+    teams::create_table(db).await?;
+    grants::create_table(db).await?;
+    if version < 5 {
+        members::migrate_v4(db).await?;
+    }
+    db.execute(format!("PRAGMA user_version = {SCHEMA_VERSION};").as_str())
+        .await?;
     Ok(())
 }
 
+/// Returns the version of the schema (`0` if not initialized)
+pub async fn schema_version(db: &DbConn) -> anyhow::Result<i64> {
+    let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version;")
+        .fetch_one(db)
+        .await?;
+    Ok(version)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
+    use crate::{config::Config, password::Passwords};
     use tokio::sync::OnceCell;
 
     /// Returns the dn connection pool
@@ -52,6 +99,41 @@ mod tests {
     #[tokio::test]
     async fn init_db() -> anyhow::Result<()> {
         let db = db_handle().await?;
-        init(&db).await
+        init(&db).await?;
+        assert_eq!(schema_version(&db).await?, SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrate_plain_passwords() -> anyhow::Result<()> {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        db.execute(
+            "CREATE TABLE users (
+                id INTEGER PRIMARY KEY,
+                email TEXT NOT NULL,
+                name TEXT NOT NULL,
+                password TEXT NOT NULL
+            );
+            INSERT INTO users (email, name, password) VALUES ('john@doe.com', 'John', 'password');
+            PRAGMA user_version = 1;",
+        )
+        .await?;
+
+        init(&db).await?;
+        assert_eq!(schema_version(&db).await?, SCHEMA_VERSION);
+        let (user, hash) = users::get_with_password(&db, "john@doe.com")
+            .await?
+            .unwrap();
+        assert_eq!(user.name, "John");
+        assert!(Passwords::default().verify("password".into(), hash).await);
+
+        // The emails are unique
+        assert!(users::insert(&db, "john@doe.com", "Jane", "hash")
+            .await?
+            .is_none());
+        Ok(())
     }
 }
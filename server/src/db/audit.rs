@@ -0,0 +1,79 @@
+//! DB audit trail
+//!
+//! Each action records its actor: a user, or a service token. The entries are
+//! kept when their actor is deleted.
+
+use service::AuditEvent;
+
+use super::DbConn;
+
+/// Create the `audit` table
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS audit (
+            id INTEGER PRIMARY KEY,
+            at INTEGER NOT NULL,
+            user_id INTEGER,
+            service_token_id INTEGER,
+            action TEXT NOT NULL,
+            organization_id INTEGER,
+            resource_id TEXT
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Inserts an audit entry
+pub async fn insert(db: &DbConn, event: &AuditEvent) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "INSERT INTO audit (at, user_id, service_token_id, action, organization_id, resource_id)
+        VALUES (?, ?, ?, ?, ?, ?);",
+    )
+    .bind(event.at)
+    .bind(parse_id(&event.user_id)?)
+    .bind(parse_id(&event.service_token_id)?)
+    .bind(&event.action)
+    .bind(parse_id(&event.org_id)?)
+    .bind(&event.resource_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the audit entries of an organization, oldest first
+pub async fn list(db: &DbConn, org_id: i64) -> anyhow::Result<Vec<AuditEvent>> {
+    let rows = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>, String, Option<String>)>(
+        "SELECT at, user_id, service_token_id, action, resource_id FROM audit
+        WHERE organization_id = ?
+        ORDER BY id;",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(at, user_id, service_token_id, action, resource_id)| AuditEvent {
+                at,
+                user_id: user_id.map(|id| id.to_string()),
+                service_token_id: service_token_id.map(|id| id.to_string()),
+                action,
+                org_id: Some(org_id.to_string()),
+                resource_id,
+            },
+        )
+        .collect())
+}
+
+/// Parses an optional ID
+fn parse_id(id: &Option<String>) -> anyhow::Result<Option<i64>> {
+    Ok(match id {
+        Some(id) => Some(id.parse()?),
+        None => None,
+    })
+}
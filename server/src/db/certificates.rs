@@ -0,0 +1,41 @@
+//! DB client certificates
+
+use super::DbConn;
+
+/// Create the `certificates` table
+///
+/// A client certificate is identified by its subject (common name).
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS certificates (
+            subject TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Maps a certificate subject to a user
+pub async fn insert(db: &DbConn, subject: &str, user_id: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("INSERT INTO certificates (subject, user_id) VALUES (?, ?);")
+        .bind(subject)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the ID of the user mapped to a certificate subject
+pub async fn user_id(db: &DbConn, subject: &str) -> anyhow::Result<Option<i64>> {
+    let row = sqlx::query_as::<_, (i64,)>("SELECT user_id FROM certificates WHERE subject = ?;")
+        .bind(subject)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(row.map(|(user_id,)| user_id))
+}
@@ -0,0 +1,212 @@
+//! DB grants
+//!
+//! The subject of a grant is stored in one of its `user_id`, `team_id` or
+//! `service_token_id` columns.
+
+use service::{Grant, Permission, Subject};
+
+use super::DbConn;
+
+/// Create the `grants` table
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS grants (
+            id INTEGER PRIMARY KEY,
+            project_id INTEGER NOT NULL,
+            user_id INTEGER,
+            team_id INTEGER,
+            service_token_id INTEGER,
+            environment TEXT,
+            key_prefix TEXT,
+            permission TEXT NOT NULL,
+            created_by INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            CHECK ((user_id IS NOT NULL) + (team_id IS NOT NULL)
+                + (service_token_id IS NOT NULL) = 1),
+            FOREIGN KEY (project_id) REFERENCES projects (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+            FOREIGN KEY (team_id) REFERENCES teams (id) ON DELETE CASCADE,
+            FOREIGN KEY (service_token_id) REFERENCES service_tokens (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Row of the `grants` table
+type Row = (
+    i64,
+    i64,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<String>,
+    Option<String>,
+    String,
+    i64,
+);
+
+/// Columns of a [Row]
+const COLUMNS: &str = "g.id, g.project_id, g.user_id, g.team_id, g.service_token_id,
+    g.environment, g.key_prefix, g.permission, g.created_at";
+
+/// Grant to insert
+#[derive(Debug)]
+pub struct NewGrant<'a> {
+    /// Subject
+    pub subject: &'a Subject,
+    /// Project ID
+    pub project_id: i64,
+    /// Environment
+    pub environment: Option<&'a str>,
+    /// Key prefix
+    pub key_prefix: Option<&'a str>,
+    /// Permission
+    pub permission: Permission,
+    /// ID of the user creating the grant
+    pub created_by: i64,
+    /// Creation time
+    pub created_at: i64,
+}
+
+/// Inserts a new grant
+pub async fn insert(db: &DbConn, new: NewGrant<'_>) -> anyhow::Result<Grant> {
+    let (user_id, team_id, service_token_id) = match new.subject {
+        Subject::User(id) => (Some(id.parse::<i64>()?), None, None),
+        Subject::Team(id) => (None, Some(id.parse::<i64>()?), None),
+        Subject::ServiceToken(id) => (None, None, Some(id.parse::<i64>()?)),
+    };
+    let res = sqlx::query(
+        "INSERT INTO grants (project_id, user_id, team_id, service_token_id, environment,
+            key_prefix, permission, created_by, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(new.project_id)
+    .bind(user_id)
+    .bind(team_id)
+    .bind(service_token_id)
+    .bind(new.environment)
+    .bind(new.key_prefix)
+    .bind(new.permission.as_str())
+    .bind(new.created_by)
+    .bind(new.created_at)
+    .execute(db)
+    .await?;
+
+    Ok(Grant {
+        id: res.last_insert_rowid().to_string(),
+        subject: new.subject.clone(),
+        project_id: new.project_id.to_string(),
+        environment: new.environment.map(String::from),
+        key_prefix: new.key_prefix.map(String::from),
+        permission: new.permission,
+        created_at: new.created_at,
+    })
+}
+
+/// Reads a grant
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<Grant>> {
+    let row = sqlx::query_as::<_, Row>(&format!("SELECT {COLUMNS} FROM grants g WHERE g.id = ?;"))
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+
+    row.map(from_row).transpose()
+}
+
+/// Lists the grants of a project
+pub async fn list(db: &DbConn, project_id: i64) -> anyhow::Result<Vec<Grant>> {
+    let rows = sqlx::query_as::<_, Row>(&format!(
+        "SELECT {COLUMNS} FROM grants g WHERE g.project_id = ? ORDER BY g.id;"
+    ))
+    .bind(project_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter().map(from_row).collect()
+}
+
+/// Lists the grants of a user on a project, directly or through its teams
+pub async fn for_user(db: &DbConn, project_id: i64, user_id: i64) -> anyhow::Result<Vec<Grant>> {
+    let rows = sqlx::query_as::<_, Row>(&format!(
+        "SELECT {COLUMNS} FROM grants g
+        WHERE g.project_id = ? AND (g.user_id = ?
+            OR g.team_id IN (SELECT team_id FROM team_members WHERE user_id = ?))
+        ORDER BY g.id;"
+    ))
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter().map(from_row).collect()
+}
+
+/// Lists the grants of a service token on a project
+pub async fn for_service_token(
+    db: &DbConn,
+    project_id: i64,
+    token_id: i64,
+) -> anyhow::Result<Vec<Grant>> {
+    let rows = sqlx::query_as::<_, Row>(&format!(
+        "SELECT {COLUMNS} FROM grants g
+        WHERE g.project_id = ? AND g.service_token_id = ?
+        ORDER BY g.id;"
+    ))
+    .bind(project_id)
+    .bind(token_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter().map(from_row).collect()
+}
+
+/// Deletes a grant
+pub async fn delete(db: &DbConn, id: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("DELETE FROM grants WHERE id = ?;")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Converts a DB row to a [Grant]
+fn from_row(
+    (
+        id,
+        project_id,
+        user_id,
+        team_id,
+        service_token_id,
+        environment,
+        key_prefix,
+        permission,
+        created_at,
+    ): Row,
+) -> anyhow::Result<Grant> {
+    let subject = match (user_id, team_id, service_token_id) {
+        (Some(id), _, _) => Subject::User(id.to_string()),
+        (_, Some(id), _) => Subject::Team(id.to_string()),
+        (_, _, Some(id)) => Subject::ServiceToken(id.to_string()),
+        _ => return Err(anyhow::anyhow!("Grant {id} has no subject")),
+    };
+    let permission = match permission.as_str() {
+        "read" => Permission::Read,
+        "write" => Permission::Write,
+        "admin" => Permission::Admin,
+        other => return Err(anyhow::anyhow!("Invalid permission: {other}")),
+    };
+    Ok(Grant {
+        id: id.to_string(),
+        subject,
+        project_id: project_id.to_string(),
+        environment,
+        key_prefix,
+        permission,
+        created_at,
+    })
+}
@@ -0,0 +1,310 @@
+//! DB members and invitations
+//!
+//! A membership links a user to an organization, with a role. An invitation is
+//! pending until the invited user accepts it.
+
+use service::{Invitation, Member, Organization, Role, User};
+
+use super::DbConn;
+
+/// Create the `memberships` and `invitations` tables
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS memberships (
+            organization_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            role TEXT NOT NULL,
+            PRIMARY KEY (organization_id, user_id),
+            FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS invitations (
+            id INTEGER PRIMARY KEY,
+            organization_id INTEGER NOT NULL,
+            email TEXT NOT NULL,
+            role TEXT NOT NULL,
+            invited_by INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            UNIQUE (organization_id, email),
+            FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Migrates the memberships from the version 4 of the schema
+///
+/// The organizations had no members, and all the users had access to them: the
+/// existing users become owners of the existing organizations, which keeps their
+/// access until an owner removes them.
+pub(super) async fn migrate_v4(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "INSERT OR IGNORE INTO memberships (organization_id, user_id, role)
+        SELECT o.id, u.id, 'owner' FROM organizations o, users u;",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the role of a user in an organization, [None] if not a member
+pub async fn role(db: &DbConn, org_id: i64, user_id: i64) -> anyhow::Result<Option<Role>> {
+    let row = sqlx::query_as::<_, (String,)>(
+        "SELECT role FROM memberships WHERE organization_id = ? AND user_id = ?;",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    row.map(|(role,)| parse_role(&role)).transpose()
+}
+
+/// Inserts or updates a membership
+pub async fn upsert(db: &DbConn, org_id: i64, user_id: i64, role: Role) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "INSERT INTO memberships (organization_id, user_id, role) VALUES (?, ?, ?)
+        ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role;",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role.as_str())
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Reads a member
+pub async fn get(db: &DbConn, org_id: i64, user_id: i64) -> anyhow::Result<Option<Member>> {
+    Ok(list(db, org_id)
+        .await?
+        .into_iter()
+        .find(|member| member.user.id == user_id.to_string()))
+}
+
+/// Lists the members of an organization
+pub async fn list(db: &DbConn, org_id: i64) -> anyhow::Result<Vec<Member>> {
+    let rows = sqlx::query_as::<_, (i64, String, String, String)>(
+        "SELECT u.id, u.name, u.email, m.role FROM memberships m
+        INNER JOIN users u ON u.id = m.user_id
+        WHERE m.organization_id = ?
+        ORDER BY u.id;",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter()
+        .map(|(id, name, email, role)| {
+            Ok(Member {
+                org_id: org_id.to_string(),
+                user: User {
+                    id: id.to_string(),
+                    name,
+                    email,
+                },
+                role: parse_role(&role)?,
+            })
+        })
+        .collect()
+}
+
+/// Deletes a membership, with the teams and grants of the user in the organization
+pub async fn delete(db: &DbConn, org_id: i64, user_id: i64) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    let _res = sqlx::query("DELETE FROM memberships WHERE organization_id = ? AND user_id = ?;")
+        .bind(org_id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    let _res = sqlx::query(
+        "DELETE FROM team_members WHERE user_id = ?
+        AND team_id IN (SELECT id FROM teams WHERE organization_id = ?);",
+    )
+    .bind(user_id)
+    .bind(org_id)
+    .execute(&mut tx)
+    .await?;
+    let _res = sqlx::query(
+        "DELETE FROM grants WHERE user_id = ?
+        AND project_id IN (SELECT id FROM projects WHERE organization_id = ?);",
+    )
+    .bind(user_id)
+    .bind(org_id)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Returns true if two users are members of the same organization
+pub async fn share_organization(db: &DbConn, user_id: i64, other_id: i64) -> anyhow::Result<bool> {
+    let (shared,) = sqlx::query_as::<_, (bool,)>(
+        "SELECT EXISTS (SELECT 1 FROM memberships m
+            INNER JOIN memberships o ON o.organization_id = m.organization_id
+            WHERE m.user_id = ? AND o.user_id = ?);",
+    )
+    .bind(user_id)
+    .bind(other_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(shared)
+}
+
+/// Returns the number of owners of an organization
+pub async fn count_owners(db: &DbConn, org_id: i64) -> anyhow::Result<i64> {
+    let (count,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM memberships WHERE organization_id = ? AND role = 'owner';",
+    )
+    .bind(org_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(count)
+}
+
+/// Returns the IDs of the organizations of which a user is the only owner
+pub async fn sole_owner_of(db: &DbConn, user_id: i64) -> anyhow::Result<Vec<i64>> {
+    let rows = sqlx::query_as::<_, (i64,)>(
+        "SELECT m.organization_id FROM memberships m
+        WHERE m.user_id = ? AND m.role = 'owner'
+        AND (SELECT COUNT(*) FROM memberships o
+            WHERE o.organization_id = m.organization_id AND o.role = 'owner') = 1
+        ORDER BY m.organization_id;",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Inserts an invitation, or updates the pending invitation of the same email
+pub async fn invite(
+    db: &DbConn,
+    org: Organization,
+    email: &str,
+    role: Role,
+    invited_by: i64,
+    now: i64,
+) -> anyhow::Result<Invitation> {
+    let (id,) = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO invitations (organization_id, email, role, invited_by, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (organization_id, email) DO UPDATE
+        SET role = excluded.role, invited_by = excluded.invited_by, created_at = excluded.created_at
+        RETURNING id;",
+    )
+    .bind(org.id.parse::<i64>()?)
+    .bind(email)
+    .bind(role.as_str())
+    .bind(invited_by)
+    .bind(now)
+    .fetch_one(db)
+    .await?;
+
+    Ok(Invitation {
+        id: id.to_string(),
+        organization: org,
+        email: email.to_string(),
+        role,
+        invited_by: invited_by.to_string(),
+        created_at: now,
+    })
+}
+
+/// Lists the pending invitations of an email
+pub async fn invitations(db: &DbConn, email: &str) -> anyhow::Result<Vec<Invitation>> {
+    let rows = sqlx::query_as::<_, (i64, i64, String, String, String, i64, i64)>(
+        "SELECT i.id, o.id, o.name, i.email, i.role, i.invited_by, i.created_at
+        FROM invitations i
+        INNER JOIN organizations o ON o.id = i.organization_id
+        WHERE i.email = ?
+        ORDER BY i.id;",
+    )
+    .bind(email)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter()
+        .map(
+            |(id, org_id, org_name, email, role, invited_by, created_at)| {
+                Ok(Invitation {
+                    id: id.to_string(),
+                    organization: Organization {
+                        id: org_id.to_string(),
+                        name: org_name,
+                    },
+                    email,
+                    role: parse_role(&role)?,
+                    invited_by: invited_by.to_string(),
+                    created_at,
+                })
+            },
+        )
+        .collect()
+}
+
+/// Accepts an invitation of an email: the user becomes a member
+///
+/// Returns the organization ID, or [None] if the email has no such invitation.
+/// A member keeps the highest of its role and the invited role.
+pub async fn accept(
+    db: &DbConn,
+    id: i64,
+    email: &str,
+    user_id: i64,
+) -> anyhow::Result<Option<i64>> {
+    let mut tx = db.begin().await?;
+    let row = sqlx::query_as::<_, (i64, String)>(
+        "DELETE FROM invitations WHERE id = ? AND email = ? RETURNING organization_id, role;",
+    )
+    .bind(id)
+    .bind(email)
+    .fetch_optional(&mut tx)
+    .await?;
+    let (org_id, role) = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let current = sqlx::query_as::<_, (String,)>(
+        "SELECT role FROM memberships WHERE organization_id = ? AND user_id = ?;",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(&mut tx)
+    .await?;
+    let mut role = parse_role(&role)?;
+    if let Some((current,)) = current {
+        role = role.max(parse_role(&current)?);
+    }
+    let _res = sqlx::query(
+        "INSERT INTO memberships (organization_id, user_id, role) VALUES (?, ?, ?)
+        ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role;",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role.as_str())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(org_id))
+}
+
+/// Parses a DB role
+fn parse_role(role: &str) -> anyhow::Result<Role> {
+    role.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid role: {role}"))
+}
@@ -1,5 +1,7 @@
 //! DB organizations
 
+use service::Organization;
+
 use super::DbConn;
 
 /// Create the `organizations` table
@@ -15,3 +17,50 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Inserts a new organization
+pub async fn insert(db: &DbConn, name: &str) -> anyhow::Result<Organization> {
+    let res = sqlx::query("INSERT INTO organizations (name) VALUES (?);")
+        .bind(name)
+        .execute(db)
+        .await?;
+
+    Ok(Organization {
+        id: res.last_insert_rowid().to_string(),
+        name: name.to_string(),
+    })
+}
+
+/// Reads an organization
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<Organization>> {
+    let row =
+        sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM organizations WHERE id = ?;")
+            .bind(id)
+            .fetch_optional(db)
+            .await?;
+
+    Ok(row.map(|(id, name)| Organization {
+        id: id.to_string(),
+        name,
+    }))
+}
+
+/// Deletes an organization, with its projects and secrets
+pub async fn delete(db: &DbConn, id: i64) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    let _res = sqlx::query("DELETE FROM secrets WHERE organization_id = ?;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    let _res = sqlx::query("DELETE FROM projects WHERE organization_id = ?;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    let _res = sqlx::query("DELETE FROM organizations WHERE id = ?;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 //! DB projects
 
+use service::{Organization, Project};
+
 use super::DbConn;
 
 /// Create the `projects` table
@@ -7,7 +9,9 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
     let _res = sqlx::query(
         "CREATE TABLE IF NOT EXISTS projects (
             id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL
+            name TEXT NOT NULL,
+            organization_id INTEGER NOT NULL,
+            FOREIGN KEY (organization_id) REFERENCES organizations (id)
         );",
     )
     .execute(db)
@@ -15,3 +19,56 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Inserts a new project
+pub async fn insert(db: &DbConn, name: &str, org: Organization) -> anyhow::Result<Project> {
+    let res = sqlx::query("INSERT INTO projects (name, organization_id) VALUES (?, ?);")
+        .bind(name)
+        .bind(org.id.parse::<i64>()?)
+        .execute(db)
+        .await?;
+
+    Ok(Project {
+        id: res.last_insert_rowid().to_string(),
+        name: name.to_string(),
+        organization: org,
+    })
+}
+
+/// Reads a project
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<Project>> {
+    let row = sqlx::query_as::<_, (i64, String, i64, String)>(
+        "SELECT p.id, p.name, o.id, o.name
+        FROM projects p
+        INNER JOIN organizations o ON o.id = p.organization_id
+        WHERE p.id = ?;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(|(id, name, org_id, org_name)| Project {
+        id: id.to_string(),
+        name,
+        organization: Organization {
+            id: org_id.to_string(),
+            name: org_name,
+        },
+    }))
+}
+
+/// Deletes a project, with its secrets
+pub async fn delete(db: &DbConn, id: i64) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    let _res = sqlx::query("DELETE FROM secrets WHERE project_id = ?;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    let _res = sqlx::query("DELETE FROM projects WHERE id = ?;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 //! DB secrets
 
+use service::{Organization, Project, Secret};
+
 use super::DbConn;
 
 /// Create the `secrets` table
@@ -11,6 +13,7 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
             value TEXT NOT NULL,
             organization_id INTEGER NOT NULL,
             project_id INTEGER,
+            environment TEXT,
             FOREIGN KEY (organization_id) REFERENCES organizations (id),
             FOREIGN KEY (project_id) REFERENCES projects (id)
         );",
@@ -20,3 +23,134 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Migrates the `secrets` table from the version 3 of the schema, which has no environments
+pub(super) async fn migrate_v3(db: &DbConn) -> anyhow::Result<()> {
+    let (columns,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM pragma_table_info('secrets') WHERE name = 'key';",
+    )
+    .fetch_one(db)
+    .await?;
+    let (environment,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM pragma_table_info('secrets') WHERE name = 'environment';",
+    )
+    .fetch_one(db)
+    .await?;
+    if columns > 0 && environment == 0 {
+        sqlx::query("ALTER TABLE secrets ADD COLUMN environment TEXT;")
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Inserts a new secret
+pub async fn insert(
+    db: &DbConn,
+    org: Organization,
+    project: Option<Project>,
+    environment: Option<&str>,
+    key: &str,
+    value: &str,
+) -> anyhow::Result<Secret> {
+    let project_id = match &project {
+        Some(p) => Some(p.id.parse::<i64>()?),
+        None => None,
+    };
+
+    let res = sqlx::query(
+        "INSERT INTO secrets (key, value, organization_id, project_id, environment)
+        VALUES (?, ?, ?, ?, ?);",
+    )
+    .bind(key)
+    .bind(value)
+    .bind(org.id.parse::<i64>()?)
+    .bind(project_id)
+    .bind(environment)
+    .execute(db)
+    .await?;
+
+    Ok(Secret {
+        id: res.last_insert_rowid().to_string(),
+        oeganization: org,
+        project,
+        environment: environment.map(String::from),
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Reads a secret
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<Secret>> {
+    #[allow(clippy::type_complexity)]
+    let row = sqlx::query_as::<
+        _,
+        (
+            i64,
+            String,
+            String,
+            i64,
+            String,
+            Option<i64>,
+            Option<String>,
+            Option<String>,
+        ),
+    >(
+        "SELECT s.id, s.key, s.value, o.id, o.name, p.id, p.name, s.environment
+        FROM secrets s
+        INNER JOIN organizations o ON o.id = s.organization_id
+        LEFT JOIN projects p ON p.id = s.project_id
+        WHERE s.id = ?;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(
+        |(id, key, value, org_id, org_name, project_id, project_name, environment)| {
+            let organization = Organization {
+                id: org_id.to_string(),
+                name: org_name,
+            };
+            let project = match (project_id, project_name) {
+                (Some(project_id), Some(project_name)) => Some(Project {
+                    id: project_id.to_string(),
+                    name: project_name,
+                    organization: organization.clone(),
+                }),
+                _ => None,
+            };
+            Secret {
+                id: id.to_string(),
+                oeganization: organization,
+                project,
+                environment,
+                key,
+                value,
+            }
+        },
+    ))
+}
+
+/// Updates a secret key and value
+pub async fn update(db: &DbConn, id: i64, key: &str, value: &str) -> anyhow::Result<()> {
+    let _res = sqlx::query("UPDATE secrets SET key = ?, value = ? WHERE id = ?;")
+        .bind(key)
+        .bind(value)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a secret
+pub async fn delete(db: &DbConn, id: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("DELETE FROM secrets WHERE id = ?;")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
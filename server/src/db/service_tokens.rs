@@ -0,0 +1,205 @@
+//! DB service tokens
+//!
+//! Like the session tokens, the service tokens are only stored as SHA-256 hashes.
+//! A revoked token is kept, as the audit trail refers to it.
+
+use service::{ServiceToken, TokenScope};
+
+use super::{sessions::token_hash, DbConn};
+
+/// Create the `service_tokens` table
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS service_tokens (
+            id INTEGER PRIMARY KEY,
+            token_hash TEXT NOT NULL UNIQUE,
+            name TEXT NOT NULL,
+            organization_id INTEGER NOT NULL,
+            project_id INTEGER,
+            environment TEXT,
+            scope TEXT NOT NULL,
+            created_by INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            last_used_at INTEGER,
+            revoked_at INTEGER,
+            FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE,
+            FOREIGN KEY (project_id) REFERENCES projects (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Row of the `service_tokens` table
+type Row = (
+    i64,
+    String,
+    i64,
+    Option<i64>,
+    Option<String>,
+    String,
+    i64,
+    i64,
+    Option<i64>,
+    Option<i64>,
+);
+
+/// Columns of a [Row]
+const COLUMNS: &str = "id, name, organization_id, project_id, environment, scope,
+    created_at, expires_at, last_used_at, revoked_at";
+
+/// Service token to insert
+#[derive(Debug)]
+pub struct NewServiceToken<'a> {
+    /// Token
+    pub token: &'a str,
+    /// Name
+    pub name: &'a str,
+    /// Organization ID
+    pub org_id: i64,
+    /// Project ID
+    pub project_id: Option<i64>,
+    /// Environment
+    pub environment: Option<&'a str>,
+    /// Scope
+    pub scope: TokenScope,
+    /// ID of the user creating the token
+    pub created_by: i64,
+    /// Creation time
+    pub created_at: i64,
+    /// Expiration time
+    pub expires_at: i64,
+}
+
+/// Inserts a new service token
+pub async fn insert(db: &DbConn, new: NewServiceToken<'_>) -> anyhow::Result<ServiceToken> {
+    let res = sqlx::query(
+        "INSERT INTO service_tokens (token_hash, name, organization_id, project_id, environment,
+            scope, created_by, created_at, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(token_hash(new.token))
+    .bind(new.name)
+    .bind(new.org_id)
+    .bind(new.project_id)
+    .bind(new.environment)
+    .bind(scope_str(new.scope))
+    .bind(new.created_by)
+    .bind(new.created_at)
+    .bind(new.expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(ServiceToken {
+        id: res.last_insert_rowid().to_string(),
+        name: new.name.to_string(),
+        org_id: new.org_id.to_string(),
+        project_id: new.project_id.map(|id| id.to_string()),
+        environment: new.environment.map(String::from),
+        scope: new.scope,
+        created_at: new.created_at,
+        expires_at: new.expires_at,
+        last_used_at: None,
+        revoked_at: None,
+    })
+}
+
+/// Returns the service token of a token, if neither expired nor revoked
+///
+/// The token is marked as used.
+pub async fn find(db: &DbConn, token: &str, now: i64) -> anyhow::Result<Option<ServiceToken>> {
+    let row = sqlx::query_as::<_, Row>(&format!(
+        "UPDATE service_tokens SET last_used_at = ?
+        WHERE token_hash = ? AND expires_at > ? AND revoked_at IS NULL
+        RETURNING {COLUMNS};"
+    ))
+    .bind(now)
+    .bind(token_hash(token))
+    .bind(now)
+    .fetch_optional(db)
+    .await?;
+
+    row.map(from_row).transpose()
+}
+
+/// Reads a service token
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<ServiceToken>> {
+    let row = sqlx::query_as::<_, Row>(&format!(
+        "SELECT {COLUMNS} FROM service_tokens WHERE id = ?;"
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    row.map(from_row).transpose()
+}
+
+/// Lists the service tokens of an organization, including the revoked ones
+pub async fn list(db: &DbConn, org_id: i64) -> anyhow::Result<Vec<ServiceToken>> {
+    let rows = sqlx::query_as::<_, Row>(&format!(
+        "SELECT {COLUMNS} FROM service_tokens WHERE organization_id = ? ORDER BY id;"
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter().map(from_row).collect()
+}
+
+/// Revokes a service token
+pub async fn revoke(db: &DbConn, id: i64, now: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "UPDATE service_tokens SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL;",
+    )
+    .bind(now)
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the DB value of a scope
+fn scope_str(scope: TokenScope) -> &'static str {
+    match scope {
+        TokenScope::Read => "read",
+        TokenScope::ReadWrite => "read_write",
+    }
+}
+
+/// Converts a DB row to a [ServiceToken]
+fn from_row(
+    (
+        id,
+        name,
+        org_id,
+        project_id,
+        environment,
+        scope,
+        created_at,
+        expires_at,
+        last_used_at,
+        revoked_at,
+    ): Row,
+) -> anyhow::Result<ServiceToken> {
+    let scope = match scope.as_str() {
+        "read" => TokenScope::Read,
+        "read_write" => TokenScope::ReadWrite,
+        other => return Err(anyhow::anyhow!("Invalid token scope: {other}")),
+    };
+    Ok(ServiceToken {
+        id: id.to_string(),
+        name,
+        org_id: org_id.to_string(),
+        project_id: project_id.map(|id| id.to_string()),
+        environment,
+        scope,
+        created_at,
+        expires_at,
+        last_used_at,
+        revoked_at,
+    })
+}
@@ -0,0 +1,156 @@
+//! DB sessions
+//!
+//! The session tokens are not stored: a session is found by the SHA-256 hash of
+//! its token. The times are UNIX timestamps, in seconds.
+
+use service::Session;
+use sha2::{Digest, Sha256};
+
+use super::DbConn;
+
+/// Create the `sessions` table
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            id INTEGER PRIMARY KEY,
+            token_hash TEXT NOT NULL UNIQUE,
+            user_id INTEGER NOT NULL,
+            label TEXT,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            last_used_at INTEGER,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Migrates the `sessions` table from the version 2 of the schema
+///
+/// The sessions of plain text tokens are dropped: their users must login again.
+pub(super) async fn migrate_v2(db: &DbConn) -> anyhow::Result<()> {
+    let (plain,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM pragma_table_info('sessions') WHERE name = 'token';",
+    )
+    .fetch_one(db)
+    .await?;
+    if plain > 0 {
+        sqlx::query("DROP TABLE sessions;").execute(db).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the hash of a session token
+pub(super) fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Inserts a new session, which expires at `expires_at`
+pub async fn insert(
+    db: &DbConn,
+    token: &str,
+    user_id: i64,
+    label: Option<&str>,
+    now: i64,
+    expires_at: i64,
+) -> anyhow::Result<Session> {
+    let res = sqlx::query(
+        "INSERT INTO sessions (token_hash, user_id, label, created_at, expires_at)
+        VALUES (?, ?, ?, ?, ?);",
+    )
+    .bind(token_hash(token))
+    .bind(user_id)
+    .bind(label)
+    .bind(now)
+    .bind(expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(Session {
+        id: res.last_insert_rowid().to_string(),
+        label: label.map(String::from),
+        created_at: now,
+        expires_at,
+        last_used_at: None,
+        current: true,
+    })
+}
+
+/// Returns the IDs of the session of a token and of its user, if not expired
+///
+/// The session is marked as used.
+pub async fn find(db: &DbConn, token: &str, now: i64) -> anyhow::Result<Option<(i64, i64)>> {
+    let row = sqlx::query_as::<_, (i64, i64)>(
+        "UPDATE sessions SET last_used_at = ?
+        WHERE token_hash = ? AND expires_at > ?
+        RETURNING id, user_id;",
+    )
+    .bind(now)
+    .bind(token_hash(token))
+    .bind(now)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
+
+/// Lists the sessions of a user which are not expired
+///
+/// `current` is the ID of the session of the request, if any.
+pub async fn list(
+    db: &DbConn,
+    user_id: i64,
+    current: Option<i64>,
+    now: i64,
+) -> anyhow::Result<Vec<Session>> {
+    let rows = sqlx::query_as::<_, (i64, Option<String>, i64, i64, Option<i64>)>(
+        "SELECT id, label, created_at, expires_at, last_used_at FROM sessions
+        WHERE user_id = ? AND expires_at > ?
+        ORDER BY id;",
+    )
+    .bind(user_id)
+    .bind(now)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, label, created_at, expires_at, last_used_at)| Session {
+                id: id.to_string(),
+                label,
+                created_at,
+                expires_at,
+                last_used_at,
+                current: Some(id) == current,
+            },
+        )
+        .collect())
+}
+
+/// Deletes a session of a user
+///
+/// Returns false if the user has no such session.
+pub async fn delete(db: &DbConn, id: i64, user_id: i64) -> anyhow::Result<bool> {
+    let res = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?;")
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// Deletes the expired sessions
+pub async fn delete_expired(db: &DbConn, now: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("DELETE FROM sessions WHERE expires_at <= ?;")
+        .bind(now)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! DB teams
+
+use service::Team;
+
+use super::DbConn;
+
+/// Create the `teams` and `team_members` tables
+pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS teams (
+            id INTEGER PRIMARY KEY,
+            organization_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            UNIQUE (organization_id, name),
+            FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+    let _res = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS team_members (
+            team_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            PRIMARY KEY (team_id, user_id),
+            FOREIGN KEY (team_id) REFERENCES teams (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        );",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Inserts a new team
+///
+/// Returns [None] if the organization already has a team of this name.
+pub async fn insert(db: &DbConn, org_id: i64, name: &str) -> anyhow::Result<Option<Team>> {
+    let row = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO teams (organization_id, name) VALUES (?, ?)
+        ON CONFLICT (organization_id, name) DO NOTHING
+        RETURNING id;",
+    )
+    .bind(org_id)
+    .bind(name)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(|(id,)| Team {
+        id: id.to_string(),
+        org_id: org_id.to_string(),
+        name: name.to_string(),
+        user_ids: vec![],
+    }))
+}
+
+/// Reads a team, with its users
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<Team>> {
+    let row =
+        sqlx::query_as::<_, (i64, String)>("SELECT organization_id, name FROM teams WHERE id = ?;")
+            .bind(id)
+            .fetch_optional(db)
+            .await?;
+
+    Ok(match row {
+        Some((org_id, name)) => Some(Team {
+            id: id.to_string(),
+            org_id: org_id.to_string(),
+            name,
+            user_ids: user_ids(db, id).await?,
+        }),
+        None => None,
+    })
+}
+
+/// Lists the teams of an organization, with their users
+pub async fn list(db: &DbConn, org_id: i64) -> anyhow::Result<Vec<Team>> {
+    let rows = sqlx::query_as::<_, (i64, String)>(
+        "SELECT id, name FROM teams WHERE organization_id = ? ORDER BY id;",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut teams = Vec::with_capacity(rows.len());
+    for (id, name) in rows {
+        teams.push(Team {
+            id: id.to_string(),
+            org_id: org_id.to_string(),
+            name,
+            user_ids: user_ids(db, id).await?,
+        });
+    }
+    Ok(teams)
+}
+
+/// Adds a user to a team
+pub async fn add_member(db: &DbConn, team_id: i64, user_id: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("INSERT OR IGNORE INTO team_members (team_id, user_id) VALUES (?, ?);")
+        .bind(team_id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes a user from a team
+///
+/// Returns false if the user is not in the team.
+pub async fn remove_member(db: &DbConn, team_id: i64, user_id: i64) -> anyhow::Result<bool> {
+    let res = sqlx::query("DELETE FROM team_members WHERE team_id = ? AND user_id = ?;")
+        .bind(team_id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// Returns the IDs of the users of a team
+async fn user_ids(db: &DbConn, team_id: i64) -> anyhow::Result<Vec<String>> {
+    let rows = sqlx::query_as::<_, (i64,)>(
+        "SELECT user_id FROM team_members WHERE team_id = ? ORDER BY user_id;",
+    )
+    .bind(team_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id.to_string()).collect())
+}
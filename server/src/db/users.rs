@@ -1,15 +1,18 @@
 //! DB users
 
+use service::User;
+
 use super::DbConn;
+use crate::password::Passwords;
 
 /// Create the `users` table
 pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
     let _res = sqlx::query(
         "CREATE TABLE IF NOT EXISTS users (
             id INTEGER PRIMARY KEY,
-            email TEXT NOT NULL,
+            email TEXT NOT NULL UNIQUE,
             name TEXT NOT NULL,
-            password TEXT NOT NULL
+            password_hash TEXT NOT NULL
         );",
     )
     .execute(db)
@@ -17,3 +20,127 @@ pub(super) async fn create_table(db: &DbConn) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Migrates the `users` table from the version 1 of the schema
+///
+/// The plain text passwords are hashed, and the emails become unique (the
+/// migration fails if an email is used twice). This is a no-op without a
+/// `users` table holding plain text passwords.
+pub(super) async fn migrate_v1(db: &DbConn) -> anyhow::Result<()> {
+    let (plain,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM pragma_table_info('users') WHERE name = 'password';",
+    )
+    .fetch_one(db)
+    .await?;
+    if plain == 0 {
+        return Ok(());
+    }
+
+    let mut tx = db.begin().await?;
+    sqlx::query("ALTER TABLE users RENAME COLUMN password TO password_hash;")
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("CREATE UNIQUE INDEX users_email ON users (email);")
+        .execute(&mut tx)
+        .await?;
+
+    let passwords = Passwords::default();
+    let rows = sqlx::query_as::<_, (i64, String)>("SELECT id, password_hash FROM users;")
+        .fetch_all(&mut tx)
+        .await?;
+    for (id, password) in rows {
+        let hash = passwords.hash(password).await?;
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?;")
+            .bind(hash)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Inserts a new user
+///
+/// Returns [None] if the email is already used.
+pub async fn insert(
+    db: &DbConn,
+    email: &str,
+    name: &str,
+    password_hash: &str,
+) -> anyhow::Result<Option<User>> {
+    let res = sqlx::query(
+        "INSERT INTO users (email, name, password_hash) VALUES (?, ?, ?)
+        ON CONFLICT (email) DO NOTHING;",
+    )
+    .bind(email)
+    .bind(name)
+    .bind(password_hash)
+    .execute(db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(User {
+        id: res.last_insert_rowid().to_string(),
+        name: name.to_string(),
+        email: email.to_string(),
+    }))
+}
+
+/// Reads a user
+pub async fn get(db: &DbConn, id: i64) -> anyhow::Result<Option<User>> {
+    let row = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT id, email, name FROM users WHERE id = ?;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(from_row))
+}
+
+/// Reads a user by email
+pub async fn get_by_email(db: &DbConn, email: &str) -> anyhow::Result<Option<User>> {
+    let row = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT id, email, name FROM users WHERE email = ?;",
+    )
+    .bind(email)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(from_row))
+}
+
+/// Reads a user and its password hash by email
+pub async fn get_with_password(db: &DbConn, email: &str) -> anyhow::Result<Option<(User, String)>> {
+    let row = sqlx::query_as::<_, (i64, String, String, String)>(
+        "SELECT id, email, name, password_hash FROM users WHERE email = ?;",
+    )
+    .bind(email)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(|(id, email, name, password_hash)| (from_row((id, email, name)), password_hash)))
+}
+
+/// Deletes a user
+pub async fn delete(db: &DbConn, id: i64) -> anyhow::Result<()> {
+    let _res = sqlx::query("DELETE FROM users WHERE id = ?;")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Converts a DB row to a [User]
+fn from_row((id, email, name): (i64, String, String)) -> User {
+    User {
+        id: id.to_string(),
+        name,
+        email,
+    }
+}
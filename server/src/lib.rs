@@ -2,75 +2,702 @@
 
 #![deny(missing_docs)]
 
-use std::{fs, net::SocketAddr, path::PathBuf};
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::Duration,
+};
 
-use ::service::rpc;
+use ::service::{gen, rpc};
+use anyhow::anyhow;
 use db::DbConn;
+use tokio::{net::TcpListener, sync::watch};
 
+mod auth;
 mod config;
 mod db;
-mod service;
+mod metrics;
+mod password;
+pub mod service;
+mod tls;
 
 pub use config::*;
+pub use metrics::*;
+pub use password::*;
+pub use tls::*;
+
+/// Path of the liveness probe
+pub const HEALTH_PATH: &str = "/healthz";
+
+/// Path of the readiness probe
+pub const READY_PATH: &str = "/readyz";
+
+/// Deadline to close the database pool on shutdown
+const DB_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Server
 #[derive(Debug)]
 pub struct Server {
-    /// Port
-    pub port: u16,
+    /// TCP port
+    pub port: Option<u16>,
+    /// Path to the Unix socket
+    pub socket: Option<PathBuf>,
+    /// Permissions of the Unix socket file
+    pub socket_mode: Option<u32>,
     /// Path to the database file (`****.db`)
     pub database: PathBuf,
+    /// Path to the TLS certificate (PEM)
+    pub cert: Option<PathBuf>,
+    /// Path to the TLS private key (PEM)
+    pub key: Option<PathBuf>,
+    /// Path to the CA certificate (PEM), which signs the client certificates
+    pub ca_cert: Option<PathBuf>,
+    /// Path to the CA private key (PEM)
+    pub ca_key: Option<PathBuf>,
+    /// Limits of the connections
+    pub limits: rpc::Limits,
+    /// Deadline to finish the in-flight requests on shutdown
+    pub shutdown_timeout: Duration,
+    /// Path of the metrics, [None] if disabled
+    pub metrics_path: Option<String>,
+    /// Separate TCP port of the metrics
+    pub metrics_port: Option<u16>,
+    /// TCP port of the JSON-RPC 2.0 endpoint
+    pub jsonrpc_port: Option<u16>,
+    /// Cost of the password hashes
+    pub password_cost: PasswordCost,
+    /// Lifetime of the sessions
+    pub session_ttl: Duration,
+    /// TCP listeners bound before the start, by port (see [Server::with_listener])
+    pub listeners: HashMap<u16, TcpListener>,
 }
 
 impl Server {
     /// Instantiates a new [Server]
     pub fn new(config: Config) -> Self {
+        let limits = config.limits();
+        let shutdown_timeout = config.shutdown_timeout();
+        let metrics_path = config.metrics_path().map(String::from);
+        let password_cost = config.password_cost();
+        let session_ttl = config.session_ttl();
         Server {
             port: config.port,
+            socket: config.socket,
+            socket_mode: config.socket_mode,
             database: config.database,
+            cert: config.cert,
+            key: config.key,
+            ca_cert: config.ca_cert,
+            ca_key: config.ca_key,
+            limits,
+            shutdown_timeout,
+            metrics_path,
+            metrics_port: config.metrics_port,
+            jsonrpc_port: config.jsonrpc_port,
+            password_cost,
+            session_ttl,
+            listeners: HashMap::new(),
+        }
+    }
+
+    /// Sets a TCP listener bound before the start (eg. on port 0 in tests)
+    ///
+    /// The listener serves the TCP port, the JSON-RPC port or the metrics port
+    /// which matches its own port, instead of binding it.
+    pub fn with_listener(mut self, listener: TcpListener) -> io::Result<Self> {
+        let port = listener.local_addr()?.port();
+        self.listeners.insert(port, listener);
+        Ok(self)
+    }
+
+    /// Returns the server TCP address
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.port.map(|port| SocketAddr::from(([0, 0, 0, 0], port)))
+    }
+
+    /// Returns the TCP address of the metrics, if served on a separate port
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_port
+            .map(|port| SocketAddr::from(([0, 0, 0, 0], port)))
+    }
+
+    /// Returns the TCP address of the JSON-RPC 2.0 endpoint
+    pub fn jsonrpc_addr(&self) -> Option<SocketAddr> {
+        self.jsonrpc_port
+            .map(|port| SocketAddr::from(([0, 0, 0, 0], port)))
+    }
+
+    /// Returns the path of the metrics on the TCP port and the Unix socket
+    ///
+    /// The metrics are not served there if they have their own port.
+    fn inline_metrics_path(&self) -> Option<&str> {
+        match self.metrics_port {
+            Some(_) => None,
+            None => self.metrics_path.as_deref(),
         }
     }
 
-    /// Returns the server address
-    pub fn addr(&self) -> SocketAddr {
-        SocketAddr::from(([0, 0, 0, 0], self.port))
+    /// Returns true if the server is served over TLS
+    pub fn tls(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
     }
 
     /// Returns a database connection pool
     pub async fn db(&self) -> anyhow::Result<DbConn> {
         db::conn_pool(&self.database).await
     }
+
+    /// Returns the service implementation, to be served without sockets
+    pub async fn service(&self) -> anyhow::Result<service::Service> {
+        let passwords = Passwords::new(self.password_cost)?;
+        Ok(service::Service::new(self.db().await?)
+            .with_passwords(passwords)
+            .with_session_ttl(self.session_ttl))
+    }
 }
 
 impl Server {
     /// Initializes the server
     pub async fn init(&self) -> anyhow::Result<()> {
-        if !self.database.exists() {
-            fs::write(&self.database, "")?;
-        }
-
         let db_conn = self.db().await?;
         db::init(&db_conn).await?;
 
         Ok(())
     }
 
-    /// Starts the server
-    pub async fn start(self) -> anyhow::Result<()> {
-        // Set the db connection
+    /// Issues a client certificate for a user
+    ///
+    /// The user authenticates with this certificate instead of a session token.
+    pub async fn issue_client_certificate(&self, email: &str) -> anyhow::Result<ClientCertificate> {
+        let (ca_cert, ca_key) = match (&self.ca_cert, &self.ca_key) {
+            (Some(ca_cert), Some(ca_key)) => (ca_cert, ca_key),
+            _ => return Err(anyhow!("CA certificate not configured")),
+        };
+
         let db_conn = self.db().await?;
+        let user = db::users::get_by_email(&db_conn, email)
+            .await?
+            .ok_or_else(|| anyhow!("User not found: {email}"))?;
+        let user_id = user.id.parse::<i64>()?;
+
+        let cert = tls::issue_client_certificate(ca_cert, ca_key)?;
+        db::certificates::insert(&db_conn, &cert.subject, user_id).await?;
+        Ok(cert)
+    }
+
+    /// Starts the server, until a SIGINT or SIGTERM signal
+    ///
+    /// The server listens on the TCP port and on the Unix socket, if set.
+    /// The TCP port also accepts WebSocket connections, which stream the secret events.
+    /// The JSON-RPC 2.0 endpoint is served on its own port, if set.
+    /// The requests are traced with [rpc::Trace], without their payloads.
+    ///
+    /// The metrics are served on their own port if set, or else on the TCP port and
+    /// the Unix socket. The liveness and readiness probes ([HEALTH_PATH] and [READY_PATH])
+    /// are served on the TCP port and the Unix socket.
+    pub async fn start(self) -> anyhow::Result<()> {
+        self.start_with_shutdown(shutdown_signal()).await
+    }
+
+    /// Starts the server, until the shutdown signal resolves
+    ///
+    /// On shutdown, the server stops accepting connections, and finishes the in-flight
+    /// requests within the shutdown deadline. The requests still running at the deadline
+    /// are aborted, and an error is returned. The database pool is then closed.
+    pub async fn start_with_shutdown(
+        mut self,
+        signal: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        if self.port.is_none() && self.socket.is_none() && self.jsonrpc_port.is_none() {
+            return Err(anyhow!("No port or socket to listen on"));
+        }
 
         // Initialize the service
-        let handler = service::Service::new(db_conn);
-
-        // Configure the router
-        let receiver = rpc::json::JsonTransport::new();
-        let server = rpc::Server::new(receiver, handler);
-        let addr = self.addr();
-        if let Err(err) = server.start(&addr).await {
-            eprintln!("SERVER ERROR: {err}");
+        let service = self.service().await?;
+        let db = service.db().clone();
+        let events = service.events().clone();
+        let metrics = service.metrics().clone();
+        let router = gen::Handler::new(service.clone()).router();
+
+        // Bind the TCP ports
+        let mut listeners = std::mem::take(&mut self.listeners);
+        let tcp_listener = bind(&mut listeners, self.addr()).await?;
+        let jsonrpc_listener = bind(&mut listeners, self.jsonrpc_addr()).await?;
+        let metrics_listener = bind(&mut listeners, self.metrics_addr()).await?;
+
+        // Each server drains its connections once the shutdown is sent
+        let (shutdown, _) = watch::channel(());
+        let tcp_shutdown = drained(&shutdown);
+        let unix_shutdown = drained(&shutdown);
+        let jsonrpc_shutdown = drained(&shutdown);
+        let metrics_shutdown = drained(&shutdown);
+
+        let tcp = async {
+            let listener = match tcp_listener {
+                Some(listener) => listener,
+                None => return Ok(()),
+            };
+            let mut receiver = self
+                .http_builder()
+                .route(HEALTH_PATH, health())
+                .route(READY_PATH, ready(&service));
+            if let Some(path) = self.inline_metrics_path() {
+                receiver = receiver.route(path, scrape(&service));
+            }
+            let receiver = receiver.build::<String>().map_err(|err| anyhow!(err))?;
+            let receiver = rpc::ws::WsTransport::new(receiver).broker(events.clone());
+            let server = rpc::Server::new(receiver, router.clone())
+                .layer(versions())
+                .layer(metrics.clone())
+                .layer(rpc::Trace);
+            Ok::<_, anyhow::Error>(server.serve_with_shutdown(listener, tcp_shutdown).await?)
         };
 
-        Ok(())
+        let unix = async {
+            let path = match &self.socket {
+                Some(path) => path,
+                None => return Ok(()),
+            };
+            self.start_unix(path, router.clone(), &service, unix_shutdown)
+                .await
+        };
+
+        let jsonrpc = async {
+            let listener = match jsonrpc_listener {
+                Some(listener) => listener,
+                None => return Ok(()),
+            };
+            let http = self
+                .http_builder()
+                .build::<String>()
+                .map_err(|err| anyhow!(err))?;
+            let receiver = rpc::jsonrpc::JsonRpcTransport::new(http);
+            let server = rpc::Server::new(receiver, router.clone())
+                .layer(versions())
+                .layer(metrics.clone())
+                .layer(rpc::Trace);
+            Ok::<_, anyhow::Error>(
+                server
+                    .serve_with_shutdown(listener, jsonrpc_shutdown)
+                    .await?,
+            )
+        };
+
+        // The metrics port has no RPC methods
+        let metrics_server = async {
+            let (listener, path) = match (metrics_listener, &self.metrics_path) {
+                (Some(listener), Some(path)) => (listener, path),
+                _ => return Ok(()),
+            };
+            let receiver = rpc::http::HttpTransport::builder()
+                .limits(self.limits)
+                .route(path, scrape(&service))
+                .build::<String>()
+                .map_err(|err| anyhow!(err))?;
+            let server = rpc::Server::new(receiver, rpc::Router::new());
+            Ok::<_, anyhow::Error>(
+                server
+                    .serve_with_shutdown(listener, metrics_shutdown)
+                    .await?,
+            )
+        };
+
+        // The connections are aborted when the servers are dropped
+        let res = {
+            let servers =
+                async { tokio::try_join!(tcp, unix, jsonrpc, metrics_server).map(|_| ()) };
+            tokio::pin!(servers);
+            tokio::select! {
+                res = &mut servers => res,
+                _ = signal => {
+                    tracing::info!("shutting down");
+                    let _ = shutdown.send(());
+                    match tokio::time::timeout(self.shutdown_timeout, &mut servers).await {
+                        Ok(res) => res,
+                        Err(_) => Err(anyhow!("Shutdown deadline exceeded, in-flight requests aborted")),
+                    }
+                }
+            }
+        };
+        if let Err(err) = &res {
+            tracing::error!("server error: {err}");
+        };
+
+        if tokio::time::timeout(DB_CLOSE_TIMEOUT, db.close())
+            .await
+            .is_err()
+        {
+            tracing::error!("database pool not closed within {DB_CLOSE_TIMEOUT:?}");
+            return res.and(Err(anyhow!("Database pool not closed")));
+        }
+        res
+    }
+
+    /// Returns the builder of a HTTP receiver, with the limits and the TLS of the TCP port
+    fn http_builder(&self) -> rpc::http::HttpTransportBuilder {
+        let mut builder = rpc::http::HttpTransport::builder().limits(self.limits);
+        if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            builder = builder.server_cert(cert, key);
+            if let Some(ca_cert) = &self.ca_cert {
+                builder = builder.client_ca(ca_cert);
+            }
+        }
+        builder
+    }
+
+    /// Starts the server on the Unix socket
+    #[cfg(unix)]
+    async fn start_unix(
+        &self,
+        path: &Path,
+        router: rpc::Router,
+        service: &service::Service,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let mut receiver = rpc::unix::UnixTransport::builder()
+            .limits(self.limits)
+            .route(HEALTH_PATH, health())
+            .route(READY_PATH, ready(service));
+        if let Some(mode) = self.socket_mode {
+            receiver = receiver.mode(mode);
+        }
+        if let Some(path) = self.inline_metrics_path() {
+            receiver = receiver.route(path, scrape(service));
+        }
+        let server = rpc::Server::new(receiver.build(), router)
+            .layer(versions())
+            .layer(service.metrics().clone())
+            .layer(rpc::Trace);
+        Ok(server.start_with_shutdown(path, signal).await?)
+    }
+
+    /// Starts the server on the Unix socket
+    #[cfg(not(unix))]
+    async fn start_unix(
+        &self,
+        _path: &Path,
+        _router: rpc::Router,
+        _service: &service::Service,
+        _signal: impl Future<Output = ()> + Send + 'static,
+    ) -> anyhow::Result<()> {
+        Err(anyhow!("Unix sockets are not supported on this platform"))
+    }
+}
+
+/// Binds a TCP address, unless a listener was bound on its port before the start
+async fn bind(
+    listeners: &mut HashMap<u16, TcpListener>,
+    addr: Option<SocketAddr>,
+) -> io::Result<Option<TcpListener>> {
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+    match listeners.remove(&addr.port()) {
+        Some(listener) => Ok(Some(listener)),
+        None => Ok(Some(TcpListener::bind(addr).await?)),
+    }
+}
+
+/// Returns the middleware which rejects the unsupported API versions
+fn versions() -> rpc::Versions {
+    rpc::Versions::new(::service::MIN_API_VERSION..=::service::API_VERSION)
+}
+
+/// Returns the route which serves the metrics
+fn scrape(
+    service: &service::Service,
+) -> impl Fn(hyper::Request<hyper::Body>) -> future::Ready<hyper::Response<hyper::Body>>
+       + Send
+       + Sync
+       + 'static {
+    let service = service.clone();
+    move |_req| future::ready(service.metrics().response(service.db()))
+}
+
+/// Returns the route of the liveness probe, which answers as long as the server runs
+fn health() -> impl Fn(hyper::Request<hyper::Body>) -> future::Ready<hyper::Response<hyper::Body>>
+       + Send
+       + Sync
+       + 'static {
+    |_req| future::ready(hyper::Response::new(hyper::Body::from("ok")))
+}
+
+/// Returns the route of the readiness probe, which answers the service status
+///
+/// A sealed service is answered with `503 Service Unavailable`.
+fn ready(
+    service: &service::Service,
+) -> impl Fn(
+    hyper::Request<hyper::Body>,
+) -> Pin<Box<dyn Future<Output = hyper::Response<hyper::Body>> + Send>>
+       + Send
+       + Sync
+       + 'static {
+    let service = service.clone();
+    move |_req| {
+        let service = service.clone();
+        Box::pin(async move {
+            let status = service.service_status().await;
+            let body = serde_json::to_vec(&status).unwrap_or_default();
+            let mut res = hyper::Response::new(hyper::Body::from(body));
+            if !status.state.is_ready() {
+                *res.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+            }
+            res.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                hyper::header::HeaderValue::from_static("application/json"),
+            );
+            res
+        })
+    }
+}
+
+/// Returns a future which resolves once the shutdown is sent
+fn drained(shutdown: &watch::Sender<()>) -> impl Future<Output = ()> + Send + 'static {
+    let mut rx = shutdown.subscribe();
+    async move {
+        let _ = rx.changed().await;
+    }
+}
+
+/// Resolves on a SIGINT (Ctrl+C) or SIGTERM signal
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    /// Binds a listener on an ephemeral local port, and returns it with its port
+    ///
+    /// The listener is handed to the server (see [Server::with_listener]): the
+    /// connections wait in its backlog until the server accepts them, without retries.
+    async fn listener() -> (TcpListener, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("server.sock");
+        let server = Server::new(Config {
+            port: None,
+            socket: Some(socket.clone()),
+            database: dir.path().join("data.db"),
+            ..Config::default()
+        });
+        server.init().await.unwrap();
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let server = tokio::spawn(server.start_with_shutdown(signal));
+        for _ in 0..50 {
+            if socket.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(socket.exists());
+
+        // The server returns once drained
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(2), server).await;
+        assert!(res.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown_deadline() {
+        let dir = tempfile::tempdir().unwrap();
+        let (listener, port) = listener().await;
+        let mut server = Server::new(Config {
+            port: Some(port),
+            database: dir.path().join("data.db"),
+            ..Config::default()
+        })
+        .with_listener(listener)
+        .unwrap();
+        server.shutdown_timeout = Duration::from_millis(200);
+        server.init().await.unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let server = tokio::spawn(server.start_with_shutdown(signal));
+
+        // A request still running at shutdown: its body is never sent
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let req = "POST / HTTP/1.1\r\nHost: localhost\r\nX-RPC-METHOD: organizations\r\nContent-Length: 64\r\n\r\n{";
+        tokio::io::AsyncWriteExt::write_all(&mut stream, req.as_bytes())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The server aborts it at the deadline, and fails
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_millis(500), server).await;
+        assert!(res.unwrap().unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn metrics_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let (listener, port) = listener().await;
+        let server = Server::new(Config {
+            port: None,
+            socket: Some(dir.path().join("server.sock")),
+            database: dir.path().join("data.db"),
+            metrics_port: Some(port),
+            ..Config::default()
+        })
+        .with_listener(listener)
+        .unwrap();
+        assert_eq!(server.metrics_path.as_deref(), Some(DEFAULT_METRICS_PATH));
+        server.init().await.unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let server = tokio::spawn(server.start_with_shutdown(signal));
+
+        let url = format!("http://127.0.0.1:{port}/metrics");
+        let client = hyper::Client::new();
+        let res = client.get(url.parse().unwrap()).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("db_pool_connections"), "{body}");
+        assert!(body.contains("login_failures_total 0"), "{body}");
+
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(2), server).await;
+        assert!(res.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn jsonrpc_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let (listener, port) = listener().await;
+        let server = Server::new(Config {
+            port: None,
+            database: dir.path().join("data.db"),
+            jsonrpc_port: Some(port),
+            ..Config::default()
+        })
+        .with_listener(listener)
+        .unwrap();
+        server.init().await.unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let server = tokio::spawn(server.start_with_shutdown(signal));
+
+        let http = rpc::http::HttpTransport::builder()
+            .url(format!("http://127.0.0.1:{port}"))
+            .build::<String>()
+            .unwrap();
+        let client = rpc::Client::new(rpc::jsonrpc::JsonRpcTransport::new(http));
+        let reqs = vec![
+            rpc::Request::new("status", None, serde_json::Value::Null),
+            rpc::Request::new("organization", None, serde_json::json!("1")),
+        ];
+        let res = client.batch::<_, serde_json::Value, ::service::Error>(reqs);
+        let res = res.await.unwrap();
+
+        // The calls of the batch are answered in order
+        let mut res = res.into_iter();
+        let status =
+            serde_json::from_value::<::service::ServiceStatus>(res.next().unwrap().unwrap())
+                .unwrap();
+        assert_eq!(status.api_version, ::service::API_VERSION);
+        let err = res.next().unwrap().unwrap_err();
+        assert_eq!(err.kind, ::service::ErrorKind::Unauthorized);
+        assert!(res.next().is_none());
+
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(2), server).await;
+        assert!(res.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn probes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (listener, port) = listener().await;
+        let server = Server::new(Config {
+            port: Some(port),
+            database: dir.path().join("data.db"),
+            ..Config::default()
+        })
+        .with_listener(listener)
+        .unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let signal = async {
+            let _ = rx.await;
+        };
+        let db = server.db().await.unwrap();
+        let server = tokio::spawn(server.start_with_shutdown(signal));
+
+        let client = hyper::Client::new();
+        let get = |path: &str| {
+            let uri = format!("http://127.0.0.1:{port}{path}").parse().unwrap();
+            client.get(uri)
+        };
+        let res = get(HEALTH_PATH).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+
+        // The service is sealed until the DB is initialized
+        let res = get(READY_PATH).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        db::init(&db).await.unwrap();
+        let res = get(READY_PATH).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let status = serde_json::from_slice::<::service::ServiceStatus>(&body).unwrap();
+        assert_eq!(status.state, ::service::ServiceState::Ready);
+        assert_eq!(status.api_version, ::service::API_VERSION);
+        assert!(status.supports(::service::MIN_API_VERSION));
+        assert_eq!(status.database.schema_version, Some(db::SCHEMA_VERSION));
+
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(2), server).await;
+        assert!(res.unwrap().unwrap().is_ok());
     }
 }
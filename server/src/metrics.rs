@@ -0,0 +1,206 @@
+//! Metrics
+//!
+//! The metrics are exposed in the Prometheus text format.
+
+use std::{fmt, time::Instant};
+
+use ::service::rpc::{
+    self,
+    middleware::{Next, Outcome},
+    Receiver,
+};
+use async_trait::async_trait;
+use hyper::header;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::db::DbConn;
+
+/// Default path of the metrics
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// Method label of the requests to unknown methods
+///
+/// The method names sent by the clients are not used as labels, to bound their number.
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// Buckets of the request latencies, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Server metrics
+///
+/// The metrics are also a middleware, which records the requests per method.
+#[derive(Clone)]
+pub struct Metrics {
+    /// Registry of the metrics
+    registry: Registry,
+    /// Requests, by method and outcome
+    requests: IntCounterVec,
+    /// Failed requests, by method
+    errors: IntCounterVec,
+    /// Request latencies, by method
+    latency: HistogramVec,
+    /// Connections of the DB pool
+    db_connections: IntGauge,
+    /// Idle connections of the DB pool
+    db_idle_connections: IntGauge,
+    /// Failed logins
+    login_failures: IntCounter,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Instantiates new [Metrics], with their own registry
+    pub fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("rpc_requests_total", "RPC requests"),
+            &["method", "outcome"],
+        )
+        .expect("valid metric");
+        let errors = IntCounterVec::new(
+            Opts::new("rpc_errors_total", "Failed RPC requests"),
+            &["method"],
+        )
+        .expect("valid metric");
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "rpc_request_duration_seconds",
+                "Latency of the RPC requests",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method"],
+        )
+        .expect("valid metric");
+        let db_connections = IntGauge::new("db_pool_connections", "Connections of the DB pool")
+            .expect("valid metric");
+        let db_idle_connections = IntGauge::new(
+            "db_pool_idle_connections",
+            "Idle connections of the DB pool",
+        )
+        .expect("valid metric");
+        let login_failures =
+            IntCounter::new("login_failures_total", "Failed logins").expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests.clone()))
+            .and_then(|_| registry.register(Box::new(errors.clone())))
+            .and_then(|_| registry.register(Box::new(latency.clone())))
+            .and_then(|_| registry.register(Box::new(db_connections.clone())))
+            .and_then(|_| registry.register(Box::new(db_idle_connections.clone())))
+            .and_then(|_| registry.register(Box::new(login_failures.clone())))
+            .expect("unique metrics");
+
+        Self {
+            registry,
+            requests,
+            errors,
+            latency,
+            db_connections,
+            db_idle_connections,
+            login_failures,
+        }
+    }
+
+    /// Records a handled request
+    pub fn record_request(&self, method: &str, outcome: Outcome, seconds: f64) {
+        let method = match outcome {
+            Outcome::UnknownMethod => UNKNOWN_METHOD,
+            _ => method,
+        };
+        self.requests
+            .with_label_values(&[method, outcome.as_str()])
+            .inc();
+        if outcome != Outcome::Ok {
+            self.errors.with_label_values(&[method]).inc();
+        }
+        self.latency.with_label_values(&[method]).observe(seconds);
+    }
+
+    /// Records a failed login
+    pub fn record_login_failure(&self) {
+        self.login_failures.inc();
+    }
+
+    /// Returns the metrics in the Prometheus text format
+    ///
+    /// The usage of the DB pool is read at this point.
+    pub fn render(&self, db: &DbConn) -> String {
+        self.db_connections.set(db.size().into());
+        self.db_idle_connections.set(db.num_idle() as i64);
+
+        let mut buffer = vec![];
+        // The text encoder only fails on invalid metrics, which are rejected at registration
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    /// Returns the HTTP response to a scrape
+    pub fn response(&self, db: &DbConn) -> hyper::Response<hyper::Body> {
+        let mut res = hyper::Response::new(hyper::Body::from(self.render(db)));
+        if let Ok(content_type) = TextEncoder::new().format_type().parse() {
+            res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+        }
+        res
+    }
+}
+
+#[async_trait]
+impl rpc::Middleware for Metrics {
+    async fn handle<R>(&self, req: rpc::Request<Vec<u8>>, next: Next<'_, R>) -> R::Response
+    where
+        R: Receiver,
+    {
+        let method = req.method.clone();
+        let start = Instant::now();
+        let reply = next.run(req).await;
+        self.record_request(&method, reply.outcome, start.elapsed().as_secs_f64());
+        reply.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics::new();
+        metrics.record_request("login", Outcome::Ok, 0.002);
+        metrics.record_request("login", Outcome::Err, 0.3);
+        metrics.record_request("l0gin", Outcome::UnknownMethod, 0.001);
+        metrics.record_login_failure();
+
+        let families = metrics.registry.gather();
+        let text = {
+            let mut buffer = vec![];
+            TextEncoder::new().encode(&families, &mut buffer).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        assert!(text.contains(r#"rpc_requests_total{method="login",outcome="ok"} 1"#));
+        assert!(text.contains(r#"rpc_requests_total{method="login",outcome="err"} 1"#));
+        assert!(text.contains(r#"rpc_requests_total{method="unknown",outcome="unknown_method"} 1"#));
+        assert!(text.contains(r#"rpc_errors_total{method="login"} 1"#));
+        assert!(text.contains(r#"rpc_request_duration_seconds_count{method="login"} 2"#));
+        assert!(
+            text.contains(r#"rpc_request_duration_seconds_bucket{method="login",le="0.0025"} 1"#)
+        );
+        assert!(text.contains("login_failures_total 1"));
+        assert!(!text.contains("l0gin"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Password hashing
+//!
+//! The passwords are hashed with Argon2id, with a random salt per password. The
+//! hashes are stored as PHC strings (`$argon2id$v=19$m=...`), which carry their
+//! salt and cost: a change of cost only applies to the new hashes.
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use rand::rngs::OsRng;
+
+/// Cost of the password hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordCost {
+    /// Memory, in KiB
+    pub memory: u32,
+    /// Number of iterations
+    pub iterations: u32,
+    /// Degree of parallelism
+    pub parallelism: u32,
+}
+
+impl Default for PasswordCost {
+    /// Returns the recommended cost (19 MiB, 2 iterations, 1 lane)
+    fn default() -> Self {
+        Self {
+            memory: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Password hasher
+///
+/// The hashing runs on the blocking threads, as it is slow on purpose. The
+/// default hasher has the default [PasswordCost].
+#[derive(Debug, Clone, Default)]
+pub struct Passwords {
+    /// Argon2id hasher
+    argon2: Argon2<'static>,
+}
+
+impl Passwords {
+    /// Instantiates a new [Passwords] hasher
+    ///
+    /// Fails if the cost is out of the Argon2 bounds.
+    pub fn new(cost: PasswordCost) -> anyhow::Result<Self> {
+        let params = Params::new(cost.memory, cost.iterations, cost.parallelism, None)
+            .map_err(|err| anyhow::anyhow!("Invalid password cost: {err}"))?;
+        Ok(Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+
+    /// Hashes a password with a new salt
+    pub async fn hash(&self, password: String) -> anyhow::Result<String> {
+        let argon2 = self.argon2.clone();
+        tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            argon2
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|err| anyhow::anyhow!("Cannot hash password: {err}"))
+        })
+        .await?
+    }
+
+    /// Verifies a password against a hash
+    ///
+    /// The hashes are compared in constant time. An invalid hash never verifies.
+    pub async fn verify(&self, password: String, hash: String) -> bool {
+        let argon2 = self.argon2.clone();
+        tokio::task::spawn_blocking(move || match PasswordHash::new(&hash) {
+            Ok(hash) => argon2.verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(_) => false,
+        })
+        .await
+        .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn hash_and_verify() -> anyhow::Result<()> {
+        let cost = PasswordCost {
+            memory: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let passwords = Passwords::new(cost)?;
+
+        let hash = passwords.hash("password".to_string()).await?;
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"), "{hash}");
+        assert!(!hash.contains("password"));
+        assert!(passwords.verify("password".into(), hash.clone()).await);
+        assert!(!passwords.verify("wrong".into(), hash.clone()).await);
+        assert!(!passwords.verify("password".into(), "password".into()).await);
+
+        // Each hash has its own salt
+        assert_ne!(passwords.hash("password".to_string()).await?, hash);
+
+        // The hashes carry their cost
+        assert!(Passwords::default().verify("password".into(), hash).await);
+
+        let cost = PasswordCost { memory: 1, ..cost };
+        assert!(Passwords::new(cost).is_err());
+        Ok(())
+    }
+}
@@ -1,45 +1,115 @@
 //! Service implementation
 
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use async_trait::async_trait;
-use service::*;
+use rand::{distributions::Alphanumeric, Rng};
+use service::{
+    rpc::{events::Broker, Credentials},
+    *,
+};
+
+use crate::{
+    auth::{Access, Principal, Resource},
+    db::{self, DbConn},
+    metrics::Metrics,
+    password::Passwords,
+};
+
+/// Length of the session tokens
+const TOKEN_LEN: usize = 32;
+
+/// Default lifetime of the sessions (30 days)
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Maximum length of a session label
+const MAX_LABEL_LEN: usize = 64;
 
-use crate::db::DbConn;
+/// Prefix of the service tokens, which tells them from the session tokens
+const SERVICE_TOKEN_PREFIX: &str = "st_";
+
+/// Length of the service tokens, without their prefix
+const SERVICE_TOKEN_LEN: usize = 40;
+
+/// Default lifetime of the service tokens (90 days)
+pub const DEFAULT_SERVICE_TOKEN_TTL: Duration = Duration::from_secs(90 * 24 * 3600);
 
 /// Secrets service implementation
 #[derive(Debug, Clone)]
 pub struct Service {
     /// DB connection
     db: DbConn,
+    /// Secret events
+    events: Broker,
+    /// Metrics
+    metrics: Metrics,
+    /// Password hasher
+    passwords: Passwords,
+    /// Lifetime of the sessions
+    session_ttl: Duration,
+    /// Start time of the service
+    started: Instant,
 }
 
 impl Service {
     /// Instantiates a new [Service]
     pub fn new(db: DbConn) -> Self {
-        Self { db }
+        Self {
+            db,
+            events: Broker::new(),
+            metrics: Metrics::new(),
+            passwords: Passwords::default(),
+            session_ttl: DEFAULT_SESSION_TTL,
+            started: Instant::now(),
+        }
     }
-}
 
-#[async_trait]
-impl rpc::Handler for Service {
-    async fn handle<R>(&self, receiver: R, request: R::Request) -> R::Response
-    where
-        R: rpc::Receiver,
-    {
-        let req = match receiver.decode_request::<String>(request).await {
-            Ok(ok) => ok,
-            Err(err) => return receiver.encode_err(err).await,
-        };
+    /// Sets the password hasher (eg. with another cost)
+    pub fn with_passwords(mut self, passwords: Passwords) -> Self {
+        self.passwords = passwords;
+        self
+    }
 
-        match req.method.as_str() {
-            "status" => {
-                // let _data = receiver.decode_payload::<(), Error>(&req.data).await;
-                let res = self.status().await;
-                return receiver.encode_response(res).await;
-            }
-            m => {
-                // Invalid method => return an error response
-                return receiver.encode_err(format!("Invalid method: {m}")).await;
-            }
+    /// Sets the lifetime of the sessions
+    pub fn with_session_ttl(mut self, session_ttl: Duration) -> Self {
+        self.session_ttl = session_ttl;
+        self
+    }
+
+    /// Returns the broker of the secret events
+    pub fn events(&self) -> &Broker {
+        &self.events
+    }
+
+    /// Returns the metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns the DB connection
+    pub(crate) fn db(&self) -> &DbConn {
+        &self.db
+    }
+
+    /// Returns the service status
+    ///
+    /// The service is sealed if its database is unreachable or has another schema version.
+    pub async fn service_status(&self) -> ServiceStatus {
+        let schema_version = db::schema_version(&self.db).await.ok();
+        let state = match schema_version {
+            Some(db::SCHEMA_VERSION) => ServiceState::Ready,
+            _ => ServiceState::Sealed,
+        };
+        ServiceStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            api_version: API_VERSION,
+            min_api_version: MIN_API_VERSION,
+            uptime: self.started.elapsed().as_secs(),
+            database: DatabaseStatus {
+                reachable: schema_version.is_some(),
+                schema_version,
+            },
+            state,
         }
     }
 }
@@ -48,80 +118,2188 @@ impl rpc::Handler for Service {
 impl SecretsService for Service {
     /// Returns the API status
     async fn status(&self) -> Result<ServiceStatus, Error> {
-        todo!()
+        Ok(self.service_status().await)
     }
 
     /// Signup a new user
+    ///
+    /// The password is stored as an Argon2id hash.
     async fn signup(&self, input: SignupInput) -> Result<LoginResponse, Error> {
-        todo!()
+        check_label(input.label.as_deref())?;
+        let password_hash = self
+            .passwords
+            .hash(input.password)
+            .await
+            .map_err(|err| Error::internal(err.to_string()))?;
+        let user = db::users::insert(&self.db, &input.email, &input.name, &password_hash)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::conflict(format!("Email already used: {}", input.email)))?;
+        let (token, session) = self.new_session(&user, input.label.as_deref()).await?;
+        Ok(LoginResponse {
+            token,
+            user,
+            session,
+        })
     }
 
     /// Login a new user
+    ///
+    /// An unknown email costs a hash, like a wrong password, so that the emails
+    /// cannot be probed from the response time.
     async fn login(&self, input: LoginInput) -> Result<LoginResponse, Error> {
-        todo!()
+        check_label(input.label.as_deref())?;
+        let user = db::users::get_with_password(&self.db, &input.email)
+            .await
+            .map_err(db_err)?;
+        let verified = match user {
+            Some((user, hash)) => self
+                .passwords
+                .verify(input.password, hash)
+                .await
+                .then_some(user),
+            None => {
+                let _ = self.passwords.hash(input.password).await;
+                None
+            }
+        };
+        let user = match verified {
+            Some(user) => user,
+            None => {
+                self.metrics.record_login_failure();
+                return Err(Error::unauthorized("Invalid email or password"));
+            }
+        };
+
+        let (token, session) = self.new_session(&user, input.label.as_deref()).await?;
+        Ok(LoginResponse {
+            token,
+            user,
+            session,
+        })
+    }
+
+    /// Logouts, by revoking the session of the token
+    async fn logout(&self, auth: Credentials) -> Result<(), Error> {
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        let session_id = principal
+            .session_id()
+            .ok_or_else(|| Error::invalid_input("A client certificate has no session"))?;
+        db::sessions::delete(&self.db, session_id, user_id)
+            .await
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Lists the sessions of the user
+    async fn sessions(&self, auth: Credentials) -> Result<Vec<Session>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        db::sessions::list(
+            &self.db,
+            principal.user_id()?,
+            principal.session_id(),
+            now(),
+        )
+        .await
+        .map_err(db_err)
+    }
+
+    /// Revokes a session of the user
+    async fn revoke_session(&self, auth: Credentials, id: String) -> Result<(), Error> {
+        let user_id = self.authenticate(&auth).await?.user_id()?;
+        if !db::sessions::delete(&self.db, parse_id(&id)?, user_id)
+            .await
+            .map_err(db_err)?
+        {
+            return Err(Error::not_found(format!("Session not found: {id}")));
+        }
+        Ok(())
     }
 
     /// Reads a user
-    async fn user(&self, token: String, id: String) -> Result<User, Error> {
-        todo!()
+    ///
+    /// A user only reads itself and the members of its organizations: the others
+    /// are reported as not found.
+    async fn user(&self, auth: Credentials, id: String) -> Result<User, Error> {
+        let user_id = self.authenticate(&auth).await?.user_id()?;
+        let other_id = parse_id(&id)?;
+        if other_id != user_id
+            && !db::members::share_organization(&self.db, user_id, other_id)
+                .await
+                .map_err(db_err)?
+        {
+            return Err(Error::not_found(format!("User not found: {id}")));
+        }
+        self.get_user(&id).await
     }
 
     /// Deletes a user
-    async fn delete_user(&self, token: String, id: String) -> Result<User, Error> {
-        todo!()
+    async fn delete_user(&self, auth: Credentials, id: String) -> Result<User, Error> {
+        let user_id = self.authenticate(&auth).await?.user_id()?;
+        let user = self.get_user(&id).await?;
+        if user.id != user_id.to_string() {
+            return Err(Error::forbidden("Cannot delete another user"));
+        }
+        let owned = db::members::sole_owner_of(&self.db, user_id)
+            .await
+            .map_err(db_err)?;
+        if let Some(org_id) = owned.first() {
+            return Err(Error::conflict(format!(
+                "Only owner of organization {org_id}: transfer or delete it first"
+            )));
+        }
+
+        db::users::delete(&self.db, user_id).await.map_err(db_err)?;
+        Ok(user)
     }
 
-    /// Add an organization
+    /// Add an organization, of which the user becomes the owner
     async fn add_organization(
         &self,
-        token: String,
+        auth: Credentials,
         organization: OrganizationInput,
     ) -> Result<Organization, Error> {
-        todo!()
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        let org = db::orgs::insert(&self.db, &organization.name)
+            .await
+            .map_err(db_err)?;
+        db::members::upsert(&self.db, parse_id(&org.id)?, user_id, Role::Owner)
+            .await
+            .map_err(db_err)?;
+        self.record(&principal, "organization.add", &org.id, &org.id)
+            .await;
+        Ok(org)
     }
 
     /// Reads an organization
-    async fn organization(&self, token: String, id: String) -> Result<Organization, Error> {
-        todo!()
+    async fn organization(&self, auth: Credentials, id: String) -> Result<Organization, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(&principal, Access::Read, Resource::Organization(&id))
+            .await?;
+        self.get_organization(&id).await
     }
 
     /// Deletes an organization
-    async fn delete_organization(&self, token: String, id: String) -> Result<Organization, Error> {
-        todo!()
+    ///
+    /// Its audit trail is kept.
+    async fn delete_organization(
+        &self,
+        auth: Credentials,
+        id: String,
+    ) -> Result<Organization, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(&principal, Access::Write, Resource::Organization(&id))
+            .await?;
+        let org = self.get_organization(&id).await?;
+        db::orgs::delete(&self.db, parse_id(&org.id)?)
+            .await
+            .map_err(db_err)?;
+        self.record(&principal, "organization.delete", &org.id, &org.id)
+            .await;
+        Ok(org)
     }
 
     /// Add a project
-    async fn add_project(&self, token: String, project: ProjectInput) -> Result<Project, Error> {
-        todo!()
+    async fn add_project(
+        &self,
+        auth: Credentials,
+        project: ProjectInput,
+    ) -> Result<Project, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&project.org_id),
+        )
+        .await?;
+        let org = self.get_organization(&project.org_id).await?;
+        let project = db::projects::insert(&self.db, &project.name, org)
+            .await
+            .map_err(db_err)?;
+        self.record(
+            &principal,
+            "project.add",
+            &project.organization.id,
+            &project.id,
+        )
+        .await;
+        Ok(project)
     }
 
     /// Reads a project
-    async fn project(&self, token: String, id: String) -> Result<Project, Error> {
-        todo!()
+    async fn project(&self, auth: Credentials, id: String) -> Result<Project, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let project = self.get_project(&id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Read,
+            resource_project(&project),
+            "Project",
+            &id,
+        )
+        .await?;
+        Ok(project)
     }
 
     /// Deletes a project
-    async fn delete_project(&self, token: String, id: String) -> Result<Project, Error> {
-        todo!()
+    async fn delete_project(&self, auth: Credentials, id: String) -> Result<Project, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let project = self.get_project(&id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Write,
+            resource_project(&project),
+            "Project",
+            &id,
+        )
+        .await?;
+        db::projects::delete(&self.db, parse_id(&project.id)?)
+            .await
+            .map_err(db_err)?;
+        self.record(
+            &principal,
+            "project.delete",
+            &project.organization.id,
+            &project.id,
+        )
+        .await;
+        Ok(project)
     }
 
     /// Adds a secret
-    async fn add_secret(&self, token: String, secret: SecretInput) -> Result<Secret, Error> {
-        todo!()
+    async fn add_secret(&self, auth: Credentials, secret: SecretInput) -> Result<Secret, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(
+            &principal,
+            Access::Write,
+            Resource::Secret {
+                org_id: &secret.org_id,
+                project_id: secret.project_id.as_deref(),
+                environment: secret.environment.as_deref(),
+                key: &secret.key,
+            },
+        )
+        .await?;
+        let org = self.get_organization(&secret.org_id).await?;
+        let project = match &secret.project_id {
+            Some(project_id) => {
+                let project = self.get_project(project_id).await?;
+                // The projects of the other organizations are not disclosed
+                if project.organization != org {
+                    return Err(Error::not_found(format!("Project not found: {project_id}")));
+                }
+                Some(project)
+            }
+            None => None,
+        };
+
+        let secret = db::secrets::insert(
+            &self.db,
+            org,
+            project,
+            secret.environment.as_deref(),
+            &secret.key,
+            &secret.value,
+        )
+        .await
+        .map_err(db_err)?;
+        self.record_secret(&principal, "secret.add", &secret).await;
+        self.publish(SecretEventKind::Added, &secret);
+        Ok(secret)
     }
 
     /// Reads a secret
-    async fn secret(&self, token: String, id: String) -> Result<Secret, Error> {
-        todo!()
+    async fn secret(&self, auth: Credentials, id: String) -> Result<Secret, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let secret = self.get_secret(&id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Read,
+            resource_secret(&secret),
+            "Secret",
+            &id,
+        )
+        .await?;
+        self.record_secret(&principal, "secret.read", &secret).await;
+        Ok(secret)
     }
 
     /// Update a secret
-    async fn update_secret(&self, token: String, secret: Secret) -> Result<Secret, Error> {
-        todo!()
+    ///
+    /// The environment of a secret cannot be changed.
+    async fn update_secret(&self, auth: Credentials, secret: Secret) -> Result<Secret, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let existing = self.get_secret(&secret.id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Write,
+            resource_secret(&existing),
+            "Secret",
+            &secret.id,
+        )
+        .await?;
+        if secret.key != existing.key {
+            let renamed = Secret {
+                key: secret.key.clone(),
+                ..existing.clone()
+            };
+            self.authorize(&principal, Access::Write, resource_secret(&renamed))
+                .await?;
+        }
+        db::secrets::update(
+            &self.db,
+            parse_id(&existing.id)?,
+            &secret.key,
+            &secret.value,
+        )
+        .await
+        .map_err(db_err)?;
+        let secret = Secret {
+            key: secret.key,
+            value: secret.value,
+            ..existing
+        };
+        self.record_secret(&principal, "secret.update", &secret)
+            .await;
+        self.publish(SecretEventKind::Updated, &secret);
+        Ok(secret)
     }
 
     /// Deletes a secret
-    async fn delete_secret(&self, token: String, id: String) -> Result<Secret, Error> {
-        todo!()
+    async fn delete_secret(&self, auth: Credentials, id: String) -> Result<Secret, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let secret = self.get_secret(&id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Write,
+            resource_secret(&secret),
+            "Secret",
+            &id,
+        )
+        .await?;
+        db::secrets::delete(&self.db, parse_id(&secret.id)?)
+            .await
+            .map_err(db_err)?;
+        self.record_secret(&principal, "secret.delete", &secret)
+            .await;
+        self.publish(SecretEventKind::Deleted, &secret);
+        Ok(secret)
+    }
+
+    /// Authorizes a subscription to the secret events of a topic
+    async fn subscribe(&self, auth: Credentials, topic: String) -> Result<(), Error> {
+        let principal = self.authenticate(&auth).await?;
+        match topic.parse::<Topic>()? {
+            Topic::Organization(id) => {
+                self.authorize(
+                    &principal,
+                    Access::Read,
+                    Resource::Events {
+                        org_id: &id,
+                        project_id: None,
+                    },
+                )
+                .await?;
+                self.get_organization(&id).await.map(|_| ())
+            }
+            Topic::Project(id) => {
+                let project = self.get_project(&id).await?;
+                self.authorize_found(
+                    &principal,
+                    Access::Read,
+                    Resource::Events {
+                        org_id: &project.organization.id,
+                        project_id: Some(&project.id),
+                    },
+                    "Project",
+                    &id,
+                )
+                .await
+                .map(|_| ())
+            }
+        }
+    }
+
+    /// Creates a service token
+    ///
+    /// Only the users manage the service tokens.
+    async fn add_service_token(
+        &self,
+        auth: Credentials,
+        input: ServiceTokenInput,
+    ) -> Result<ServiceTokenResponse, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        self.authorize(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&input.org_id),
+        )
+        .await?;
+        if input.name.trim().is_empty() {
+            return Err(Error::invalid_input("Empty service token name"));
+        }
+        let org = self.get_organization(&input.org_id).await?;
+        let project_id = match &input.project_id {
+            Some(project_id) => {
+                let project = self.get_project(project_id).await?;
+                // The projects of the other organizations are not disclosed
+                if project.organization != org {
+                    return Err(Error::not_found(format!("Project not found: {project_id}")));
+                }
+                Some(parse_id(&project.id)?)
+            }
+            None => None,
+        };
+
+        let token = format!(
+            "{SERVICE_TOKEN_PREFIX}{}",
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(SERVICE_TOKEN_LEN)
+                .map(char::from)
+                .collect::<String>()
+        );
+        let now = now();
+        let ttl = input.ttl.unwrap_or(DEFAULT_SERVICE_TOKEN_TTL.as_secs());
+        let service_token = db::service_tokens::insert(
+            &self.db,
+            db::service_tokens::NewServiceToken {
+                token: &token,
+                name: &input.name,
+                org_id: parse_id(&org.id)?,
+                project_id,
+                environment: input.environment.as_deref(),
+                scope: input.scope,
+                created_by: user_id,
+                created_at: now,
+                expires_at: now.saturating_add(i64::try_from(ttl).unwrap_or(i64::MAX)),
+            },
+        )
+        .await
+        .map_err(db_err)?;
+        self.record(&principal, "service_token.add", &org.id, &service_token.id)
+            .await;
+        Ok(ServiceTokenResponse {
+            token,
+            service_token,
+        })
+    }
+
+    /// Lists the service tokens of an organization
+    async fn service_tokens(
+        &self,
+        auth: Credentials,
+        org_id: String,
+    ) -> Result<Vec<ServiceToken>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(&principal, Access::Admin, Resource::Organization(&org_id))
+            .await?;
+        let org = self.get_organization(&org_id).await?;
+        db::service_tokens::list(&self.db, parse_id(&org.id)?)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Revokes a service token
+    ///
+    /// A revoked token is kept, with its revocation time.
+    async fn revoke_service_token(
+        &self,
+        auth: Credentials,
+        id: String,
+    ) -> Result<ServiceToken, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let token_id = parse_id(&id)?;
+        let token = db::service_tokens::get(&self.db, token_id)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Service token not found: {id}")))?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&token.org_id),
+            "Service token",
+            &id,
+        )
+        .await?;
+        db::service_tokens::revoke(&self.db, token_id, now())
+            .await
+            .map_err(db_err)?;
+        self.record(&principal, "service_token.revoke", &token.org_id, &token.id)
+            .await;
+        db::service_tokens::get(&self.db, token_id)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Service token not found: {id}")))
+    }
+
+    /// Lists the audit trail of an organization
+    async fn audit(&self, auth: Credentials, org_id: String) -> Result<Vec<AuditEvent>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(&principal, Access::Admin, Resource::Organization(&org_id))
+            .await?;
+        db::audit::list(&self.db, parse_id(&org_id)?)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Lists the members of an organization
+    ///
+    /// Only the users list the members, as they include their emails.
+    async fn members(&self, auth: Credentials, org_id: String) -> Result<Vec<Member>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        principal.user_id()?;
+        self.authorize(&principal, Access::Read, Resource::Organization(&org_id))
+            .await?;
+        db::members::list(&self.db, parse_id(&org_id)?)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Invites a user to an organization
+    ///
+    /// Only an owner invites an owner. A new invitation of the same email
+    /// replaces the pending one.
+    async fn invite(&self, auth: Credentials, input: InvitationInput) -> Result<Invitation, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        let role = self
+            .authorize(
+                &principal,
+                Access::Admin,
+                Resource::Organization(&input.org_id),
+            )
+            .await?;
+        check_grant(role, None, input.role)?;
+        if !input.email.contains('@') {
+            return Err(Error::invalid_input(format!(
+                "Invalid email: {}",
+                input.email
+            )));
+        }
+        let org = self.get_organization(&input.org_id).await?;
+        let invitation =
+            db::members::invite(&self.db, org, &input.email, input.role, user_id, now())
+                .await
+                .map_err(db_err)?;
+        self.record(
+            &principal,
+            "member.invite",
+            &invitation.organization.id,
+            &invitation.id,
+        )
+        .await;
+        Ok(invitation)
+    }
+
+    /// Lists the pending invitations of the user
+    async fn invitations(&self, auth: Credentials) -> Result<Vec<Invitation>, Error> {
+        let user_id = self.authenticate(&auth).await?.user_id()?;
+        let user = self.get_user(&user_id.to_string()).await?;
+        db::members::invitations(&self.db, &user.email)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Accepts an invitation of the user
+    async fn accept_invitation(&self, auth: Credentials, id: String) -> Result<Member, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        let user = self.get_user(&user_id.to_string()).await?;
+        let org_id = db::members::accept(&self.db, parse_id(&id)?, &user.email, user_id)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Invitation not found: {id}")))?;
+        self.record(&principal, "member.accept", &org_id.to_string(), &user.id)
+            .await;
+        self.get_member(org_id, user_id).await
+    }
+
+    /// Changes the role of a member
+    ///
+    /// Only an owner changes the role of an owner, or grants the owner role. The
+    /// last owner cannot be demoted.
+    async fn change_role(&self, auth: Credentials, input: RoleInput) -> Result<Member, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let role = self
+            .authorize(
+                &principal,
+                Access::Admin,
+                Resource::Organization(&input.org_id),
+            )
+            .await?;
+        let org_id = parse_id(&input.org_id)?;
+        let member = self.get_member(org_id, parse_id(&input.user_id)?).await?;
+        check_grant(role, Some(member.role), input.role)?;
+        if member.role == Role::Owner && input.role != Role::Owner {
+            self.check_other_owner(org_id).await?;
+        }
+        db::members::upsert(&self.db, org_id, parse_id(&member.user.id)?, input.role)
+            .await
+            .map_err(db_err)?;
+        self.record(
+            &principal,
+            "member.change_role",
+            &input.org_id,
+            &member.user.id,
+        )
+        .await;
+        Ok(Member {
+            role: input.role,
+            ..member
+        })
+    }
+
+    /// Removes a member from an organization
+    ///
+    /// A member may leave an organization, unless its last owner. Only an owner
+    /// removes an owner.
+    async fn remove_member(&self, auth: Credentials, input: MemberInput) -> Result<Member, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let org_id = parse_id(&input.org_id)?;
+        let member_id = parse_id(&input.user_id)?;
+        // A member (eg. a guest) may leave an organization which it cannot read
+        if principal.user_id()? != member_id {
+            let role = self
+                .authorize(
+                    &principal,
+                    Access::Admin,
+                    Resource::Organization(&input.org_id),
+                )
+                .await?;
+            let member = self.get_member(org_id, member_id).await?;
+            check_grant(role, Some(member.role), Role::Viewer)?;
+        }
+        let member = self.get_member(org_id, member_id).await?;
+        if member.role == Role::Owner {
+            self.check_other_owner(org_id).await?;
+        }
+        db::members::delete(&self.db, org_id, member_id)
+            .await
+            .map_err(db_err)?;
+        self.record(&principal, "member.remove", &input.org_id, &member.user.id)
+            .await;
+        Ok(member)
+    }
+
+    /// Adds a team to an organization
+    async fn add_team(&self, auth: Credentials, input: TeamInput) -> Result<Team, Error> {
+        let principal = self.authenticate(&auth).await?;
+        self.authorize(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&input.org_id),
+        )
+        .await?;
+        if input.name.trim().is_empty() {
+            return Err(Error::invalid_input("Empty team name"));
+        }
+        let org = self.get_organization(&input.org_id).await?;
+        let team = db::teams::insert(&self.db, parse_id(&org.id)?, &input.name)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::conflict(format!("Team already exists: {}", input.name)))?;
+        self.record(&principal, "team.add", &org.id, &team.id).await;
+        Ok(team)
+    }
+
+    /// Lists the teams of an organization
+    ///
+    /// Only the users list the teams, as they include their users.
+    async fn teams(&self, auth: Credentials, org_id: String) -> Result<Vec<Team>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        principal.user_id()?;
+        self.authorize(&principal, Access::Read, Resource::Organization(&org_id))
+            .await?;
+        db::teams::list(&self.db, parse_id(&org_id)?)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Adds a user to a team
+    ///
+    /// The user must be a member of the organization (eg. a guest).
+    async fn add_team_member(
+        &self,
+        auth: Credentials,
+        input: TeamMemberInput,
+    ) -> Result<Team, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let team = self.get_team(&input.team_id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&team.org_id),
+            "Team",
+            &input.team_id,
+        )
+        .await?;
+        let member = self
+            .get_member(parse_id(&team.org_id)?, parse_id(&input.user_id)?)
+            .await?;
+        db::teams::add_member(&self.db, parse_id(&team.id)?, parse_id(&member.user.id)?)
+            .await
+            .map_err(db_err)?;
+        self.record(&principal, "team.add_member", &team.org_id, &team.id)
+            .await;
+        self.get_team(&team.id).await
+    }
+
+    /// Removes a user from a team
+    async fn remove_team_member(
+        &self,
+        auth: Credentials,
+        input: TeamMemberInput,
+    ) -> Result<Team, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let team = self.get_team(&input.team_id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            Resource::Organization(&team.org_id),
+            "Team",
+            &input.team_id,
+        )
+        .await?;
+        if !db::teams::remove_member(&self.db, parse_id(&team.id)?, parse_id(&input.user_id)?)
+            .await
+            .map_err(db_err)?
+        {
+            return Err(Error::not_found(format!(
+                "User {} is not in team {}",
+                input.user_id, team.id
+            )));
+        }
+        self.record(&principal, "team.remove_member", &team.org_id, &team.id)
+            .await;
+        self.get_team(&team.id).await
+    }
+
+    /// Grants a permission on a project
+    ///
+    /// The grants are managed by the admins of the organization, and by the
+    /// subjects of an admin grant on the project.
+    async fn add_grant(&self, auth: Credentials, input: GrantInput) -> Result<Grant, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let user_id = principal.user_id()?;
+        let project = self.get_project(&input.project_id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            resource_project(&project),
+            "Project",
+            &input.project_id,
+        )
+        .await?;
+        if input.environment.as_deref() == Some("") || input.key_prefix.as_deref() == Some("") {
+            return Err(Error::invalid_input(
+                "Empty grant environment or key prefix",
+            ));
+        }
+        let org_id = &project.organization.id;
+        match &input.subject {
+            Subject::User(id) => self
+                .get_member(parse_id(org_id)?, parse_id(id)?)
+                .await
+                .map(|_| ())?,
+            Subject::Team(id) => {
+                let team = db::teams::get(&self.db, parse_id(id)?)
+                    .await
+                    .map_err(db_err)?;
+                if team.is_none_or(|team| &team.org_id != org_id) {
+                    return Err(Error::not_found(format!("Team not found: {id}")));
+                }
+            }
+            Subject::ServiceToken(id) => {
+                let token = db::service_tokens::get(&self.db, parse_id(id)?)
+                    .await
+                    .map_err(db_err)?;
+                if token.is_none_or(|token| &token.org_id != org_id) {
+                    return Err(Error::not_found(format!("Service token not found: {id}")));
+                }
+            }
+        }
+
+        let grant = db::grants::insert(
+            &self.db,
+            db::grants::NewGrant {
+                subject: &input.subject,
+                project_id: parse_id(&project.id)?,
+                environment: input.environment.as_deref(),
+                key_prefix: input.key_prefix.as_deref(),
+                permission: input.permission,
+                created_by: user_id,
+                created_at: now(),
+            },
+        )
+        .await
+        .map_err(db_err)?;
+        self.record(&principal, "grant.add", org_id, &grant.id)
+            .await;
+        Ok(grant)
+    }
+
+    /// Lists the grants of a project
+    async fn grants(&self, auth: Credentials, project_id: String) -> Result<Vec<Grant>, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let project = self.get_project(&project_id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            resource_project(&project),
+            "Project",
+            &project_id,
+        )
+        .await?;
+        db::grants::list(&self.db, parse_id(&project.id)?)
+            .await
+            .map_err(db_err)
+    }
+
+    /// Revokes a grant
+    async fn revoke_grant(&self, auth: Credentials, id: String) -> Result<Grant, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let grant = db::grants::get(&self.db, parse_id(&id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Grant not found: {id}")))?;
+        let project = self.get_project(&grant.project_id).await?;
+        self.authorize_found(
+            &principal,
+            Access::Admin,
+            resource_project(&project),
+            "Grant",
+            &id,
+        )
+        .await?;
+        db::grants::delete(&self.db, parse_id(&grant.id)?)
+            .await
+            .map_err(db_err)?;
+        self.record(
+            &principal,
+            "grant.revoke",
+            &project.organization.id,
+            &grant.id,
+        )
+        .await;
+        Ok(grant)
+    }
+
+    /// Explains the permissions of a user or a service token on a secret
+    ///
+    /// The read and write permissions are on the secret, the admin permission is
+    /// on its project. A principal explains its own permissions on the projects
+    /// it reads; the admins of a project explain the permissions of the others.
+    async fn effective_permissions(
+        &self,
+        auth: Credentials,
+        input: PermissionsInput,
+    ) -> Result<EffectivePermissions, Error> {
+        let principal = self.authenticate(&auth).await?;
+        let project = self.get_project(&input.project_id).await?;
+        let project_resource = resource_project(&project);
+        let own = match &input.subject {
+            None => true,
+            Some(Subject::User(id)) => principal.user_id().ok() == Some(parse_id(id)?),
+            Some(Subject::ServiceToken(id)) => principal.service_token_id() == Some(id.as_str()),
+            Some(Subject::Team(_)) => false,
+        };
+        let access = if own { Access::Read } else { Access::Admin };
+        self.authorize_found(
+            &principal,
+            access,
+            project_resource,
+            "Project",
+            &input.project_id,
+        )
+        .await?;
+        let subject = match input.subject {
+            Some(subject) if !own => {
+                self.get_principal(&subject, &project.organization.id)
+                    .await?
+            }
+            _ => principal,
+        };
+
+        if let Principal::ServiceToken(token) = &subject {
+            if token.revoked_at.is_some() || token.expires_at <= now() {
+                let decision = Decision {
+                    allowed: false,
+                    reason: format!("Service token {} is revoked or expired", token.name),
+                };
+                return Ok(EffectivePermissions {
+                    read: decision.clone(),
+                    write: decision.clone(),
+                    admin: decision,
+                });
+            }
+        }
+
+        let secret = Resource::Secret {
+            org_id: &project.organization.id,
+            project_id: Some(&project.id),
+            environment: input.environment.as_deref(),
+            key: &input.key,
+        };
+        let (role, grants) = self.permissions(&subject, secret).await?;
+        let decide = |access, resource| {
+            let decision = subject.decide(access, resource, role, &grants);
+            Decision {
+                allowed: decision.is_ok(),
+                reason: decision.unwrap_or_else(|reason| reason),
+            }
+        };
+        Ok(EffectivePermissions {
+            read: decide(Access::Read, secret),
+            write: decide(Access::Write, secret),
+            admin: decide(Access::Admin, project_resource),
+        })
+    }
+}
+
+impl Service {
+    /// Checks the access of a principal to a resource
+    ///
+    /// Returns the role of a user in the organization of the resource, [None] for
+    /// a service token.
+    async fn authorize(
+        &self,
+        principal: &Principal,
+        access: Access,
+        resource: Resource<'_>,
+    ) -> Result<Option<Role>, Error> {
+        let (role, grants) = self.permissions(principal, resource).await?;
+        principal.check(access, resource, role, &grants)?;
+        Ok(role)
+    }
+
+    /// Checks the access of a principal to a resource read by ID
+    ///
+    /// A principal which cannot read the resource gets it reported as not found,
+    /// like a missing one (eg. `Project not found: 1`), not to disclose it exists.
+    async fn authorize_found(
+        &self,
+        principal: &Principal,
+        access: Access,
+        resource: Resource<'_>,
+        name: &str,
+        id: &str,
+    ) -> Result<Option<Role>, Error> {
+        let (role, grants) = self.permissions(principal, resource).await?;
+        match principal.check(access, resource, role, &grants) {
+            Ok(()) => Ok(role),
+            Err(err)
+                if principal
+                    .decide(Access::Read, resource, role, &grants)
+                    .is_ok() =>
+            {
+                Err(err)
+            }
+            Err(_) => Err(Error::not_found(format!("{name} not found: {id}"))),
+        }
+    }
+
+    /// Returns the role of a principal in the organization of a resource, and
+    /// its grants on the project of the resource
+    async fn permissions(
+        &self,
+        principal: &Principal,
+        resource: Resource<'_>,
+    ) -> Result<(Option<Role>, Vec<Grant>), Error> {
+        let project_id = resource.project_id().map(parse_id).transpose()?;
+        match principal {
+            Principal::User { id, .. } => {
+                let role = db::members::role(&self.db, parse_id(resource.org_id())?, *id)
+                    .await
+                    .map_err(db_err)?;
+                // The grants of a removed member no longer apply
+                let grants = match project_id.filter(|_| role.is_some()) {
+                    Some(project_id) => db::grants::for_user(&self.db, project_id, *id)
+                        .await
+                        .map_err(db_err)?,
+                    None => vec![],
+                };
+                Ok((role, grants))
+            }
+            Principal::ServiceToken(token) => {
+                let grants = match project_id {
+                    Some(project_id) => {
+                        db::grants::for_service_token(&self.db, project_id, parse_id(&token.id)?)
+                            .await
+                            .map_err(db_err)?
+                    }
+                    None => vec![],
+                };
+                Ok((None, grants))
+            }
+        }
+    }
+
+    /// Fails if an organization has no other owner
+    async fn check_other_owner(&self, org_id: i64) -> Result<(), Error> {
+        let owners = db::members::count_owners(&self.db, org_id)
+            .await
+            .map_err(db_err)?;
+        if owners <= 1 {
+            return Err(Error::conflict(format!(
+                "Organization {org_id} must keep an owner"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads a member or fails if not found
+    async fn get_member(&self, org_id: i64, user_id: i64) -> Result<Member, Error> {
+        db::members::get(&self.db, org_id, user_id)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Member not found: {user_id}")))
+    }
+
+    /// Checks the credentials and returns the authenticated [Principal]
+    ///
+    /// A session token must belong to a session which has not expired. A service
+    /// token must neither be expired nor revoked. A client certificate has no session.
+    async fn authenticate(&self, auth: &Credentials) -> Result<Principal, Error> {
+        match auth {
+            Credentials::Token(token) if token.starts_with(SERVICE_TOKEN_PREFIX) => {
+                db::service_tokens::find(&self.db, token, now())
+                    .await
+                    .map_err(db_err)?
+                    .map(Principal::ServiceToken)
+                    .ok_or_else(|| Error::unauthorized("Invalid, expired or revoked service token"))
+            }
+            Credentials::Token(token) => db::sessions::find(&self.db, token, now())
+                .await
+                .map_err(db_err)?
+                .map(|(session_id, user_id)| Principal::User {
+                    id: user_id,
+                    session_id: Some(session_id),
+                })
+                .ok_or_else(|| Error::unauthorized("Invalid or expired token")),
+            Credentials::Certificate(subject) => db::certificates::user_id(&self.db, subject)
+                .await
+                .map_err(db_err)?
+                .map(|user_id| Principal::User {
+                    id: user_id,
+                    session_id: None,
+                })
+                .ok_or_else(|| Error::unauthorized(format!("Unknown certificate: {subject}"))),
+        }
+    }
+
+    /// Records an action on a resource of an organization in the audit trail
+    ///
+    /// A failure is only logged, as the action is already done.
+    async fn record(&self, principal: &Principal, action: &str, org_id: &str, resource_id: &str) {
+        let event = AuditEvent {
+            at: now(),
+            user_id: principal.user_id().ok().map(|id| id.to_string()),
+            service_token_id: principal.service_token_id().map(String::from),
+            action: action.to_string(),
+            org_id: Some(org_id.to_string()),
+            resource_id: Some(resource_id.to_string()),
+        };
+        if let Err(err) = db::audit::insert(&self.db, &event).await {
+            tracing::error!("Cannot record audit event {action}: {err}");
+        }
+    }
+
+    /// Records an action on a secret in the audit trail
+    async fn record_secret(&self, principal: &Principal, action: &str, secret: &Secret) {
+        self.record(principal, action, &secret.oeganization.id, &secret.id)
+            .await;
+    }
+
+    /// Publishes a secret event to its topics
+    fn publish(&self, kind: SecretEventKind, secret: &Secret) {
+        let event = SecretEvent::new(kind, secret);
+        for topic in event.topics() {
+            // An event is only data, it always serializes
+            let _ = self.events.publish(topic.to_string(), &event);
+        }
+    }
+
+    /// Opens a new session for a user and returns its token
+    ///
+    /// The expired sessions are purged at this point.
+    async fn new_session(
+        &self,
+        user: &User,
+        label: Option<&str>,
+    ) -> Result<(String, Session), Error> {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LEN)
+            .map(char::from)
+            .collect();
+        let now = now();
+        let expires_at = now.saturating_add(self.session_ttl.as_secs() as i64);
+        db::sessions::delete_expired(&self.db, now)
+            .await
+            .map_err(db_err)?;
+        let session = db::sessions::insert(
+            &self.db,
+            &token,
+            parse_id(&user.id)?,
+            label,
+            now,
+            expires_at,
+        )
+        .await
+        .map_err(db_err)?;
+        Ok((token, session))
+    }
+
+    /// Reads a user or fails if not found
+    async fn get_user(&self, id: &str) -> Result<User, Error> {
+        db::users::get(&self.db, parse_id(id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("User not found: {id}")))
+    }
+
+    /// Reads an organization or fails if not found
+    async fn get_organization(&self, id: &str) -> Result<Organization, Error> {
+        db::orgs::get(&self.db, parse_id(id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Organization not found: {id}")))
+    }
+
+    /// Reads a project or fails if not found
+    async fn get_project(&self, id: &str) -> Result<Project, Error> {
+        db::projects::get(&self.db, parse_id(id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Project not found: {id}")))
+    }
+
+    /// Reads a team or fails if not found
+    async fn get_team(&self, id: &str) -> Result<Team, Error> {
+        db::teams::get(&self.db, parse_id(id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Team not found: {id}")))
+    }
+
+    /// Returns the principal of a user or a service token of an organization
+    ///
+    /// A team is not a principal: its permissions apply to its users.
+    async fn get_principal(&self, subject: &Subject, org_id: &str) -> Result<Principal, Error> {
+        match subject {
+            Subject::User(id) => {
+                let user = self.get_user(id).await?;
+                Ok(Principal::User {
+                    id: parse_id(&user.id)?,
+                    session_id: None,
+                })
+            }
+            Subject::Team(id) => Err(Error::invalid_input(format!(
+                "Team {id} is not a principal: explain the permissions of one of its users"
+            ))),
+            Subject::ServiceToken(id) => db::service_tokens::get(&self.db, parse_id(id)?)
+                .await
+                .map_err(db_err)?
+                .filter(|token| token.org_id == org_id)
+                .map(Principal::ServiceToken)
+                .ok_or_else(|| Error::not_found(format!("Service token not found: {id}"))),
+        }
+    }
+
+    /// Reads a secret or fails if not found
+    async fn get_secret(&self, id: &str) -> Result<Secret, Error> {
+        db::secrets::get(&self.db, parse_id(id)?)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Error::not_found(format!("Secret not found: {id}")))
+    }
+}
+
+/// Returns the [Resource] of a project
+fn resource_project(project: &Project) -> Resource<'_> {
+    Resource::Project {
+        org_id: &project.organization.id,
+        project_id: &project.id,
+    }
+}
+
+/// Returns the [Resource] of a secret
+fn resource_secret(secret: &Secret) -> Resource<'_> {
+    Resource::Secret {
+        org_id: &secret.oeganization.id,
+        project_id: secret.project.as_ref().map(|project| project.id.as_str()),
+        environment: secret.environment.as_deref(),
+        key: &secret.key,
+    }
+}
+
+/// Checks that a user of a role may grant a role to a member
+///
+/// `current` is the role of the member, [None] for an invitation. The owners
+/// are only managed by owners.
+fn check_grant(role: Option<Role>, current: Option<Role>, granted: Role) -> Result<(), Error> {
+    if role != Some(Role::Owner) && (granted == Role::Owner || current == Some(Role::Owner)) {
+        return Err(Error::forbidden("Only an owner manages the owners"));
+    }
+    Ok(())
+}
+
+/// Checks the label of a new session
+fn check_label(label: Option<&str>) -> Result<(), Error> {
+    match label {
+        Some(label) if label.chars().count() > MAX_LABEL_LEN => Err(Error::invalid_input(format!(
+            "Session label longer than {MAX_LABEL_LEN} characters"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the current UNIX timestamp, in seconds
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// Parses a DB ID
+fn parse_id(id: &str) -> Result<i64, Error> {
+    id.parse::<i64>()
+        .map_err(|_| Error::invalid_input(format!("Invalid ID: {id}")))
+}
+
+/// Converts a DB error to a service error
+fn db_err(err: anyhow::Error) -> Error {
+    Error::internal(format!("Database error: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rpc::{http::HttpTransport, Handler};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    /// Returns a service backed by an in-memory DB
+    async fn service() -> anyhow::Result<Service> {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        db::init(&db).await?;
+        Ok(Service::new(db))
+    }
+
+    /// Signs up a test user and returns its credentials
+    async fn signup(service: &Service) -> Credentials {
+        signup_as(service, "john@doe.com").await
+    }
+
+    /// Signs up a test user with an email and returns its credentials
+    async fn signup_as(service: &Service, email: &str) -> Credentials {
+        let input = SignupInput {
+            email: email.to_string(),
+            name: "John".to_string(),
+            password: "password".to_string(),
+            label: None,
+        };
+        Credentials::Token(service.signup(input).await.unwrap().token)
+    }
+
+    #[tokio::test]
+    async fn signup_and_login() -> anyhow::Result<()> {
+        let service = service().await?;
+        let _auth = signup(&service).await;
+
+        let input = LoginInput {
+            email: "john@doe.com".to_string(),
+            password: "password".to_string(),
+            label: None,
+        };
+        let res = service.login(input.clone()).await.unwrap();
+        let user = service
+            .user(Credentials::Token(res.token), res.user.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(user, res.user);
+
+        let wrong = LoginInput {
+            password: "wrong".to_string(),
+            ..input
+        };
+        assert!(service.login(wrong).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sessions() -> anyhow::Result<()> {
+        let service = service().await?;
+        let first = signup(&service).await;
+
+        let input = LoginInput {
+            email: "john@doe.com".to_string(),
+            password: "password".to_string(),
+            label: Some("cli@laptop".to_string()),
+        };
+        let res = service.login(input.clone()).await.unwrap();
+        assert_eq!(res.session.label.as_deref(), Some("cli@laptop"));
+        let second = Credentials::Token(res.token);
+
+        let sessions = service.sessions(second.clone()).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(!sessions[0].current);
+        assert!(sessions[1].current);
+        assert!(sessions[1].last_used_at.is_some());
+
+        // A session may be revoked from another one, but only by its user
+        let id = sessions[0].id.clone();
+        service
+            .revoke_session(second.clone(), id.clone())
+            .await
+            .unwrap();
+        let err = service.user(first, "1".to_string()).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unauthorized);
+        let err = service
+            .revoke_session(second.clone(), id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+
+        service.logout(second.clone()).await.unwrap();
+        let err = service.sessions(second).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unauthorized);
+
+        // The expired tokens are rejected
+        let service = service.with_session_ttl(Duration::ZERO);
+        let expired = Credentials::Token(service.login(input.clone()).await.unwrap().token);
+        let err = service.sessions(expired).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unauthorized);
+
+        let long = LoginInput {
+            label: Some("a".repeat(MAX_LABEL_LEN + 1)),
+            ..input
+        };
+        let err = service.login(long).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_token() -> anyhow::Result<()> {
+        let service = service().await?;
+        let input = OrganizationInput {
+            name: "Acme".to_string(),
+        };
+        assert!(service
+            .add_organization(Credentials::Token("invalid".to_string()), input)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn secrets_crud() -> anyhow::Result<()> {
+        let service = service().await?;
+        let auth = signup(&service).await;
+
+        let org = service
+            .add_organization(
+                auth.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let project = service
+            .add_project(
+                auth.clone(),
+                ProjectInput {
+                    org_id: org.id.clone(),
+                    name: "Website".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let secret = service
+            .add_secret(
+                auth.clone(),
+                SecretInput {
+                    org_id: org.id.clone(),
+                    project_id: Some(project.id.clone()),
+                    environment: None,
+                    key: "API_KEY".to_string(),
+                    value: "1234".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(secret.project, Some(project.clone()));
+
+        let updated = service
+            .update_secret(
+                auth.clone(),
+                Secret {
+                    value: "5678".to_string(),
+                    ..secret.clone()
+                },
+            )
+            .await
+            .unwrap();
+        let read = service
+            .secret(auth.clone(), secret.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(read.value, "5678");
+        assert_eq!(read.value, updated.value);
+
+        service
+            .delete_secret(auth.clone(), secret.id.clone())
+            .await
+            .unwrap();
+        assert!(service.secret(auth.clone(), secret.id).await.is_err());
+
+        service
+            .delete_project(auth.clone(), project.id)
+            .await
+            .unwrap();
+        service.delete_organization(auth, org.id).await.unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_non_empty() -> anyhow::Result<()> {
+        let service = service().await?;
+        let auth = signup(&service).await;
+
+        let org = service
+            .add_organization(
+                auth.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let input = |name: &str| ProjectInput {
+            org_id: org.id.clone(),
+            name: name.to_string(),
+        };
+        let website = service
+            .add_project(auth.clone(), input("Website"))
+            .await
+            .unwrap();
+        let api = service
+            .add_project(auth.clone(), input("API"))
+            .await
+            .unwrap();
+        let input = |project_id: Option<&str>| SecretInput {
+            org_id: org.id.clone(),
+            project_id: project_id.map(str::to_string),
+            environment: None,
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        };
+        let website_secret = service
+            .add_secret(auth.clone(), input(Some(&website.id)))
+            .await
+            .unwrap();
+        let api_secret = service
+            .add_secret(auth.clone(), input(Some(&api.id)))
+            .await
+            .unwrap();
+        let org_secret = service.add_secret(auth.clone(), input(None)).await.unwrap();
+
+        // A project is deleted with its secrets
+        service
+            .delete_project(auth.clone(), website.id)
+            .await
+            .unwrap();
+        let err = service
+            .secret(auth.clone(), website_secret.id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        service
+            .secret(auth.clone(), api_secret.id.clone())
+            .await
+            .unwrap();
+
+        // An organization is deleted with its projects and secrets
+        service
+            .delete_organization(auth.clone(), org.id.clone())
+            .await
+            .unwrap();
+        for id in [api_secret.id, org_secret.id] {
+            let err = service.secret(auth.clone(), id).await.unwrap_err();
+            assert_eq!(err.kind, ErrorKind::NotFound);
+        }
+        let err = service.project(auth.clone(), api.id).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let err = service.organization(auth, org.id).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn secret_events() -> anyhow::Result<()> {
+        let service = service().await?;
+        let auth = signup(&service).await;
+
+        let org = service
+            .add_organization(
+                auth.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let topic = Topic::Organization(org.id.clone()).to_string();
+        service
+            .subscribe(auth.clone(), topic.clone())
+            .await
+            .unwrap();
+        assert!(service
+            .subscribe(auth.clone(), "org:999".to_string())
+            .await
+            .is_err());
+        assert!(service
+            .subscribe(auth.clone(), "user:1".to_string())
+            .await
+            .is_err());
+
+        let mut events = service.events().subscribe(&topic);
+        let secret = service
+            .add_secret(
+                auth.clone(),
+                SecretInput {
+                    org_id: org.id.clone(),
+                    project_id: None,
+                    environment: None,
+                    key: "API_KEY".to_string(),
+                    value: "1234".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        service
+            .delete_secret(auth, secret.id.clone())
+            .await
+            .unwrap();
+
+        let added = events.recv().await?.decode::<SecretEvent>().unwrap();
+        assert_eq!(added, SecretEvent::new(SecretEventKind::Added, &secret));
+        let deleted = events.recv().await?.decode::<SecretEvent>().unwrap();
+        assert_eq!(deleted.kind, SecretEventKind::Deleted);
+        assert_eq!(deleted.key, "API_KEY");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_tokens() -> anyhow::Result<()> {
+        let service = service().await?;
+        let auth = signup(&service).await;
+
+        let org = service
+            .add_organization(
+                auth.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let project = service
+            .add_project(
+                auth.clone(),
+                ProjectInput {
+                    org_id: org.id.clone(),
+                    name: "Website".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let input = |environment: Option<&str>| SecretInput {
+            org_id: org.id.clone(),
+            project_id: Some(project.id.clone()),
+            environment: environment.map(String::from),
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        };
+        let staging = service
+            .add_secret(auth.clone(), input(Some("staging")))
+            .await
+            .unwrap();
+
+        let res = service
+            .add_service_token(
+                auth.clone(),
+                ServiceTokenInput {
+                    name: "deploy".to_string(),
+                    org_id: org.id.clone(),
+                    project_id: Some(project.id.clone()),
+                    environment: Some("production".to_string()),
+                    scope: TokenScope::ReadWrite,
+                    ttl: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(res.token.starts_with(SERVICE_TOKEN_PREFIX));
+        let token = Credentials::Token(res.token);
+
+        // The token is restricted to the secrets of its project and environment
+        let production = service
+            .add_secret(token.clone(), input(Some("production")))
+            .await
+            .unwrap();
+        service
+            .secret(token.clone(), production.id.clone())
+            .await
+            .unwrap();
+        let err = service
+            .secret(token.clone(), staging.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+
+        // ...and does not read the organization, nor its members
+        let err = service
+            .organization(token.clone(), org.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        let err = service
+            .members(token.clone(), org.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        let err = service
+            .add_secret(token.clone(), input(None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        let err = service
+            .service_tokens(token.clone(), org.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+
+        let tokens = service
+            .service_tokens(auth.clone(), org.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens[0].last_used_at.is_some());
+
+        // The audit trail records the actor of each action
+        let audit = service.audit(auth.clone(), org.id.clone()).await.unwrap();
+        let actions: Vec<_> = audit.iter().map(|event| event.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            [
+                "organization.add",
+                "project.add",
+                "secret.add",
+                "service_token.add",
+                "secret.add",
+                "secret.read"
+            ]
+        );
+        assert_eq!(audit[3].user_id.as_deref(), Some("1"));
+        assert_eq!(audit[4].user_id, None);
+        assert_eq!(
+            audit[4].service_token_id,
+            Some(res.service_token.id.clone())
+        );
+        assert_eq!(audit[4].resource_id, Some(production.id.clone()));
+
+        // A revoked token is rejected
+        let revoked = service
+            .revoke_service_token(auth.clone(), res.service_token.id)
+            .await
+            .unwrap();
+        assert!(revoked.revoked_at.is_some());
+        let err = service.secret(token, production.id).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unauthorized);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn members() -> anyhow::Result<()> {
+        let service = service().await?;
+        let john = signup(&service).await;
+        let jane = signup_as(&service, "jane@doe.com").await;
+
+        let org = service
+            .add_organization(
+                john.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let input = SecretInput {
+            org_id: org.id.clone(),
+            project_id: None,
+            environment: None,
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        };
+        let secret = service
+            .add_secret(john.clone(), input.clone())
+            .await
+            .unwrap();
+
+        // The organizations of the others are not disclosed, nor their users
+        let err = service
+            .organization(jane.clone(), org.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let err = service
+            .user(jane.clone(), "1".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        // ...nor their resources, answered like the missing ones
+        let err = service
+            .secret(jane.clone(), secret.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        assert_eq!(err.message, format!("Secret not found: {}", secret.id));
+        let err = service
+            .delete_secret(jane.clone(), secret.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, format!("Secret not found: {}", secret.id));
+        let err = service
+            .secret(jane.clone(), "999".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "Secret not found: 999");
+
+        let invitation = service
+            .invite(
+                john.clone(),
+                InvitationInput {
+                    org_id: org.id.clone(),
+                    email: "jane@doe.com".to_string(),
+                    role: Role::Viewer,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(service.invitations(jane.clone()).await.unwrap().len(), 1);
+        let err = service
+            .accept_invitation(john.clone(), invitation.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let member = service
+            .accept_invitation(jane.clone(), invitation.id)
+            .await
+            .unwrap();
+        assert_eq!(member.role, Role::Viewer);
+        let jane_id = member.user.id;
+        let user = service.user(jane.clone(), "1".to_string()).await.unwrap();
+        assert_eq!(user.email, "john@doe.com");
+
+        // A viewer only reads
+        service
+            .secret(jane.clone(), secret.id.clone())
+            .await
+            .unwrap();
+        let err = service.add_secret(jane.clone(), input).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        assert_eq!(
+            service
+                .members(jane.clone(), org.id.clone())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // An admin does not manage the owners
+        let admin = RoleInput {
+            org_id: org.id.clone(),
+            user_id: jane_id.clone(),
+            role: Role::Admin,
+        };
+        service.change_role(john.clone(), admin).await.unwrap();
+        let demote = RoleInput {
+            org_id: org.id.clone(),
+            user_id: "1".to_string(),
+            role: Role::Viewer,
+        };
+        let err = service
+            .change_role(jane.clone(), demote.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+
+        // An organization keeps an owner
+        let err = service.change_role(john.clone(), demote).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Conflict);
+        let err = service
+            .delete_user(john.clone(), "1".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Conflict);
+
+        // A member may leave
+        service
+            .remove_member(
+                jane.clone(),
+                MemberInput {
+                    org_id: org.id.clone(),
+                    user_id: jane_id,
+                },
+            )
+            .await
+            .unwrap();
+        let err = service.organization(jane, org.id).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn grants() -> anyhow::Result<()> {
+        let service = service().await?;
+        let john = signup(&service).await;
+        let jane = signup_as(&service, "jane@doe.com").await;
+
+        let org = service
+            .add_organization(
+                john.clone(),
+                OrganizationInput {
+                    name: "Acme".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let project = service
+            .add_project(
+                john.clone(),
+                ProjectInput {
+                    org_id: org.id.clone(),
+                    name: "Website".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let input = |environment: &str| SecretInput {
+            org_id: org.id.clone(),
+            project_id: Some(project.id.clone()),
+            environment: Some(environment.to_string()),
+            key: "API_KEY".to_string(),
+            value: "1234".to_string(),
+        };
+        let staging = service
+            .add_secret(john.clone(), input("staging"))
+            .await
+            .unwrap();
+        let production = service
+            .add_secret(john.clone(), input("production"))
+            .await
+            .unwrap();
+
+        // A contractor reads the staging secrets of a project, through a team
+        let team = service
+            .add_team(
+                john.clone(),
+                TeamInput {
+                    org_id: org.id.clone(),
+                    name: "contractors".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let team_member = TeamMemberInput {
+            team_id: team.id,
+            user_id: "2".to_string(),
+        };
+        let err = service
+            .add_team_member(john.clone(), team_member.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let invitation = service
+            .invite(
+                john.clone(),
+                InvitationInput {
+                    org_id: org.id.clone(),
+                    email: "jane@doe.com".to_string(),
+                    role: Role::Guest,
+                },
+            )
+            .await
+            .unwrap();
+        service
+            .accept_invitation(jane.clone(), invitation.id)
+            .await
+            .unwrap();
+        let team = service
+            .add_team_member(john.clone(), team_member)
+            .await
+            .unwrap();
+        assert_eq!(team.user_ids, ["2"]);
+        let grant = service
+            .add_grant(
+                john.clone(),
+                GrantInput {
+                    subject: Subject::Team(team.id.clone()),
+                    project_id: project.id.clone(),
+                    environment: Some("staging".to_string()),
+                    key_prefix: None,
+                    permission: Permission::Read,
+                },
+            )
+            .await
+            .unwrap();
+        service
+            .secret(jane.clone(), staging.id.clone())
+            .await
+            .unwrap();
+        let err = service
+            .secret(jane.clone(), production.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let err = service
+            .add_secret(jane.clone(), input("staging"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        let err = service
+            .organization(jane.clone(), org.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+        let err = service
+            .grants(jane.clone(), project.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+
+        // The permissions are explained
+        let query = PermissionsInput {
+            subject: Some(Subject::User("2".to_string())),
+            project_id: project.id.clone(),
+            environment: Some("staging".to_string()),
+            key: "API_KEY".to_string(),
+        };
+        let permissions = service
+            .effective_permissions(john.clone(), query.clone())
+            .await
+            .unwrap();
+        assert!(permissions.read.allowed);
+        assert_eq!(
+            permissions.read.reason,
+            format!(
+                "Grant {} (read on project {}, environment staging)",
+                grant.id, project.id
+            )
+        );
+        assert!(!permissions.write.allowed);
+        assert!(!permissions.admin.allowed);
+        let own = service
+            .effective_permissions(
+                john.clone(),
+                PermissionsInput {
+                    subject: None,
+                    ..query.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(own.admin.reason, "Role owner in organization 1");
+        let err = service
+            .effective_permissions(
+                jane.clone(),
+                PermissionsInput {
+                    subject: Some(Subject::User("1".to_string())),
+                    ..query.clone()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Forbidden);
+
+        // A revoked grant no longer applies
+        service.revoke_grant(john.clone(), grant.id).await.unwrap();
+        let permissions = service
+            .effective_permissions(john.clone(), query.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            permissions.read.reason,
+            "Role guest has no read access to this resource (requires viewer)"
+        );
+        let err = service
+            .secret(jane.clone(), staging.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+
+        // A removed member loses its teams and grants
+        service
+            .add_grant(
+                john.clone(),
+                GrantInput {
+                    subject: Subject::User("2".to_string()),
+                    project_id: project.id.clone(),
+                    environment: None,
+                    key_prefix: None,
+                    permission: Permission::Read,
+                },
+            )
+            .await
+            .unwrap();
+        service
+            .secret(jane.clone(), staging.id.clone())
+            .await
+            .unwrap();
+        service
+            .remove_member(
+                john.clone(),
+                MemberInput {
+                    org_id: org.id.clone(),
+                    user_id: "2".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let err = service.secret(jane, staging.id).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+        let permissions = service
+            .effective_permissions(john.clone(), query)
+            .await
+            .unwrap();
+        assert_eq!(permissions.read.reason, "Not a member of organization 1");
+        assert!(service
+            .grants(john.clone(), project.id)
+            .await
+            .unwrap()
+            .is_empty());
+        let teams = service.teams(john, org.id).await.unwrap();
+        assert!(teams[0].user_ids.is_empty());
+        Ok(())
+    }
+
+    /// Sends a JSON request to the service router
+    async fn dispatch(
+        router: &rpc::Router,
+        method: &str,
+        token: Option<&str>,
+        data: serde_json::Value,
+    ) -> (hyper::StatusCode, serde_json::Value) {
+        let mut req = hyper::Request::builder().header("X-RPC-METHOD", method);
+        if let Some(token) = token {
+            req = req.header("Authorization", format!("Bearer {token}"));
+        }
+        let req = req.body(hyper::Body::from(data.to_string())).unwrap();
+
+        let res = router.handle(HttpTransport::new(), req).await;
+        let status = res.status();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn handler_dispatch() -> anyhow::Result<()> {
+        let router = gen::Handler::new(service().await?).router();
+
+        let signup = serde_json::json!({
+            "email": "john@doe.com",
+            "name": "John",
+            "password": "password",
+        });
+        let (status, res) = dispatch(&router, "signup", None, signup.clone()).await;
+        assert!(status.is_success());
+        assert_eq!(res["user"]["email"], "john@doe.com");
+        assert!(res["user"].get("password").is_none());
+        let token = res["token"].as_str().unwrap().to_string();
+
+        let (status, res) = dispatch(&router, "signup", None, signup).await;
+        assert_eq!(status, hyper::StatusCode::CONFLICT);
+        assert_eq!(res["kind"], "conflict");
+
+        let org = serde_json::json!({ "name": "Acme" });
+        let (status, res) = dispatch(&router, "add_organization", None, org.clone()).await;
+        assert_eq!(status, hyper::StatusCode::UNAUTHORIZED);
+        assert_eq!(res["kind"], "unauthorized");
+
+        let (status, res) = dispatch(&router, "add_organization", Some(&token), org).await;
+        assert!(status.is_success());
+        assert_eq!(res["name"], "Acme");
+
+        let (status, _) = dispatch(&router, "organization", Some(&token), "999".into()).await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+        let (status, res) = dispatch(&router, "organization", Some(&token), 1.into()).await;
+        assert_eq!(status, hyper::StatusCode::BAD_REQUEST);
+        assert_eq!(res["kind"], "invalid_input");
+
+        let (status, _) = dispatch(&router, "unknown", Some(&token), ().into()).await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[test]
+    fn error_kinds() {
+        // Each kind is mapped to a status by the transports
+        for kind in ErrorKind::ALL {
+            let value = serde_json::to_value(kind).unwrap();
+            assert_eq!(value, kind.as_str());
+            assert!(rpc::ERROR_STATUS.iter().any(|(k, _)| *k == kind.as_str()));
+        }
+
+        // The errors raised before the service may have no kind
+        let err = serde_json::from_str::<Error>(r#"{"message": "Invalid body"}"#).unwrap();
+        assert_eq!(err.kind, ErrorKind::Internal);
+    }
+
+    #[test]
+    fn error_without_kind() {
+        // An error built from a message has the kind of an error decoded without one
+        let err = Error::from("Invalid body".to_string());
+        let mut value = serde_json::to_value(&err).unwrap();
+        assert_eq!(value["kind"], "internal");
+        value.as_object_mut().unwrap().remove("kind");
+        let decoded = serde_json::from_value::<Error>(value).unwrap();
+        assert_eq!(decoded.kind, err.kind);
+        assert_eq!(decoded.message, "Invalid body");
+    }
+
+    #[tokio::test]
+    async fn router_namespace() -> anyhow::Result<()> {
+        let secrets = gen::Handler::new(service().await?).router();
+        let router = rpc::Router::new()
+            .method("ping", |_ctx, ()| async { Ok::<_, Error>("pong") })
+            .nest("secrets", secrets);
+
+        let (status, res) = dispatch(&router, "ping", None, ().into()).await;
+        assert!(status.is_success());
+        assert_eq!(res, "pong");
+
+        let signup = serde_json::json!({
+            "email": "john@doe.com",
+            "name": "John",
+            "password": "password",
+        });
+        let (status, _) = dispatch(&router, "secrets.signup", None, signup.clone()).await;
+        assert!(status.is_success());
+        let (status, res) = dispatch(&router, "signup", None, signup).await;
+        assert!(!status.is_success());
+        assert_eq!(res["message"], "Invalid method: signup");
+        Ok(())
     }
 }
@@ -0,0 +1,286 @@
+//! TLS certificates
+
+use std::{
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+
+/// TLS directory (in the config directory)
+const TLS_DIR: &str = "tls";
+
+/// Prefix of the client certificate subjects
+const CLIENT_SUBJECT_PREFIX: &str = "secrets-client-";
+
+/// Length of the random part of the client certificate subjects
+const CLIENT_SUBJECT_LEN: usize = 16;
+
+/// Generated TLS files
+#[derive(Debug)]
+pub struct TlsFiles {
+    /// CA certificate, to pin on the clients
+    pub ca_cert: PathBuf,
+    /// CA private key
+    pub ca_key: PathBuf,
+    /// Server certificate
+    pub cert: PathBuf,
+    /// Server private key
+    pub key: PathBuf,
+}
+
+/// Generates a self-signed CA and a server certificate signed by this CA
+///
+/// The files are written as PEM in the `tls` subfolder of `dir`.
+/// `hosts` are the DNS names or IP addresses the server is reachable at.
+pub fn generate_certificates(dir: &Path, hosts: &[String]) -> anyhow::Result<TlsFiles> {
+    let tls_dir = dir.join(TLS_DIR);
+    fs::create_dir_all(&tls_dir)?;
+
+    // CA
+    let mut ca_params = CertificateParams::default();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name = distinguished_name("Secrets CA");
+    ca_params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    let ca = Certificate::from_params(ca_params)?;
+
+    // Server
+    let mut params = CertificateParams::default();
+    params.distinguished_name =
+        distinguished_name(hosts.first().map(|h| h.as_str()).unwrap_or("localhost"));
+    params.subject_alt_names = hosts
+        .iter()
+        .map(|host| match host.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(host.clone()),
+        })
+        .collect();
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let cert = Certificate::from_params(params)?;
+
+    let files = TlsFiles {
+        ca_cert: tls_dir.join("ca.pem"),
+        ca_key: tls_dir.join("ca-key.pem"),
+        cert: tls_dir.join("server.pem"),
+        key: tls_dir.join("server-key.pem"),
+    };
+    fs::write(&files.ca_cert, ca.serialize_pem()?)?;
+    write_private(&files.ca_key, &ca.serialize_private_key_pem())?;
+    fs::write(&files.cert, cert.serialize_pem_with_signer(&ca)?)?;
+    write_private(&files.key, &cert.serialize_private_key_pem())?;
+
+    Ok(files)
+}
+
+/// Client certificate (PEM)
+#[derive(Debug)]
+pub struct ClientCertificate {
+    /// Subject common name, which identifies the client
+    pub subject: String,
+    /// Certificate
+    pub cert: String,
+    /// Private key
+    pub key: String,
+}
+
+/// Issues a client certificate signed by the CA
+///
+/// The subject is random, and must be mapped to an identity by the server.
+pub fn issue_client_certificate(
+    ca_cert: &Path,
+    ca_key: &Path,
+) -> anyhow::Result<ClientCertificate> {
+    let ca_key = KeyPair::from_pem(&fs::read_to_string(ca_key)?)?;
+    let ca_params = CertificateParams::from_ca_cert_pem(&fs::read_to_string(ca_cert)?, ca_key)?;
+    let ca = Certificate::from_params(ca_params)?;
+
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CLIENT_SUBJECT_LEN)
+        .map(char::from)
+        .collect();
+    let subject = format!("{CLIENT_SUBJECT_PREFIX}{}", random.to_lowercase());
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name = distinguished_name(&subject);
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let cert = Certificate::from_params(params)?;
+
+    Ok(ClientCertificate {
+        subject,
+        cert: cert.serialize_pem_with_signer(&ca)?,
+        key: cert.serialize_private_key_pem(),
+    })
+}
+
+/// Returns a distinguished name with a common name
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name
+}
+
+/// Writes a file only readable by the current user
+pub fn write_private(path: &Path, data: &str) -> anyhow::Result<()> {
+    fs::write(path, data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use ::service::{gen, rpc::http::HttpTransport, SignupInput};
+
+    use super::*;
+    use crate::{db, rpc, service::Service};
+
+    #[tokio::test]
+    async fn tls_roundtrip() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = generate_certificates(dir.path(), &["localhost".to_string()])?;
+
+        let db = db::conn_pool(&dir.path().join("data.db")).await?;
+        let addr = spawn_server(
+            db,
+            HttpTransport::builder().server_cert(&files.cert, &files.key),
+        )
+        .await?;
+
+        // Client pinning the CA
+        let url = format!("https://localhost:{}", addr.port());
+        let sender = HttpTransport::builder()
+            .url(&url)
+            .ca_cert(&files.ca_cert)
+            .timeout(Duration::from_secs(5))
+            .build::<String>()
+            .map_err(anyhow::Error::msg)?;
+        let client = gen::Client::with_transport(sender);
+        client
+            .status()
+            .await
+            .map_err(|err| anyhow::anyhow!(err.message))?;
+
+        // Client using the default roots rejects the certificate
+        let client = gen::Client::new(&url).map_err(|err| anyhow::anyhow!(err.message))?;
+        assert!(client.status().await.is_err());
+
+        // Plain HTTP is not served
+        let client = gen::Client::new(format!(
+            "http://{}",
+            SocketAddr::from(([127, 0, 0, 1], addr.port()))
+        ))
+        .map_err(|err| anyhow::anyhow!(err.message))?;
+        assert!(client.status().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn client_certificate_auth() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = generate_certificates(dir.path(), &["localhost".to_string()])?;
+
+        let db = db::conn_pool(&dir.path().join("data.db")).await?;
+        let addr = spawn_server(
+            db.clone(),
+            HttpTransport::builder()
+                .server_cert(&files.cert, &files.key)
+                .client_ca(&files.ca_cert),
+        )
+        .await?;
+        let url = format!("https://localhost:{}", addr.port());
+
+        // Signup with a client without certificate
+        let client = gen::Client::with_transport(
+            HttpTransport::builder()
+                .url(&url)
+                .ca_cert(&files.ca_cert)
+                .build::<String>()
+                .map_err(anyhow::Error::msg)?,
+        );
+        client
+            .status()
+            .await
+            .map_err(|err| anyhow::anyhow!(err.message))?;
+        let user = client
+            .signup(SignupInput {
+                email: "ci@doe.com".to_string(),
+                name: "CI".to_string(),
+                password: "password".to_string(),
+                label: None,
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!(err.message))?
+            .user;
+        assert!(client.user(user.id.clone()).await.is_err());
+
+        // Issue a client certificate for the user
+        let cert = issue_client_certificate(&files.ca_cert, &files.ca_key)?;
+        db::certificates::insert(&db, &cert.subject, user.id.parse()?).await?;
+        let cert_file = dir.path().join("client.pem");
+        let key_file = dir.path().join("client-key.pem");
+        fs::write(&cert_file, &cert.cert)?;
+        fs::write(&key_file, &cert.key)?;
+
+        // The certificate authenticates without a token
+        let client = gen::Client::with_transport(
+            HttpTransport::builder()
+                .url(&url)
+                .ca_cert(&files.ca_cert)
+                .client_cert(&cert_file, &key_file)
+                .build::<String>()
+                .map_err(anyhow::Error::msg)?,
+        );
+        let read = client
+            .user(user.id.clone())
+            .await
+            .map_err(|err| anyhow::anyhow!(err.message))?;
+        assert_eq!(read, user);
+
+        // An unmapped certificate is rejected
+        let other = issue_client_certificate(&files.ca_cert, &files.ca_key)?;
+        fs::write(&cert_file, &other.cert)?;
+        fs::write(&key_file, &other.key)?;
+        let client = gen::Client::with_transport(
+            HttpTransport::builder()
+                .url(&url)
+                .ca_cert(&files.ca_cert)
+                .client_cert(&cert_file, &key_file)
+                .build::<String>()
+                .map_err(anyhow::Error::msg)?,
+        );
+        assert!(client.user(user.id).await.is_err());
+        Ok(())
+    }
+
+    /// Spawns a server on a random port and returns its address
+    async fn spawn_server(
+        db: db::DbConn,
+        receiver: rpc::http::HttpTransportBuilder,
+    ) -> anyhow::Result<SocketAddr> {
+        db::init(&db).await?;
+        let router = gen::Handler::new(Service::new(db)).router();
+        let receiver = receiver.build::<String>().map_err(anyhow::Error::msg)?;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let server = rpc::Server::new(receiver, router);
+            server.serve(listener).await.unwrap();
+        });
+        Ok(addr)
+    }
+}
@@ -1,158 +1,141 @@
 //! Generated client and server
+//!
+//! The service methods are derived from [SecretsService](crate::SecretsService)
+//! by the [rpc::service] macro.
 
-use rpc::transports::json::JsonTransport;
+#[cfg(unix)]
+use std::path::Path;
+
+#[cfg(unix)]
+use rpc::transports::unix::UnixTransport;
+use rpc::{
+    events::EventStream,
+    transports::{http::HttpTransport, local::LocalTransport, ws::WsTransport},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Error, SecretEvent, SecretsService, Topic, API_VERSION};
 
 // -------------------------------------------------
 // SERVER
 // -------------------------------------------------
 
+/// Service handler
+///
+/// Dispatches the RPC requests to a [SecretsService](crate::SecretsService) implementation
+#[derive(Debug, Clone)]
+pub struct Handler<S> {
+    /// Service implementation
+    service: S,
+}
+
+impl<S> Handler<S> {
+    /// Instantiates a new [Handler]
+    pub const fn new(service: S) -> Self {
+        Self { service }
+    }
+
+    /// Returns the service implementation
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+}
+
 // -------------------------------------------------
 // CLIENT
 // -------------------------------------------------
 
 /// Client
-pub struct Client {
+///
+/// The client sends the requests over a [HttpTransport] by default.
+pub struct Client<S = HttpTransport>
+where
+    S: rpc::Sender,
+{
     /// RPC client
-    rpc_client: rpc::Client<JsonTransport>,
+    rpc_client: rpc::Client<S>,
     /// Token
     token: Option<String>,
 }
 
 impl Client {
-    /// Instantiates a new [Client]
-    pub const fn new() -> Self {
-        let sender = JsonTransport::new();
-        let rpc_client = rpc::Client::new(sender);
-        Self {
-            rpc_client,
-            token: None,
-        }
-    }
-
-    /// Authenticates the client
-    pub fn authenticate(&mut self, token: impl AsRef<str>) {
-        self.token = Some(token.as_ref().to_string());
-    }
-
-    /// Deuthenticates the client
-    pub fn deauthenticate(&mut self) {
-        self.token = None;
+    /// Instantiates a new [Client] for the server at `url` (eg. `http://localhost:6666`)
+    pub fn new(url: impl AsRef<str>) -> Result<Self, Error> {
+        let sender = HttpTransport::builder().url(url).build::<Error>()?;
+        Ok(Self::with_transport(sender))
     }
 }
 
-// NB : The client API could be derived via a macro parameterized
-// by the trait definining the service interface
-impl Client {
-    /// Returns the service status
-    pub async fn status(&self) -> Result<ServiceStatus, Error> {
-        let request = rpc::Request::new("status", self.token.clone(), ());
-        self.rpc_client
-            .call::<(), ServiceStatus, Error>(request)
-            .await
-    }
-
-    /// Signup a new user
-    pub async fn signup(&self, input: SignupInput) -> Result<LoginResponse, Error> {
-        let request = rpc::Request::new("signup", self.token.clone(), input);
-        self.rpc_client
-            .call::<SignupInput, LoginResponse, Error>(request)
-            .await
-    }
-
-    /// Login a new user
-    pub async fn login(&self, input: LoginInput) -> Result<LoginResponse, Error> {
-        let request = rpc::Request::new("login", self.token.clone(), input);
-        self.rpc_client
-            .call::<LoginInput, LoginResponse, Error>(request)
-            .await
-    }
-
-    /// Reads a user
-    pub async fn user(&self, id: String) -> Result<User, Error> {
-        let request = rpc::Request::new("user", self.token.clone(), id);
-        self.rpc_client.call::<String, User, Error>(request).await
-    }
-
-    /// Deletes a user
-    pub async fn delete_user(&self, id: String) -> Result<User, Error> {
-        let request = rpc::Request::new("delete_user", self.token.clone(), id);
-        self.rpc_client.call::<String, User, Error>(request).await
-    }
-
-    /// Add an organization
-    pub async fn add_organization(
-        &self,
-        organization: OrganizationInput,
-    ) -> Result<Organization, Error> {
-        let request = rpc::Request::new("add_organization", self.token.clone(), organization);
-        self.rpc_client
-            .call::<OrganizationInput, Organization, Error>(request)
-            .await
-    }
-
-    /// Reads an organization
-    pub async fn organization(&self, id: String) -> Result<Organization, Error> {
-        let request = rpc::Request::new("organization", self.token.clone(), id);
-        self.rpc_client
-            .call::<String, Organization, Error>(request)
-            .await
-    }
-
-    /// Deletes an organization
-    pub async fn delete_organization(&self, id: String) -> Result<Organization, Error> {
-        let request = rpc::Request::new("delete_organization", self.token.clone(), id);
-        self.rpc_client
-            .call::<String, Organization, Error>(request)
-            .await
+impl Client<LocalTransport> {
+    /// Instantiates a new [Client] for a service in the same process
+    ///
+    /// The service is served in the background, without any socket.
+    pub fn local<T>(service: T) -> Self
+    where
+        T: SecretsService + Clone + Send + Sync + 'static,
+    {
+        Self::with_transport(LocalTransport::serve(Handler::new(service).router()))
     }
+}
 
-    /// Add a project
-    pub async fn add_project(&self, project: ProjectInput) -> Result<Project, Error> {
-        let request = rpc::Request::new("add_project", self.token.clone(), project);
-        self.rpc_client
-            .call::<ProjectInput, Project, Error>(request)
-            .await
+#[cfg(unix)]
+impl Client<UnixTransport> {
+    /// Instantiates a new [Client] for the server listening on a Unix socket
+    pub fn unix(path: impl AsRef<Path>) -> Self {
+        Self::with_transport(UnixTransport::builder().path(path).build())
     }
+}
 
-    /// Reads a project
-    pub async fn project(&self, id: String) -> Result<Project, Error> {
-        let request = rpc::Request::new("project", self.token.clone(), id);
-        self.rpc_client
-            .call::<String, Project, Error>(request)
-            .await
+impl Client<WsTransport> {
+    /// Instantiates a new [Client] for the server at `url` (eg. `http://localhost:6666`),
+    /// over a WebSocket connection
+    pub fn ws(url: impl AsRef<str>) -> Result<Self, Error> {
+        let http = HttpTransport::builder().url(url).build::<Error>()?;
+        Ok(Self::with_transport(WsTransport::new(http)))
     }
 
-    /// Deletes a project
-    pub async fn delete_project(&self, id: String) -> Result<Project, Error> {
-        let request = rpc::Request::new("delete_project", self.token.clone(), id);
+    /// Returns the stream of the secret events of a topic
+    pub async fn events(&self, topic: &Topic) -> Result<EventStream<SecretEvent>, Error> {
         self.rpc_client
-            .call::<String, Project, Error>(request)
+            .sender()
+            .subscribe(topic.to_string(), self.token.clone())
             .await
     }
+}
 
-    /// Adds a secret
-    pub async fn add_secret(&self, secret: SecretInput) -> Result<Secret, Error> {
-        let request = rpc::Request::new("add_secret", self.token.clone(), secret);
-        self.rpc_client
-            .call::<SecretInput, Secret, Error>(request)
-            .await
+impl<S> Client<S>
+where
+    S: rpc::Sender,
+{
+    /// Instantiates a new [Client] from a configured transport
+    pub const fn with_transport(sender: S) -> Self {
+        let rpc_client = rpc::Client::new(sender);
+        Self {
+            rpc_client,
+            token: None,
+        }
     }
 
-    /// Reads a secret
-    pub async fn secret(&self, id: String) -> Result<Secret, Error> {
-        let request = rpc::Request::new("secret", self.token.clone(), id);
-        self.rpc_client.call::<String, Secret, Error>(request).await
+    /// Authenticates the client
+    pub fn authenticate(&mut self, token: impl AsRef<str>) {
+        self.token = Some(token.as_ref().to_string());
     }
 
-    /// Update a secret
-    pub async fn update_secret(&self, secret: Secret) -> Result<Secret, Error> {
-        let request = rpc::Request::new("update_secret", self.token.clone(), secret);
-        self.rpc_client.call::<Secret, Secret, Error>(request).await
+    /// Deuthenticates the client
+    pub fn deauthenticate(&mut self) {
+        self.token = None;
     }
 
-    /// Deletes a secret
-    pub async fn delete_secret(&self, id: String) -> Result<Secret, Error> {
-        let request = rpc::Request::new("delete_secret", self.token.clone(), id);
-        self.rpc_client.call::<String, Secret, Error>(request).await
+    /// Calls a RPC method
+    ///
+    /// The request carries the [API_VERSION] of the client.
+    pub(crate) async fn call<P, R, E>(&self, method: &str, data: P) -> rpc::Response<R, E>
+    where
+        P: Serialize + Send,
+        R: DeserializeOwned,
+        E: DeserializeOwned + From<String>,
+    {
+        let request = rpc::Request::new(method, self.token.clone(), data).with_version(API_VERSION);
+        self.rpc_client.call::<P, R, E>(request).await
     }
 }
@@ -2,16 +2,30 @@
 
 #![deny(missing_docs)]
 
+use std::{fmt, str::FromStr};
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
 pub mod gen;
 
+pub use rpc;
+
+/// Version of the API
+///
+/// The clients send it with each request, and the server rejects the versions
+/// it does not support.
+pub const API_VERSION: u32 = 1;
+
+/// Oldest version of the API supported by the server
+pub const MIN_API_VERSION: u32 = 1;
+
 // ---------------------------------------------------------------
 // SERVICE DEFINITION
 // ---------------------------------------------------------------
 
 /// The secrets service is the interface between the server and client
+#[rpc::service(client = gen::Client, handler = gen::Handler)]
 #[async_trait]
 pub trait SecretsService {
     /// Returns the API status
@@ -23,45 +37,158 @@ pub trait SecretsService {
     /// Login a new user
     async fn login(&self, input: LoginInput) -> Result<LoginResponse, Error>;
 
+    /// Logouts, by revoking the session of the token
+    async fn logout(&self, auth: rpc::Credentials) -> Result<(), Error>;
+
+    /// Lists the sessions of the user
+    async fn sessions(&self, auth: rpc::Credentials) -> Result<Vec<Session>, Error>;
+
+    /// Revokes a session of the user (eg. of a stolen token)
+    async fn revoke_session(&self, auth: rpc::Credentials, id: String) -> Result<(), Error>;
+
     /// Reads a user
-    async fn user(&self, token: String, id: String) -> Result<User, Error>;
+    async fn user(&self, auth: rpc::Credentials, id: String) -> Result<User, Error>;
 
     /// Deletes a user
-    async fn delete_user(&self, token: String, id: String) -> Result<User, Error>;
+    async fn delete_user(&self, auth: rpc::Credentials, id: String) -> Result<User, Error>;
 
     /// Add an organization
     async fn add_organization(
         &self,
-        token: String,
+        auth: rpc::Credentials,
         organization: OrganizationInput,
     ) -> Result<Organization, Error>;
 
     /// Reads an organization
-    async fn organization(&self, token: String, id: String) -> Result<Organization, Error>;
+    async fn organization(&self, auth: rpc::Credentials, id: String)
+        -> Result<Organization, Error>;
 
-    /// Deletes an organization
-    async fn delete_organization(&self, token: String, id: String) -> Result<Organization, Error>;
+    /// Deletes an organization, with its projects and secrets
+    async fn delete_organization(
+        &self,
+        auth: rpc::Credentials,
+        id: String,
+    ) -> Result<Organization, Error>;
 
     /// Add a project
-    async fn add_project(&self, token: String, project: ProjectInput) -> Result<Project, Error>;
+    async fn add_project(
+        &self,
+        auth: rpc::Credentials,
+        project: ProjectInput,
+    ) -> Result<Project, Error>;
 
     /// Reads a project
-    async fn project(&self, token: String, id: String) -> Result<Project, Error>;
+    async fn project(&self, auth: rpc::Credentials, id: String) -> Result<Project, Error>;
 
-    /// Deletes a project
-    async fn delete_project(&self, token: String, id: String) -> Result<Project, Error>;
+    /// Deletes a project, with its secrets
+    async fn delete_project(&self, auth: rpc::Credentials, id: String) -> Result<Project, Error>;
 
     /// Adds a secret
-    async fn add_secret(&self, token: String, secret: SecretInput) -> Result<Secret, Error>;
+    async fn add_secret(
+        &self,
+        auth: rpc::Credentials,
+        secret: SecretInput,
+    ) -> Result<Secret, Error>;
 
     /// Reads a secret
-    async fn secret(&self, token: String, id: String) -> Result<Secret, Error>;
+    async fn secret(&self, auth: rpc::Credentials, id: String) -> Result<Secret, Error>;
 
     /// Update a secret
-    async fn update_secret(&self, token: String, secret: Secret) -> Result<Secret, Error>;
+    async fn update_secret(&self, auth: rpc::Credentials, secret: Secret) -> Result<Secret, Error>;
 
     /// Deletes a secret
-    async fn delete_secret(&self, token: String, id: String) -> Result<Secret, Error>;
+    async fn delete_secret(&self, auth: rpc::Credentials, id: String) -> Result<Secret, Error>;
+
+    /// Authorizes a subscription to the secret events of a [Topic]
+    async fn subscribe(&self, auth: rpc::Credentials, topic: String) -> Result<(), Error>;
+
+    /// Creates a service token, for a workload of an organization or a project
+    async fn add_service_token(
+        &self,
+        auth: rpc::Credentials,
+        input: ServiceTokenInput,
+    ) -> Result<ServiceTokenResponse, Error>;
+
+    /// Lists the service tokens of an organization
+    async fn service_tokens(
+        &self,
+        auth: rpc::Credentials,
+        org_id: String,
+    ) -> Result<Vec<ServiceToken>, Error>;
+
+    /// Revokes a service token
+    async fn revoke_service_token(
+        &self,
+        auth: rpc::Credentials,
+        id: String,
+    ) -> Result<ServiceToken, Error>;
+
+    /// Lists the audit trail of an organization, oldest first
+    async fn audit(&self, auth: rpc::Credentials, org_id: String)
+        -> Result<Vec<AuditEvent>, Error>;
+
+    /// Lists the members of an organization
+    async fn members(&self, auth: rpc::Credentials, org_id: String) -> Result<Vec<Member>, Error>;
+
+    /// Invites a user to an organization, by email
+    async fn invite(
+        &self,
+        auth: rpc::Credentials,
+        input: InvitationInput,
+    ) -> Result<Invitation, Error>;
+
+    /// Lists the pending invitations of the user
+    async fn invitations(&self, auth: rpc::Credentials) -> Result<Vec<Invitation>, Error>;
+
+    /// Accepts an invitation of the user, which makes it a member
+    async fn accept_invitation(&self, auth: rpc::Credentials, id: String) -> Result<Member, Error>;
+
+    /// Changes the role of a member
+    async fn change_role(&self, auth: rpc::Credentials, input: RoleInput) -> Result<Member, Error>;
+
+    /// Removes a member from an organization (or leaves it)
+    async fn remove_member(
+        &self,
+        auth: rpc::Credentials,
+        input: MemberInput,
+    ) -> Result<Member, Error>;
+
+    /// Add a team to an organization
+    async fn add_team(&self, auth: rpc::Credentials, input: TeamInput) -> Result<Team, Error>;
+
+    /// Lists the teams of an organization
+    async fn teams(&self, auth: rpc::Credentials, org_id: String) -> Result<Vec<Team>, Error>;
+
+    /// Adds a user to a team
+    async fn add_team_member(
+        &self,
+        auth: rpc::Credentials,
+        input: TeamMemberInput,
+    ) -> Result<Team, Error>;
+
+    /// Removes a user from a team
+    async fn remove_team_member(
+        &self,
+        auth: rpc::Credentials,
+        input: TeamMemberInput,
+    ) -> Result<Team, Error>;
+
+    /// Grants a permission on a project to a user, a team or a service token
+    async fn add_grant(&self, auth: rpc::Credentials, input: GrantInput) -> Result<Grant, Error>;
+
+    /// Lists the grants of a project
+    async fn grants(&self, auth: rpc::Credentials, project_id: String)
+        -> Result<Vec<Grant>, Error>;
+
+    /// Revokes a grant
+    async fn revoke_grant(&self, auth: rpc::Credentials, id: String) -> Result<Grant, Error>;
+
+    /// Explains the permissions of a user or a service token on the secrets of a project
+    async fn effective_permissions(
+        &self,
+        auth: rpc::Credentials,
+        input: PermissionsInput,
+    ) -> Result<EffectivePermissions, Error>;
 }
 
 // ---------------------------------------------------------------
@@ -71,13 +198,145 @@ pub trait SecretsService {
 /// Service error
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Error {
+    /// Kind
+    #[serde(default)]
+    pub kind: ErrorKind,
     /// Message
     pub message: String,
+    /// Details (eg. the invalid fields)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl Error {
+    /// Instantiates a new [Error]
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Instantiates a new [ErrorKind::InvalidInput] error
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    /// Instantiates a new [ErrorKind::Unauthorized] error
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unauthorized, message)
+    }
+
+    /// Instantiates a new [ErrorKind::Forbidden] error
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Forbidden, message)
+    }
+
+    /// Instantiates a new [ErrorKind::NotFound] error
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    /// Instantiates a new [ErrorKind::Conflict] error
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Conflict, message)
+    }
+
+    /// Instantiates a new [ErrorKind::IncompatibleVersion] error
+    pub fn incompatible_version(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::IncompatibleVersion, message)
+    }
+
+    /// Instantiates a new [ErrorKind::Internal] error
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// Sets the details
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Returns `true` if the request may succeed if retried as is
+    pub fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Internal
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.kind)
+    }
 }
 
+impl std::error::Error for Error {}
+
+/// Errors raised outside of the service (eg. by the transport) are internal errors
 impl From<String> for Error {
     fn from(message: String) -> Self {
-        Self { message }
+        Self::internal(message)
+    }
+}
+
+/// Kind of a service [Error]
+///
+/// The kind is mapped to the status of the response (eg. `404 Not Found`), see
+/// [rpc::ERROR_STATUS]. An error without a kind is internal, like an error built from a message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The request is invalid
+    InvalidInput,
+    /// The request is larger than the server accepts
+    PayloadTooLarge,
+    /// The credentials are missing or invalid
+    Unauthorized,
+    /// The credentials do not grant access
+    Forbidden,
+    /// The resource does not exist
+    NotFound,
+    /// The resource conflicts with an existing one
+    Conflict,
+    /// The API version of the client is not supported by the server
+    IncompatibleVersion,
+    /// The service failed
+    #[default]
+    Internal,
+}
+
+impl ErrorKind {
+    /// All kinds
+    pub const ALL: [ErrorKind; 8] = [
+        ErrorKind::InvalidInput,
+        ErrorKind::PayloadTooLarge,
+        ErrorKind::Unauthorized,
+        ErrorKind::Forbidden,
+        ErrorKind::NotFound,
+        ErrorKind::Conflict,
+        ErrorKind::IncompatibleVersion,
+        ErrorKind::Internal,
+    ];
+
+    /// Returns the name of the kind (eg. `not_found`)
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::PayloadTooLarge => "payload_too_large",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::IncompatibleVersion => "incompatible_version",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
@@ -87,7 +346,54 @@ impl From<String> for Error {
 
 /// Service status
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ServiceStatus {}
+pub struct ServiceStatus {
+    /// Server version
+    pub version: String,
+    /// API version (see [API_VERSION])
+    pub api_version: u32,
+    /// Oldest API version supported (see [MIN_API_VERSION])
+    pub min_api_version: u32,
+    /// Time since the server started, in seconds
+    pub uptime: u64,
+    /// Database status
+    pub database: DatabaseStatus,
+    /// State of the service
+    pub state: ServiceState,
+}
+
+impl ServiceStatus {
+    /// Returns true if the server supports an API version
+    pub fn supports(&self, api_version: u32) -> bool {
+        (self.min_api_version..=self.api_version).contains(&api_version)
+    }
+}
+
+/// Database status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseStatus {
+    /// Set if the database answers
+    pub reachable: bool,
+    /// Version of the database schema, [None] if unreachable
+    pub schema_version: Option<i64>,
+}
+
+/// State of the service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    /// The service serves the requests
+    Ready,
+    /// The service cannot serve the secrets: its database is unreachable,
+    /// or its schema is not the one of the server
+    Sealed,
+}
+
+impl ServiceState {
+    /// Returns true if the service is ready
+    pub fn is_ready(&self) -> bool {
+        *self == ServiceState::Ready
+    }
+}
 
 // ---------------------------------------------------------------
 // AUTH
@@ -102,6 +408,9 @@ pub struct SignupInput {
     pub name: String,
     /// Password
     pub password: String,
+    /// Label of the client (eg. `cli@laptop`), listed with the sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 /// Login input
@@ -111,6 +420,9 @@ pub struct LoginInput {
     pub email: String,
     /// Password
     pub password: String,
+    /// Label of the client (eg. `cli@laptop`), listed with the sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 /// Login response
@@ -120,6 +432,27 @@ pub struct LoginResponse {
     pub token: String,
     /// User
     pub user: User,
+    /// Session of the token
+    pub session: Session,
+}
+
+/// Session, opened by a login
+///
+/// The times are UNIX timestamps, in seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// ID
+    pub id: String,
+    /// Label of the client
+    pub label: Option<String>,
+    /// Creation time
+    pub created_at: i64,
+    /// Expiration time
+    pub expires_at: i64,
+    /// Last time the token was used, [None] if never
+    pub last_used_at: Option<i64>,
+    /// Set if this is the session of the request
+    pub current: bool,
 }
 
 /// User
@@ -131,8 +464,6 @@ pub struct User {
     pub name: String,
     /// Email
     pub email: String,
-    /// Password
-    pub password: String,
 }
 
 impl PartialEq for User {
@@ -167,6 +498,289 @@ impl PartialEq for Organization {
     }
 }
 
+// ---------------------------------------------------------------
+// MEMBERS
+// ---------------------------------------------------------------
+
+/// Role of a member in an organization
+///
+/// The roles are ordered: each role has the permissions of the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Only has the access of its grants (eg. a contractor)
+    Guest,
+    /// Reads the projects and secrets
+    Viewer,
+    /// Also writes the secrets
+    Member,
+    /// Also manages the projects, members, service tokens and reads the audit trail
+    Admin,
+    /// Also deletes the organization and manages the owners
+    Owner,
+}
+
+impl Role {
+    /// Returns the name of the role
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Viewer => "viewer",
+            Role::Member => "member",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "guest" => Ok(Role::Guest),
+            "viewer" => Ok(Role::Viewer),
+            "member" => Ok(Role::Member),
+            "admin" => Ok(Role::Admin),
+            "owner" => Ok(Role::Owner),
+            _ => Err(Error::invalid_input(format!("Invalid role: {s}"))),
+        }
+    }
+}
+
+/// Member of an organization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    /// Organization ID
+    pub org_id: String,
+    /// User
+    pub user: User,
+    /// Role
+    pub role: Role,
+}
+
+/// Invitation input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationInput {
+    /// Organization ID
+    pub org_id: String,
+    /// Email of the invited user
+    pub email: String,
+    /// Role of the invited user
+    pub role: Role,
+}
+
+/// Pending invitation to an organization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invitation {
+    /// ID
+    pub id: String,
+    /// Organization
+    pub organization: Organization,
+    /// Email of the invited user
+    pub email: String,
+    /// Role of the invited user
+    pub role: Role,
+    /// ID of the inviting user
+    pub invited_by: String,
+    /// Creation time (UNIX timestamp, in seconds)
+    pub created_at: i64,
+}
+
+/// Member of an organization, to remove
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberInput {
+    /// Organization ID
+    pub org_id: String,
+    /// User ID
+    pub user_id: String,
+}
+
+/// New role of a member
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleInput {
+    /// Organization ID
+    pub org_id: String,
+    /// User ID
+    pub user_id: String,
+    /// Role
+    pub role: Role,
+}
+
+// ---------------------------------------------------------------
+// TEAMS
+// ---------------------------------------------------------------
+
+/// Team input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamInput {
+    /// Organization ID
+    pub org_id: String,
+    /// Name (unique in the organization)
+    pub name: String,
+}
+
+/// Team of users, which may be granted permissions
+///
+/// The users of a team are not necessarily members of its organization (eg.
+/// contractors).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Team {
+    /// ID
+    pub id: String,
+    /// Organization ID
+    pub org_id: String,
+    /// Name
+    pub name: String,
+    /// IDs of the users
+    pub user_ids: Vec<String>,
+}
+
+/// User of a team, to add or remove
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamMemberInput {
+    /// Team ID
+    pub team_id: String,
+    /// User ID
+    pub user_id: String,
+}
+
+// ---------------------------------------------------------------
+// GRANTS
+// ---------------------------------------------------------------
+
+/// Permission of a grant
+///
+/// The permissions are ordered: each one includes the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Reads the secrets
+    Read,
+    /// Also creates, updates and deletes the secrets
+    Write,
+    /// Also manages the project and its grants
+    Admin,
+}
+
+impl Permission {
+    /// Returns the name of the permission
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Subject of a grant (eg. `{"user": "1"}`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subject {
+    /// User ID
+    User(String),
+    /// Team ID
+    Team(String),
+    /// Service token ID
+    ServiceToken(String),
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::User(id) => write!(f, "user {id}"),
+            Subject::Team(id) => write!(f, "team {id}"),
+            Subject::ServiceToken(id) => write!(f, "service token {id}"),
+        }
+    }
+}
+
+/// Grant input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrantInput {
+    /// Subject
+    pub subject: Subject,
+    /// Project ID
+    pub project_id: String,
+    /// Environment, to narrow the grant to the secrets of an environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Key prefix, to narrow the grant to the secrets whose key starts with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_prefix: Option<String>,
+    /// Permission
+    pub permission: Permission,
+}
+
+/// Permission of a subject on the secrets of a project
+///
+/// A grant adds to the role of a member, or to the scope of a service token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    /// ID
+    pub id: String,
+    /// Subject
+    pub subject: Subject,
+    /// Project ID
+    pub project_id: String,
+    /// Environment
+    pub environment: Option<String>,
+    /// Key prefix
+    pub key_prefix: Option<String>,
+    /// Permission
+    pub permission: Permission,
+    /// Creation time (UNIX timestamp, in seconds)
+    pub created_at: i64,
+}
+
+/// Access to explain with [SecretsService::effective_permissions]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionsInput {
+    /// User or service token, defaults to the caller
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Subject>,
+    /// Project ID
+    pub project_id: String,
+    /// Environment of the secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Key of the secret
+    pub key: String,
+}
+
+/// Decision on an access, with its reason
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decision {
+    /// True if the access is allowed
+    pub allowed: bool,
+    /// Reason (eg. the role or the grant which allows the access)
+    pub reason: String,
+}
+
+/// Effective permissions of a subject on a secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectivePermissions {
+    /// Reads the secret
+    pub read: Decision,
+    /// Creates, updates or deletes the secret
+    pub write: Decision,
+    /// Manages the project and its grants
+    pub admin: Decision,
+}
+
 // ---------------------------------------------------------------
 // PROJECTS
 // ---------------------------------------------------------------
@@ -208,6 +822,9 @@ pub struct SecretInput {
     pub org_id: String,
     /// Project ID
     pub project_id: Option<String>,
+    /// Environment (eg. `production`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
     /// Key
     pub key: String,
     /// Value
@@ -223,6 +840,9 @@ pub struct Secret {
     pub oeganization: Organization,
     /// Project
     pub project: Option<Project>,
+    /// Environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
     /// Key
     pub key: String,
     /// Value
@@ -234,3 +854,185 @@ impl PartialEq for Secret {
         self.id == other.id
     }
 }
+
+// ---------------------------------------------------------------
+// SERVICE TOKENS
+// ---------------------------------------------------------------
+
+/// Scope of a service token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Reads the secrets
+    Read,
+    /// Reads and writes the secrets
+    ReadWrite,
+}
+
+impl TokenScope {
+    /// Returns true if the scope allows writes
+    pub fn can_write(&self) -> bool {
+        *self == TokenScope::ReadWrite
+    }
+}
+
+/// Service token input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceTokenInput {
+    /// Name (eg. `deploy-api`)
+    pub name: String,
+    /// Organization ID
+    pub org_id: String,
+    /// Project ID, to restrict the token to a project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    /// Environment, to restrict the token to the secrets of an environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Scope
+    pub scope: TokenScope,
+    /// Lifetime, in seconds (defaults to 90 days)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+}
+
+/// Service token
+///
+/// The times are UNIX timestamps, in seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceToken {
+    /// ID
+    pub id: String,
+    /// Name
+    pub name: String,
+    /// Organization ID
+    pub org_id: String,
+    /// Project ID
+    pub project_id: Option<String>,
+    /// Environment
+    pub environment: Option<String>,
+    /// Scope
+    pub scope: TokenScope,
+    /// Creation time
+    pub created_at: i64,
+    /// Expiration time
+    pub expires_at: i64,
+    /// Last time the token was used, [None] if never
+    pub last_used_at: Option<i64>,
+    /// Revocation time, [None] if not revoked
+    pub revoked_at: Option<i64>,
+}
+
+/// Response to the creation of a service token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceTokenResponse {
+    /// Token, only returned at creation
+    pub token: String,
+    /// Service token
+    pub service_token: ServiceToken,
+}
+
+/// Audit event, recorded for each action on an organization
+///
+/// The actor is a user, or a service token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Time of the action (UNIX timestamp, in seconds)
+    pub at: i64,
+    /// ID of the user, if the actor is a user
+    pub user_id: Option<String>,
+    /// ID of the service token, if the actor is a service token
+    pub service_token_id: Option<String>,
+    /// Action (eg. `secret.read`)
+    pub action: String,
+    /// ID of the organization
+    pub org_id: Option<String>,
+    /// ID of the resource (eg. the secret ID)
+    pub resource_id: Option<String>,
+}
+
+// ---------------------------------------------------------------
+// EVENTS
+// ---------------------------------------------------------------
+
+/// Topic of secret events
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    /// Secrets of an organization (`org:<id>`), including the project secrets
+    Organization(String),
+    /// Secrets of a project (`project:<id>`)
+    Project(String),
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Topic::Organization(id) => write!(f, "org:{id}"),
+            Topic::Project(id) => write!(f, "project:{id}"),
+        }
+    }
+}
+
+impl FromStr for Topic {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("org", id)) if !id.is_empty() => Ok(Topic::Organization(id.to_string())),
+            Some(("project", id)) if !id.is_empty() => Ok(Topic::Project(id.to_string())),
+            _ => Err(Error::invalid_input(format!("Invalid topic: {s}"))),
+        }
+    }
+}
+
+/// Secret event
+///
+/// The event does not carry the secret value, which must be read with
+/// [SecretsService::secret].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretEvent {
+    /// Kind of change
+    pub kind: SecretEventKind,
+    /// Secret ID
+    pub id: String,
+    /// Organization ID
+    pub org_id: String,
+    /// Project ID
+    pub project_id: Option<String>,
+    /// Key
+    pub key: String,
+}
+
+impl SecretEvent {
+    /// Instantiates a new [SecretEvent]
+    pub fn new(kind: SecretEventKind, secret: &Secret) -> Self {
+        Self {
+            kind,
+            id: secret.id.clone(),
+            org_id: secret.oeganization.id.clone(),
+            project_id: secret.project.as_ref().map(|p| p.id.clone()),
+            key: secret.key.clone(),
+        }
+    }
+
+    /// Returns the topics the event is published to
+    pub fn topics(&self) -> Vec<Topic> {
+        let mut topics = vec![Topic::Organization(self.org_id.clone())];
+        if let Some(project_id) = &self.project_id {
+            topics.push(Topic::Project(project_id.clone()));
+        }
+        topics
+    }
+}
+
+/// Kind of secret event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretEventKind {
+    /// Secret added
+    Added,
+    /// Secret updated
+    Updated,
+    /// Secret deleted
+    Deleted,
+}